@@ -0,0 +1,87 @@
+use std::ops::Range;
+
+use crate::{ray::Ray, vec3::Point3};
+
+#[derive(Clone)]
+pub struct Aabb {
+    min: Point3,
+    max: Point3,
+}
+
+impl Aabb {
+    pub fn new(a: &Point3, b: &Point3) -> Aabb {
+        Aabb {
+            min: Point3::new_with(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z())),
+            max: Point3::new_with(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z())),
+        }
+    }
+
+    pub fn min(&self) -> &Point3 {
+        &self.min
+    }
+
+    pub fn max(&self) -> &Point3 {
+        &self.max
+    }
+
+    pub fn surrounding(a: &Aabb, b: &Aabb) -> Aabb {
+        Aabb {
+            min: Point3::new_with(
+                a.min.x().min(b.min.x()),
+                a.min.y().min(b.min.y()),
+                a.min.z().min(b.min.z()),
+            ),
+            max: Point3::new_with(
+                a.max.x().max(b.max.x()),
+                a.max.y().max(b.max.y()),
+                a.max.z().max(b.max.z()),
+            ),
+        }
+    }
+
+    // Widens every axis to at least `delta`, so that flat boxes still pass the slab test.
+    pub fn padded(&self, delta: f64) -> Aabb {
+        let pad = |min: f64, max: f64| {
+            if max - min < delta {
+                (min - delta / 2.0, max + delta / 2.0)
+            } else {
+                (min, max)
+            }
+        };
+        let (x0, x1) = pad(self.min.x(), self.max.x());
+        let (y0, y1) = pad(self.min.y(), self.max.y());
+        let (z0, z1) = pad(self.min.z(), self.max.z());
+        Aabb::new(&Point3::new_with(x0, y0, z0), &Point3::new_with(x1, y1, z1))
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        0.5 * &(&self.min + &self.max)
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        let extent = &self.max - &self.min;
+        if extent.x() > extent.y() && extent.x() > extent.z() {
+            0
+        } else if extent.y() > extent.z() {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn hit(&self, ray: &Ray, ray_t: Range<f64>) -> Option<Range<f64>> {
+        let mut t_min = ray_t.start;
+        let mut t_max = ray_t.end;
+        for axis in 0..3 {
+            let inverse_direction = 1.0 / ray.direction()[axis];
+            let t0 = (self.min[axis] - ray.origin()[axis]) * inverse_direction;
+            let t1 = (self.max[axis] - ray.origin()[axis]) * inverse_direction;
+            t_min = t_min.max(t0.min(t1));
+            t_max = t_max.min(t0.max(t1));
+            if t_max <= t_min {
+                return None;
+            }
+        }
+        Some(t_min..t_max)
+    }
+}
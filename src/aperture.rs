@@ -0,0 +1,159 @@
+use std::{f64::consts::PI, io, path::Path};
+
+use rand::Rng;
+
+use crate::{
+    light_sampler::AliasTable,
+    texture::{ImageTexture, Texture},
+    utils,
+    vec3::{Point3, Vec3},
+};
+
+// The shape of the lens opening, and so of out-of-focus highlights.
+pub enum Aperture {
+    Circle,
+    // A regular polygon with one side per diaphragm blade, turned by `rotation` degrees.
+    Polygon { blades: u32, rotation: f64 },
+    // The annulus of a mirror lens, whose secondary mirror blocks the centre out to
+    // `obstruction` of the radius.
+    Ring { obstruction: f64 },
+    Mask(ApertureMask),
+}
+
+impl Aperture {
+    // A point on the aperture in the lens plane, scaled so the lens is the unit disk.
+    pub fn sample(&self) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        match self {
+            Aperture::Circle => Vec3::random_in_unit_disk(),
+            Aperture::Polygon { blades, rotation } => {
+                let blades = (*blades).max(3);
+                let step = 2.0 * PI / blades as f64;
+                let start =
+                    utils::degrees_to_radians(*rotation) + step * rng.gen_range(0..blades) as f64;
+                let (a, b) = (
+                    Vec3::new_with(start.cos(), start.sin(), 0.0),
+                    Vec3::new_with((start + step).cos(), (start + step).sin(), 0.0),
+                );
+                // Uniform in the triangle between the centre and one side.
+                let (mut u, mut v) = (rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0));
+                if u + v > 1.0 {
+                    (u, v) = (1.0 - u, 1.0 - v);
+                }
+                &(u * &a) + &(v * &b)
+            }
+            Aperture::Ring { obstruction } => {
+                let inner = obstruction.clamp(0.0, 1.0).powi(2);
+                let r = (inner + rng.gen_range(0.0..1.0) * (1.0 - inner)).sqrt();
+                let phi = rng.gen_range(0.0..2.0 * PI);
+                Vec3::new_with(r * phi.cos(), r * phi.sin(), 0.0)
+            }
+            Aperture::Mask(mask) => mask.sample(),
+        }
+    }
+}
+
+// An aperture drawn as a grayscale image over the square around the lens, with white
+// letting light through.
+pub struct ApertureMask {
+    width: usize,
+    height: usize,
+    table: AliasTable,
+}
+
+impl ApertureMask {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<ApertureMask> {
+        let image = ImageTexture::load_linear(path)?;
+        let (width, height) = (image.width(), image.height());
+        let origin = Point3::new();
+        let weights: Vec<f64> = (0..height)
+            .flat_map(|j| (0..width).map(move |i| (i, j)))
+            .map(|(i, j)| {
+                let u = (i as f64 + 0.5) / width as f64;
+                let v = 1.0 - (j as f64 + 0.5) / height as f64;
+                image.value(u, v, &origin).luminance().max(0.0)
+            })
+            .collect();
+        if weights.iter().all(|&weight| weight == 0.0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "aperture mask is entirely opaque",
+            ));
+        }
+        Ok(ApertureMask {
+            width,
+            height,
+            table: AliasTable::new(&weights),
+        })
+    }
+
+    fn sample(&self) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let (index, _) = self.table.sample();
+        let (i, j) = (index % self.width, index / self.width);
+        let u = (i as f64 + rng.gen_range(0.0..1.0)) / self.width as f64;
+        let v = 1.0 - (j as f64 + rng.gen_range(0.0..1.0)) / self.height as f64;
+        Vec3::new_with(2.0 * u - 1.0, 2.0 * v - 1.0, 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: usize = 10_000;
+
+    #[test]
+    fn polygon_samples_stay_inside_the_blades() {
+        for (blades, rotation) in [(6, 15.0), (5, 0.0), (1, 30.0)] {
+            let aperture = Aperture::Polygon { blades, rotation };
+            let sides = blades.max(3);
+            let step = 2.0 * PI / sides as f64;
+            // Each side lies at the apothem along the normal through its middle.
+            let apothem = (step / 2.0).cos();
+            for _ in 0..SAMPLES {
+                let p = aperture.sample();
+                assert_eq!(p.z(), 0.0);
+                for side in 0..sides {
+                    let angle = utils::degrees_to_radians(rotation) + (side as f64 + 0.5) * step;
+                    let normal = Vec3::new_with(angle.cos(), angle.sin(), 0.0);
+                    assert!(p.dot(&normal) <= apothem + 1e-12, "{} {}", p.x(), p.y());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ring_samples_stay_outside_the_obstruction() {
+        let aperture = Aperture::Ring { obstruction: 0.5 };
+        let mut inner = 0.0;
+        for _ in 0..SAMPLES {
+            let p = aperture.sample();
+            let r = p.len();
+            assert!((0.5..=1.0).contains(&r), "{r}");
+            if r < 0.75 {
+                inner += 1.0;
+            }
+        }
+        // Uniform over the annulus, so by area 5/12 of it lies inside radius 0.75.
+        let fraction = inner / SAMPLES as f64;
+        assert!((fraction - 5.0 / 12.0).abs() < 0.03, "{fraction}");
+    }
+
+    #[test]
+    fn mask_samples_land_in_its_open_pixels() {
+        // A 4x2 mask, open only in the second column of the bottom row.
+        let mut weights = vec![0.0; 8];
+        weights[5] = 1.0;
+        let aperture = Aperture::Mask(ApertureMask {
+            width: 4,
+            height: 2,
+            table: AliasTable::new(&weights),
+        });
+        for _ in 0..SAMPLES {
+            let p = aperture.sample();
+            assert!((-0.5..=0.0).contains(&p.x()), "{}", p.x());
+            assert!((-1.0..=0.0).contains(&p.y()), "{}", p.y());
+        }
+    }
+}
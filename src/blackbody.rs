@@ -0,0 +1,105 @@
+use crate::{
+    color::Color,
+    spectrum::{cie_xyz, xyz_to_linear_srgb, WAVELENGTH_MAX, WAVELENGTH_MIN},
+};
+
+const PLANCK: f64 = 6.626_070_15e-34;
+const BOLTZMANN: f64 = 1.380_649e-23;
+const SPEED_OF_LIGHT: f64 = 299_792_458.0;
+// Lumens per watt at 555 nm.
+pub const LUMINOUS_EFFICACY: f64 = 683.0;
+
+const WAVELENGTH_STEP: f64 = 5.0;
+
+// Spectral radiance in W / (m^2 sr nm).
+pub fn planck(wavelength_nm: f64, kelvin: f64) -> f64 {
+    if kelvin <= 0.0 {
+        return 0.0;
+    }
+    let wavelength = wavelength_nm * 1e-9;
+    let numerator = 2.0 * PLANCK * SPEED_OF_LIGHT.powi(2);
+    let exponent = PLANCK * SPEED_OF_LIGHT / (wavelength * BOLTZMANN * kelvin);
+    numerator / (wavelength.powi(5) * exponent.exp_m1()) * 1e-9
+}
+
+// Linear sRGB radiance of a black body, with luminance in cd/m^2.
+pub fn blackbody(kelvin: f64) -> Color {
+    let mut xyz = Color::new();
+    let mut wavelength = WAVELENGTH_MIN;
+    while wavelength <= WAVELENGTH_MAX {
+        xyz += planck(wavelength, kelvin) * &cie_xyz(wavelength);
+        wavelength += WAVELENGTH_STEP;
+    }
+    let rgb = xyz_to_linear_srgb(&(LUMINOUS_EFFICACY * WAVELENGTH_STEP * &xyz));
+    Color::new_with(rgb.x().max(0.0), rgb.y().max(0.0), rgb.z().max(0.0))
+}
+
+// Radiance in W / (m^2 sr) over the visible range.
+fn radiant_radiance(kelvin: f64) -> f64 {
+    let mut radiance = 0.0;
+    let mut wavelength = WAVELENGTH_MIN;
+    while wavelength <= WAVELENGTH_MAX {
+        radiance += planck(wavelength, kelvin) * WAVELENGTH_STEP;
+        wavelength += WAVELENGTH_STEP;
+    }
+    radiance
+}
+
+// How to scale a black body's colour.
+pub enum Normalization {
+    // Unit luminance, so that every temperature looks equally bright and only the
+    // tint changes.
+    Luminance,
+    // Unit radiance in W / (m^2 sr) over the visible range, so that lights of
+    // different temperatures given equal power put out equal energy. Cool lights then
+    // look brighter than warm ones, as more of their energy falls where the eye is
+    // sensitive.
+    RadiantPower,
+    // Brightest channel at one, the most saturated colour that still fits.
+    MaxComponent,
+    // Physical radiance with luminance in cd/m^2, as returned by `blackbody`.
+    Physical,
+}
+
+pub fn blackbody_normalized(kelvin: f64, normalization: Normalization) -> Color {
+    let rgb = blackbody(kelvin);
+    let reference = match normalization {
+        Normalization::Luminance => rgb.luminance(),
+        Normalization::RadiantPower => radiant_radiance(kelvin),
+        Normalization::MaxComponent => rgb.x().max(rgb.y()).max(rgb.z()),
+        Normalization::Physical => 1.0,
+    };
+    if reference > 0.0 {
+        &rgb / reference
+    } else {
+        Color::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equal_power_lights_differ_in_brightness_but_not_energy() {
+        let warm = blackbody_normalized(2700.0, Normalization::RadiantPower);
+        let cool = blackbody_normalized(6500.0, Normalization::RadiantPower);
+        // A watt of visible light is worth at most the peak efficacy, and a cool light
+        // gets closer to it.
+        assert!(warm.luminance() < cool.luminance());
+        assert!(cool.luminance() < LUMINOUS_EFFICACY);
+        assert!(warm.luminance() > 0.1 * LUMINOUS_EFFICACY);
+        // Each is its physical radiance over its visible energy, tint and all.
+        let scale = blackbody(2700.0).luminance() / warm.luminance();
+        assert!((scale - radiant_radiance(2700.0)).abs() < 1e-9 * scale);
+        assert!(cool.z() / cool.x() > warm.z() / warm.x());
+    }
+
+    #[test]
+    fn luminance_normalization_only_changes_the_tint() {
+        for kelvin in [1800.0, 2700.0, 6500.0, 10000.0] {
+            let rgb = blackbody_normalized(kelvin, Normalization::Luminance);
+            assert!((rgb.luminance() - 1.0).abs() < 1e-9, "{kelvin}");
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use std::rc::Rc;
+
+use crate::{
+    color::Color,
+    hittable::HitRecord,
+    material::{EmitterSurface, Material},
+    medium::Interior,
+    ray::Ray,
+    texture::Texture,
+    vec3::Vec3,
+};
+
+// Offset along u and v for the finite differences of a height texture.
+const HEIGHT_DELTA: f64 = 1e-3;
+
+// Perturbs the shading normal with a tangent-space normal map, stored the usual way
+// with xyz remapped from [-1, 1] to [0, 1].
+pub struct NormalMapped {
+    material: Box<dyn Material>,
+    normal_map: Rc<dyn Texture>,
+    strength: f64,
+}
+
+impl NormalMapped {
+    pub fn new(material: Box<dyn Material>, normal_map: Rc<dyn Texture>) -> NormalMapped {
+        NormalMapped {
+            material,
+            normal_map,
+            strength: 1.0,
+        }
+    }
+
+    pub fn with_strength(mut self, strength: f64) -> NormalMapped {
+        self.strength = strength;
+        self
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        let texel = self.normal_map.value(rec.u, rec.v, &rec.p);
+        let local = Vec3::new_with(
+            self.strength * (2.0 * texel.x() - 1.0),
+            self.strength * (2.0 * texel.y() - 1.0),
+            2.0 * texel.z() - 1.0,
+        );
+        let (tangent, bitangent) = rec.tangent_frame();
+        let normal =
+            &(&(local.x() * &tangent) + &(local.y() * &bitangent)) + &(local.z() * &rec.normal);
+        if normal.is_near_zero() {
+            rec.normal.clone()
+        } else {
+            normal.unit_vector()
+        }
+    }
+}
+
+impl Material for NormalMapped {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let rec = shaded(rec, ray_in, self.shading_normal(rec));
+        self.material.scatter(ray_in, &rec)
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let rec = shaded(rec, ray_in, self.shading_normal(rec));
+        self.material.eval(ray_in, &rec, direction)
+    }
+
+    fn pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Option<f64> {
+        let rec = shaded(rec, ray_in, self.shading_normal(rec));
+        self.material.pdf(ray_in, &rec, direction)
+    }
+
+    fn emit(&self, ray_in: &Ray, rec: &HitRecord) -> Color {
+        let rec = shaded(rec, ray_in, self.shading_normal(rec));
+        self.material.emit(ray_in, &rec)
+    }
+
+    fn set_emitter_surface(&mut self, surface: &EmitterSurface) {
+        self.material.set_emitter_surface(surface);
+    }
+
+    fn average_emission(&self) -> Color {
+        self.material.average_emission()
+    }
+
+    fn interior(&self) -> Option<Interior> {
+        self.material.interior()
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.material.is_dispersive()
+    }
+
+    fn alpha_test(&self, rec: &HitRecord) -> bool {
+        self.material.alpha_test(rec)
+    }
+}
+
+// Perturbs the shading normal as if the surface were displaced along it by a height
+// texture, whose slope is taken by finite differences in uv.
+pub struct BumpMapped {
+    material: Box<dyn Material>,
+    height: Rc<dyn Texture>,
+    scale: f64,
+}
+
+impl BumpMapped {
+    pub fn new(material: Box<dyn Material>, height: Rc<dyn Texture>, scale: f64) -> BumpMapped {
+        BumpMapped {
+            material,
+            height,
+            scale,
+        }
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        let height = |du: f64, dv: f64| {
+            let p = &(&rec.p + &(du * &rec.dpdu)) + &(dv * &rec.dpdv);
+            self.scale * self.height.value(rec.u + du, rec.v + dv, &p).x()
+        };
+        let base = height(0.0, 0.0);
+        let slope_u = (height(HEIGHT_DELTA, 0.0) - base) / HEIGHT_DELTA;
+        let slope_v = (height(0.0, HEIGHT_DELTA) - base) / HEIGHT_DELTA;
+
+        let dpdu = &rec.dpdu + &(slope_u * &rec.normal);
+        let dpdv = &rec.dpdv + &(slope_v * &rec.normal);
+        let normal = dpdu.cross(&dpdv);
+        if normal.is_near_zero() {
+            return rec.normal.clone();
+        }
+        let normal = normal.unit_vector();
+        if normal.dot(&rec.normal) < 0.0 {
+            -&normal
+        } else {
+            normal
+        }
+    }
+}
+
+impl Material for BumpMapped {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let rec = shaded(rec, ray_in, self.shading_normal(rec));
+        self.material.scatter(ray_in, &rec)
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let rec = shaded(rec, ray_in, self.shading_normal(rec));
+        self.material.eval(ray_in, &rec, direction)
+    }
+
+    fn pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Option<f64> {
+        let rec = shaded(rec, ray_in, self.shading_normal(rec));
+        self.material.pdf(ray_in, &rec, direction)
+    }
+
+    fn emit(&self, ray_in: &Ray, rec: &HitRecord) -> Color {
+        let rec = shaded(rec, ray_in, self.shading_normal(rec));
+        self.material.emit(ray_in, &rec)
+    }
+
+    fn set_emitter_surface(&mut self, surface: &EmitterSurface) {
+        self.material.set_emitter_surface(surface);
+    }
+
+    fn average_emission(&self) -> Color {
+        self.material.average_emission()
+    }
+
+    fn interior(&self) -> Option<Interior> {
+        self.material.interior()
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.material.is_dispersive()
+    }
+
+    fn alpha_test(&self, rec: &HitRecord) -> bool {
+        self.material.alpha_test(rec)
+    }
+}
+
+// The hit as the wrapped material should see it. A perturbed normal can end up facing
+// away from the viewer, which no material handles, so it is tilted back just enough
+// to be visible.
+fn shaded<'a>(rec: &HitRecord<'a>, ray_in: &Ray, normal: Vec3) -> HitRecord<'a> {
+    let wo = -&ray_in.direction().unit_vector();
+    let cos_theta = normal.dot(&wo);
+    let normal = if cos_theta < 0.01 {
+        (&normal + &((0.01 - cos_theta) * &wo)).unit_vector()
+    } else {
+        normal
+    };
+
+    let mut shaded = rec.clone();
+    shaded.normal = normal;
+    shaded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{material::LambertianMaterial, texture::SolidColor, vec3::Point3};
+
+    // A height that rises along u.
+    struct Ramp(f64);
+
+    impl Texture for Ramp {
+        fn value(&self, u: f64, _v: f64, _p: &Point3) -> Color {
+            Color::new_with(self.0 * u, 0.0, 0.0)
+        }
+    }
+
+    fn flat_hit(material: &dyn Material) -> HitRecord<'_> {
+        let mut rec = HitRecord::new(Point3::new(), Vec3::new_with(0.0, 0.0, 1.0), 1.0, material);
+        rec.dpdu = Vec3::new_with(1.0, 0.0, 0.0);
+        rec.dpdv = Vec3::new_with(0.0, 1.0, 0.0);
+        rec.u = 0.5;
+        rec.v = 0.5;
+        rec
+    }
+
+    fn assert_near(a: &Vec3, b: &Vec3) {
+        let difference = a - b;
+        assert!(difference.len() < 1e-6, "({}, {}, {})", a.x(), a.y(), a.z());
+    }
+
+    #[test]
+    fn normal_maps_tilt_the_normal_in_tangent_space() {
+        let lambertian = || Box::new(LambertianMaterial::new(&Color::new_with(0.5, 0.5, 0.5)));
+        let flat = NormalMapped::new(
+            lambertian(),
+            Rc::new(SolidColor::new(&Color::new_with(0.5, 0.5, 1.0))),
+        );
+        let rec = flat_hit(&flat);
+        assert_near(&flat.shading_normal(&rec), &rec.normal);
+
+        // 45 degrees towards +u.
+        let s = std::f64::consts::FRAC_1_SQRT_2;
+        let tilted = NormalMapped::new(
+            lambertian(),
+            Rc::new(SolidColor::new(&Color::new_with(
+                0.5 + 0.5 * s,
+                0.5,
+                0.5 + 0.5 * s,
+            ))),
+        );
+        let rec = flat_hit(&tilted);
+        assert_near(&tilted.shading_normal(&rec), &Vec3::new_with(s, 0.0, s));
+    }
+
+    #[test]
+    fn bumps_lean_away_from_rising_height() {
+        let material = BumpMapped::new(
+            Box::new(LambertianMaterial::new(&Color::new_with(0.5, 0.5, 0.5))),
+            Rc::new(Ramp(1.0)),
+            0.5,
+        );
+        let rec = flat_hit(&material);
+        let expected = Vec3::new_with(-0.5, 0.0, 1.0).unit_vector();
+        assert_near(&material.shading_normal(&rec), &expected);
+    }
+
+    #[test]
+    fn shading_normals_stay_visible() {
+        let ray = Ray::new(
+            Point3::new_with(0.0, 0.0, 1.0),
+            Vec3::new_with(0.8, 0.0, -0.6),
+            0.0,
+        );
+        let material = LambertianMaterial::new(&Color::new());
+        let rec = flat_hit(&material);
+        let rec = shaded(&rec, &ray, Vec3::new_with(1.0, 0.0, 0.0));
+        let cos_theta = rec.normal.dot(&(-&ray.direction().unit_vector()));
+        assert!(cos_theta > 0.0, "{cos_theta}");
+        assert!((rec.normal.len() - 1.0).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,122 @@
+use std::ops::Range;
+
+use crate::{aabb::Aabb, ray::Ray};
+
+const MAX_LEAF_SIZE: usize = 4;
+
+enum Node {
+    Leaf {
+        bounds: Aabb,
+        start: usize,
+        count: usize,
+    },
+    // The left child directly follows its parent.
+    Inner {
+        bounds: Aabb,
+        right: usize,
+    },
+}
+
+impl Node {
+    fn bounds(&self) -> &Aabb {
+        match self {
+            Node::Leaf { bounds, .. } | Node::Inner { bounds, .. } => bounds,
+        }
+    }
+}
+
+// A bounding volume hierarchy over items identified by index, split at the median
+// centroid along the longest axis.
+pub struct Bvh {
+    nodes: Vec<Node>,
+    items: Vec<usize>,
+}
+
+impl Bvh {
+    pub fn new(boxes: &[Aabb]) -> Bvh {
+        let mut bvh = Bvh {
+            nodes: Vec::new(),
+            items: (0..boxes.len()).collect(),
+        };
+        if !boxes.is_empty() {
+            bvh.build(boxes, 0, boxes.len());
+        }
+        bvh
+    }
+
+    fn build(&mut self, boxes: &[Aabb], start: usize, end: usize) -> usize {
+        let items = &mut self.items[start..end];
+        let bounds = items
+            .iter()
+            .skip(1)
+            .fold(boxes[items[0]].clone(), |bounds, &item| {
+                Aabb::surrounding(&bounds, &boxes[item])
+            });
+
+        let index = self.nodes.len();
+        if items.len() <= MAX_LEAF_SIZE {
+            self.nodes.push(Node::Leaf {
+                bounds,
+                start,
+                count: end - start,
+            });
+            return index;
+        }
+
+        let centroids = items.iter().skip(1).fold(
+            Aabb::new(&boxes[items[0]].centroid(), &boxes[items[0]].centroid()),
+            |centroids, &item| {
+                let centroid = boxes[item].centroid();
+                Aabb::surrounding(&centroids, &Aabb::new(&centroid, &centroid))
+            },
+        );
+        let axis = centroids.longest_axis();
+        items.sort_by(|&a, &b| boxes[a].centroid()[axis].total_cmp(&boxes[b].centroid()[axis]));
+
+        self.nodes.push(Node::Inner { bounds, right: 0 });
+        let middle = start + (end - start) / 2;
+        self.build(boxes, start, middle);
+        let right_child = self.build(boxes, middle, end);
+        if let Node::Inner { right, .. } = &mut self.nodes[index] {
+            *right = right_child;
+        }
+        index
+    }
+
+    // Offers `hit` every item in the leaves the ray reaches. `hit` returns the distance
+    // of an accepted hit, which then limits the rest of the search.
+    pub fn traverse<F>(&self, ray: &Ray, ray_t: Range<f64>, mut hit: F)
+    where
+        F: FnMut(usize, Range<f64>) -> Option<f64>,
+    {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let mut closest_so_far = ray_t.end;
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node
+                .bounds()
+                .hit(ray, ray_t.start..closest_so_far)
+                .is_none()
+            {
+                continue;
+            }
+            match node {
+                Node::Leaf { start, count, .. } => {
+                    for &item in self.items[*start..start + count].iter() {
+                        if let Some(t) = hit(item, ray_t.start..closest_so_far) {
+                            closest_so_far = t;
+                        }
+                    }
+                }
+                Node::Inner { right, .. } => {
+                    stack.push(*right);
+                    stack.push(index + 1);
+                }
+            }
+        }
+    }
+}
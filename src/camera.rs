@@ -1,7 +1,18 @@
+use std::io;
+
 use crate::{
+    aperture::Aperture,
     color::Color,
-    hittable::Hittable,
-    ray::Ray,
+    fog::Fog,
+    hittable::{HitRecord, Hittable},
+    image::Image,
+    light::{Light, LightList, LightSample, PunctualLight},
+    medium::MediumStack,
+    mesh::Tessellation,
+    projection::{Eye, Perspective, Projection, View},
+    ray::{Ray, RayKind},
+    spectrum::{SampledWavelengths, SpectralFilm},
+    stereo::Stereo,
     utils,
     vec3::{Point3, Vec3},
 };
@@ -22,17 +33,20 @@ pub struct Camera {
     pub max_bounces: u16,
     pub start_time: f64,
     pub end_time: f64,
+    pub fog: Option<Fog>,
+    pub spectral: bool,
+    pub projection: Box<dyn Projection>,
+    pub aperture: Aperture,
+    // How far the lens barrel shifts across the aperture at the frame corners, in
+    // aperture radii; zero leaves highlights the same shape across the frame.
+    pub cat_eye: f64,
     u: Vec3,
     v: Vec3,
     w: Vec3,
     image_height: u16,
     center: Point3,
     pixel_samples_scale: f64,
-    pixel00_loc: Point3,
-    pixel_delta_u: Vec3,
-    pixel_delta_v: Vec3,
-    defocus_disk_u: Vec3,
-    defocus_disk_v: Vec3,
+    spectral_film: Option<SpectralFilm>,
 }
 
 impl Camera {
@@ -68,16 +82,17 @@ impl Camera {
             image_height: 0,
             start_time: time0,
             end_time: time1,
+            fog: None,
+            spectral: false,
+            projection: Box::new(Perspective),
+            aperture: Aperture::Circle,
+            cat_eye: 0.0,
             u: Vec3::new(),
             w: Vec3::new(),
             v: Vec3::new(),
             center: Point3::new(),
             pixel_samples_scale: 0.0,
-            pixel00_loc: Point3::new(),
-            pixel_delta_u: Vec3::new(),
-            pixel_delta_v: Vec3::new(),
-            defocus_disk_u: Vec3::new(),
-            defocus_disk_v: Vec3::new(),
+            spectral_film: None,
         }
     }
 
@@ -86,42 +101,48 @@ impl Camera {
         self.image_height = std::cmp::max(self.image_height, 1);
 
         self.center = self.lookfrom.clone();
-        let theta = utils::degrees_to_radians(self.vfov);
-        let h = (theta / 2.0).tan();
-
-        let viewport_height = 2.0 * h * self.focus_dist;
-        let viewport_width = viewport_height * (self.image_width as f64 / self.image_height as f64);
-
         self.w = (&self.lookfrom - &self.lookat).unit_vector();
         self.u = self.vup.cross(&self.w);
         self.v = self.w.cross(&self.u);
 
-        let viewport_u = viewport_width * &self.u;
-        let viewport_v = viewport_height * &(-&self.v);
-
         self.pixel_samples_scale = 1.0 / self.samples_per_pixel as f64;
-        self.pixel_delta_u = &viewport_u / self.image_width as f64;
-        self.pixel_delta_v = &viewport_v / self.image_height as f64;
+        self.spectral_film = self.spectral.then(SpectralFilm::new);
+    }
 
-        let viewport_upper_left = &(&(&self.center - &(self.focus_dist * &self.w))
-            - &(&viewport_u / 2.0))
-            - &(&viewport_v / 2.0);
+    // Tessellation that splits displaced meshes down to roughly `pixels` per edge.
+    pub fn tessellation(&self, pixels: f64) -> Tessellation {
+        let image_height = (self.image_width as f64 / self.aspect_ratio).max(1.0);
+        self.projection
+            .tessellation(&self.view(), &self.lookfrom, pixels, image_height)
+    }
 
-        self.pixel00_loc =
-            &viewport_upper_left + &(0.5 * &(&self.pixel_delta_u + &self.pixel_delta_v));
+    fn view(&self) -> View<'_> {
+        let image_height = (self.image_width as f64 / self.aspect_ratio)
+            .max(1.0)
+            .floor();
+        View {
+            vfov: self.vfov,
+            aspect_ratio: self.image_width as f64 / image_height,
+            focus_dist: self.focus_dist,
+            defocus_angle: self.defocus_angle,
+            aperture: &self.aperture,
+            cat_eye: self.cat_eye,
+        }
+    }
 
-        let defocus_radius =
-            self.focus_dist * utils::degrees_to_radians(self.defocus_angle / 2.0).tan();
-        self.defocus_disk_u = &self.u * defocus_radius;
-        self.defocus_disk_v = &self.v * defocus_radius;
+    pub fn render(&mut self, world: &dyn Hittable, lights: &LightList) {
+        let image = self.render_image(world, lights);
+        image
+            .write_ppm(&mut io::stdout().lock())
+            .expect("failed to write image");
     }
 
-    pub fn render(&mut self, world: &mut dyn Hittable) {
+    pub fn render_image(&mut self, world: &dyn Hittable, lights: &LightList) -> Image {
         self.initialize();
-        println!("P3");
-        println!("{} {}", self.image_width, self.image_height);
-        println!("255");
+        let mut image = Image::new(self.image_width as usize, self.image_height as usize);
 
+        let view = self.view();
+        let scene = Scene { world, lights };
         for j in 0..self.image_height {
             log(&format!(
                 "Iteration {} (out of {}) [{:.2}%]\r",
@@ -132,26 +153,63 @@ impl Camera {
             for i in 0..self.image_width {
                 let mut pixel_color = Color::new();
                 for _ in 0..self.samples_per_pixel {
-                    let ray = self.get_ray(i, j);
-                    pixel_color += self.ray_color(&ray, self.max_bounces, world);
+                    let Some((ray, weight)) = self.get_ray(&view, i, j) else {
+                        continue;
+                    };
+                    let wavelengths = self.spectral.then(SampledWavelengths::sample);
+                    let radiance = self.ray_color(
+                        &ray,
+                        self.max_bounces,
+                        &scene,
+                        &MediumStack::new(),
+                        wavelengths.as_ref(),
+                        Emission::Camera,
+                    );
+                    let rgb = match (&self.spectral_film, &wavelengths) {
+                        (Some(film), Some(wavelengths)) => film.to_rgb(&radiance, wavelengths),
+                        _ => radiance,
+                    };
+                    pixel_color += weight * &rgb;
                 }
-                let pixel_color = self.pixel_samples_scale * &pixel_color;
-                pixel_color.print();
+                image.set(
+                    i as usize,
+                    j as usize,
+                    self.pixel_samples_scale * &pixel_color,
+                );
             }
         }
+        image
     }
 
-    fn get_ray(&self, i: u16, j: u16) -> Ray {
-        let offset = Camera::sample_square();
-        let pixel_sample = &(&self.pixel00_loc + &((i as f64 + offset.x()) * &self.pixel_delta_u))
-            + &((j as f64 + offset.y()) * &self.pixel_delta_v);
+    // Renders a view from each eye, swapping in their projections for the duration.
+    // Only a perspective camera has an off-axis counterpart to swap for.
+    pub fn render_stereo(
+        &mut self,
+        world: &dyn Hittable,
+        lights: &LightList,
+        stereo: &Stereo,
+    ) -> io::Result<()> {
+        if !self.projection.is_perspective() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "stereo pairs need a perspective camera",
+            ));
+        }
+        let images = [Eye::Left, Eye::Right].map(|eye| {
+            let projection = std::mem::replace(&mut self.projection, Box::new(stereo.eye(eye)));
+            let image = self.render_image(world, lights);
+            self.projection = projection;
+            image
+        });
+        let [left, right] = images;
+        stereo.write(&left, &right)
+    }
 
-        let ray_origin = if self.defocus_angle <= 0.0 {
-            self.center.clone()
-        } else {
-            self.defocus_disk_samples()
-        };
-        let ray_direction = &pixel_sample - &ray_origin;
+    fn get_ray(&self, view: &View, i: u16, j: u16) -> Option<(Ray, f64)> {
+        let offset = Camera::sample_square();
+        let x = (i as f64 + 0.5 + offset.x()) / self.image_width as f64;
+        let y = (j as f64 + 0.5 + offset.y()) / self.image_height as f64;
+        let camera_ray = self.projection.generate(view, x, y)?;
 
         let mut rng = rand::thread_rng();
         let time = if self.end_time > self.start_time {
@@ -159,7 +217,16 @@ impl Camera {
         } else {
             self.start_time
         };
-        Ray::new(ray_origin, ray_direction, time)
+        let ray = Ray::new(
+            &self.center + &self.to_world(&camera_ray.origin),
+            self.to_world(&camera_ray.direction),
+            time,
+        );
+        Some((ray, camera_ray.weight))
+    }
+
+    fn to_world(&self, v: &Vec3) -> Vec3 {
+        &(&(v.x() * &self.u) + &(v.y() * &self.v)) + &(v.z() * &self.w)
     }
 
     fn sample_square() -> Vec3 {
@@ -169,33 +236,555 @@ impl Camera {
         Vec3::new_with(num1, num2, 0.0)
     }
 
-    fn defocus_disk_samples(&self) -> Point3 {
-        let p = Vec3::random_in_unit_disk();
-        &(&self.center + &(p.x() * &self.defocus_disk_u)) + &(p.y() * &self.defocus_disk_v)
-    }
-
-    fn ray_color(&self, ray: &Ray, depth: u16, world: &mut dyn Hittable) -> Color {
-        if depth <= 0 {
+    // `emission` says how much of what the ray finds emitted where it lands counts.
+    fn ray_color<'a>(
+        &self,
+        ray: &Ray,
+        depth: u16,
+        scene: &Scene<'a>,
+        media: &MediumStack<'a>,
+        wavelengths: Option<&SampledWavelengths>,
+        emission: Emission<'a>,
+    ) -> Color {
+        if depth == 0 {
             return Color::new();
         }
 
-        match world.hits(ray, 0.001..utils::INFINITY) {
-            Some(hit_record) => {
-                let mut emitted = hit_record.material.emit(&ray, &hit_record);
+        let hit = scene.world.hits(ray, 0.001..utils::INFINITY);
+        let arriving = hit.as_ref().and_then(|rec| {
+            let interior = rec.material.interior()?;
+            media.arriving(rec.material, &interior, rec.front_face)
+        });
+        let media = arriving.as_ref().unwrap_or(media);
+
+        let mut transmittance = Color::new_with(1.0, 1.0, 1.0);
+        let current_medium = media.current();
+        if let (Some(fog), None) = (&self.fog, current_medium) {
+            let t_max = hit.as_ref().map_or(utils::INFINITY, |rec| rec.t);
+            let (collision, fog_weight) = fog.sample_distance(ray, t_max, wavelengths);
+            if let Some(t) = collision {
+                let p = ray.at(t);
+                let phase = 1.0 / (4.0 * utils::PI);
+                let direct = self.direct_lighting(
+                    &p,
+                    ray,
+                    scene.world,
+                    &scene.lights.select_punctual(&p, None),
+                    |shadow, distance| {
+                        self.medium_transmittance(shadow, distance, media, wavelengths)
+                    },
+                    |light, sample| {
+                        if !light.illuminates(None) {
+                            return Color::new();
+                        }
+                        phase * &upsample_illuminant(sample.radiance.clone(), wavelengths)
+                    },
+                );
+                let (scattered, weight) = Camera::fog_in_scatter(&p, ray, scene.lights);
+                let in_scattered = self.ray_color(
+                    &scattered,
+                    depth - 1,
+                    scene,
+                    media,
+                    wavelengths,
+                    Emission::From(None),
+                );
+                return &fog_weight * &(&direct + &(weight * &in_scattered));
+            }
+            transmittance = fog_weight;
+        }
+
+        if let Some(interior) = current_medium {
+            let speed = ray.direction().len();
+            let distance = hit.as_ref().map_or(utils::INFINITY, |rec| rec.t * speed);
+            if interior.is_scattering() {
+                // Random walk through a subsurface medium, one scattering event per bounce.
+                let (scatter_distance, weight) = interior.sample_free_flight(distance, wavelengths);
+                if let Some(scatter_distance) = scatter_distance {
+                    let scattered = Ray::new(
+                        ray.at(scatter_distance / speed),
+                        Vec3::random_unit_vector(),
+                        ray.time(),
+                    )
+                    .with_kind(RayKind::Reflection);
+                    let in_scattered = self.ray_color(
+                        &scattered,
+                        depth - 1,
+                        scene,
+                        media,
+                        wavelengths,
+                        Emission::From(None),
+                    );
+                    return &weight * &in_scattered;
+                }
+                transmittance = weight;
+            } else if hit.is_some() {
+                transmittance = interior.transmittance(distance, wavelengths);
+            }
+        }
+
+        match hit {
+            Some(mut hit_record) => {
+                hit_record.wavelength = wavelengths.map(|wavelengths| wavelengths.hero());
+
+                let mut beyond = media.clone();
+                if let Some(interior) = hit_record.material.interior() {
+                    beyond = media.crossed(hit_record.material, interior, hit_record.front_face);
+                    if media.same_medium_as(&beyond) {
+                        let continued =
+                            Ray::new(hit_record.p.clone(), ray.direction().clone(), ray.time())
+                                .with_kind(ray.kind());
+                        let passed = self.ray_color(
+                            &continued,
+                            depth,
+                            scene,
+                            &beyond,
+                            wavelengths,
+                            emission,
+                        );
+                        return &transmittance * &passed;
+                    }
+                    hit_record.outer_refraction_index = if hit_record.front_face {
+                        media.refraction_index(hit_record.wavelength)
+                    } else {
+                        beyond.refraction_index(hit_record.wavelength)
+                    };
+                }
+
+                let dispersive = hit_record.material.is_dispersive();
+                let mut emitted = emission.weight(scene.lights, hit_record.object)
+                    * &upsample_illuminant(hit_record.material.emit(ray, &hit_record), wavelengths);
+                let selected = scene
+                    .lights
+                    .select_punctual(&hit_record.p, Some(&hit_record.normal));
+                // Light reaching the surface from behind comes through whatever it
+                // encloses.
+                let medium_transmittance = |shadow: &Ray, distance: f64| {
+                    let behind = shadow.direction().dot(&hit_record.normal) < 0.0;
+                    let media = if behind { &beyond } else { media };
+                    self.medium_transmittance(shadow, distance, media, wavelengths)
+                };
+                let mut direct = self.direct_lighting(
+                    &hit_record.p,
+                    ray,
+                    scene.world,
+                    &selected,
+                    medium_transmittance,
+                    |light, sample| {
+                        if !light.illuminates(hit_record.object) {
+                            return Color::new();
+                        }
+                        let f = hit_record
+                            .material
+                            .eval(ray, &hit_record, &sample.direction);
+                        &upsample(f, wavelengths)
+                            * &upsample_illuminant(sample.radiance.clone(), wavelengths)
+                    },
+                );
+                direct +=
+                    self.area_lighting(&hit_record, ray, scene, medium_transmittance, wavelengths);
+                emitted += if dispersive && wavelengths.is_some() {
+                    &direct * &SampledWavelengths::hero_only()
+                } else {
+                    direct
+                };
                 if let Some((attenuation, scattered)) =
                     hit_record.material.scatter(ray, &hit_record)
                 {
-                    emitted += &attenuation * &self.ray_color(&scattered, depth - 1, world) 
+                    let mut attenuation = upsample(attenuation, wavelengths);
+                    if dispersive && wavelengths.is_some() {
+                        attenuation = &attenuation * &SampledWavelengths::hero_only();
+                    }
+                    let transmitted = scattered.direction().dot(&hit_record.normal) < 0.0;
+                    let (next_media, kind) = if transmitted {
+                        (&beyond, RayKind::Refraction)
+                    } else {
+                        (media, RayKind::Reflection)
+                    };
+                    let next_emission =
+                        match hit_record
+                            .material
+                            .pdf(ray, &hit_record, scattered.direction())
+                        {
+                            Some(bsdf_pdf) if !scene.lights.is_empty() => {
+                                let light_pdf = scene.lights.area_pdf(
+                                    &hit_record.p,
+                                    Some(&hit_record.normal),
+                                    scattered.direction(),
+                                );
+                                Emission::Sampled(power_heuristic(bsdf_pdf, light_pdf))
+                            }
+                            _ => Emission::From(hit_record.object),
+                        };
+                    let scattered = scattered.with_kind(kind);
+                    emitted += &attenuation
+                        * &self.ray_color(
+                            &scattered,
+                            depth - 1,
+                            scene,
+                            next_media,
+                            wavelengths,
+                            next_emission,
+                        )
                 }
-                emitted
+                &transmittance * &emitted
+            }
+            None => Color::new(),
+        }
+    }
+
+    // Next-event estimation towards the punctual lights, which no path can hit.
+    // `reflected` gives the light scattered towards the viewer from a light sample,
+    // before occlusion, or nothing where the light is not linked to the receiver.
+    // `transmittance` gives the light let through by the medium along a shadow ray up to
+    // a distance.
+    fn direct_lighting<T, F>(
+        &self,
+        p: &Point3,
+        ray: &Ray,
+        world: &dyn Hittable,
+        selected: &[(&dyn PunctualLight, f64)],
+        transmittance: T,
+        reflected: F,
+    ) -> Color
+    where
+        T: Fn(&Ray, f64) -> Color,
+        F: Fn(&dyn PunctualLight, &LightSample) -> Color,
+    {
+        let mut direct = Color::new();
+        for &(light, weight) in selected {
+            let Some(sample) = light.sample(p) else {
+                continue;
+            };
+            let contribution = weight * &reflected(light, &sample);
+            if contribution.is_near_zero() {
+                continue;
+            }
+
+            let shadow = Ray::new(p.clone(), sample.direction.clone(), ray.time())
+                .with_kind(RayKind::Shadow);
+            let occlusion = Camera::shadow_transmittance(&shadow, sample.distance, world, light);
+            if occlusion <= 0.0 {
+                continue;
+            }
+            direct += occlusion * &(&transmittance(&shadow, sample.distance) * &contribution);
+        }
+        direct
+    }
+
+    // Next-event estimation towards the area lights, weighed against finding the same
+    // emission by scattering. Materials that scatter in discrete directions leave the
+    // lights to be found by scattering alone. Linked emitters are only found this way
+    // from surfaces that sample them, so they take the whole estimate.
+    fn area_lighting<'a, T>(
+        &self,
+        rec: &HitRecord<'a>,
+        ray: &Ray,
+        scene: &Scene<'a>,
+        transmittance: T,
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> Color
+    where
+        T: Fn(&Ray, f64) -> Color,
+    {
+        let Some((direction, light_pdf)) = scene.lights.sample_area(&rec.p, Some(&rec.normal))
+        else {
+            return Color::new();
+        };
+        let Some(bsdf_pdf) = rec.material.pdf(ray, rec, &direction) else {
+            return Color::new();
+        };
+        let f = rec.material.eval(ray, rec, &direction);
+        if light_pdf <= 0.0 || f.is_near_zero() {
+            return Color::new();
+        }
+
+        let shadow = Ray::new(rec.p.clone(), direction, ray.time()).with_kind(RayKind::Shadow);
+        let Some((emitter, linked)) = Camera::find_emitter(&shadow, scene, rec.object) else {
+            return Color::new();
+        };
+        let radiance = emitter.material.emit(&shadow, &emitter);
+        let occlusion = scene.world.transmittance(&shadow, 0.001..emitter.t);
+
+        let mis = if linked {
+            1.0
+        } else {
+            power_heuristic(light_pdf, bsdf_pdf)
+        };
+        let weight = occlusion * mis / light_pdf;
+        let transmittance = transmittance(&shadow, emitter.t);
+        weight
+            * &(&transmittance
+                * &(&upsample(f, wavelengths) * &upsample_illuminant(radiance, wavelengths)))
+    }
+
+    // The fraction of light let through by the medium the path is in along `shadow` up
+    // to `distance`, such as the absorbing glass around the shading point.
+    fn medium_transmittance(
+        &self,
+        shadow: &Ray,
+        distance: f64,
+        media: &MediumStack,
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> Color {
+        match (&self.fog, media.current()) {
+            (_, Some(interior)) => {
+                interior.transmittance(distance * shadow.direction().len(), wavelengths)
+            }
+            (Some(fog), None) => fog.transmittance(shadow, distance, wavelengths),
+            (None, None) => Color::new_with(1.0, 1.0, 1.0),
+        }
+    }
+
+    // The emitter a shadow ray from the named receiver reaches, and whether it is
+    // linked. Only linked emitters are reached past other objects, those not linked to
+    // cast shadows from them.
+    fn find_emitter<'a>(
+        shadow: &Ray,
+        scene: &Scene<'a>,
+        receiver: Option<&str>,
+    ) -> Option<(HitRecord<'a>, bool)> {
+        let mut passed = Vec::new();
+        let mut start = 0.001;
+        loop {
+            let rec = scene.world.hits(shadow, start..utils::INFINITY)?;
+            if let Some(light) = scene.lights.linked(rec.object) {
+                let reached = light.illuminates(receiver)
+                    && !passed.iter().any(|&object| light.shadowed_by(object));
+                return reached.then_some((rec, true));
             }
-            None => {
-                Color::new()
+            if passed.is_empty() && !rec.material.emit(shadow, &rec).is_near_zero() {
+                return Some((rec, false));
             }
+            if !scene.lights.has_linked() {
+                return None;
+            }
+            passed.push(rec.object);
+            start = rec.t + 0.001;
+        }
+    }
+
+    // Traces a shadow ray up to the light, passing through objects that are not linked
+    // to cast shadows from it, and returns the fraction of light that arrives.
+    fn shadow_transmittance(
+        shadow: &Ray,
+        distance: f64,
+        world: &dyn Hittable,
+        light: &dyn PunctualLight,
+    ) -> f64 {
+        let mut start = 0.001;
+        while let Some(rec) = world.hits(shadow, start..distance) {
+            if light.shadowed_by(rec.object) {
+                return 0.0;
+            }
+            start = rec.t + 0.001;
         }
+        world.transmittance(shadow, 0.001..distance)
+    }
+
+    fn fog_in_scatter(p: &Point3, ray: &Ray, lights: &LightList) -> (Ray, f64) {
+        let phase_pdf = 1.0 / (4.0 * utils::PI);
+        let mut rng = rand::thread_rng();
+        let sample_lights = !lights.is_empty() && rng.gen_range(0.0..1.0) < 0.5;
+
+        let direction = if sample_lights {
+            lights.random(p)
+        } else {
+            Vec3::random_unit_vector()
+        };
+        let pdf = if lights.is_empty() {
+            phase_pdf
+        } else {
+            0.5 * phase_pdf + 0.5 * lights.pdf_value(p, &direction)
+        };
+
+        let scattered = Ray::new(p.clone(), direction, ray.time()).with_kind(RayKind::Reflection);
+        (scattered, phase_pdf / pdf)
+    }
+}
+
+// The lights and the objects they light, as the paths through them see them.
+struct Scene<'a> {
+    world: &'a dyn Hittable,
+    lights: &'a LightList,
+}
+
+// How much of the emission a ray lands on counts, as decided where the ray left.
+#[derive(Clone, Copy)]
+enum Emission<'a> {
+    // All of it, from a ray leaving the camera.
+    Camera,
+    // All of it, from a ray leaving the named object, or a medium where `None`. Linked
+    // emitters only count if linked to that object.
+    From(Option<&'a str>),
+    // A share of it, where the area lights were also sampled from where the ray left.
+    // Linked emitters were sampled in full there, so none of theirs counts.
+    Sampled(f64),
+}
+
+impl Emission<'_> {
+    fn weight(&self, lights: &LightList, object: Option<&str>) -> f64 {
+        match (self, lights.linked(object)) {
+            (Emission::Camera, _) => 1.0,
+            (Emission::From(source), Some(light)) if !light.illuminates(*source) => 0.0,
+            (Emission::From(_), _) => 1.0,
+            (Emission::Sampled(_), Some(_)) => 0.0,
+            (Emission::Sampled(weight), None) => *weight,
+        }
+    }
+}
+
+// Veach's power heuristic with an exponent of two, for the weight of a sample drawn
+// with density `pdf` when `other_pdf` could also have drawn it.
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
+    }
+}
+
+fn upsample(rgb: Color, wavelengths: Option<&SampledWavelengths>) -> Color {
+    match wavelengths {
+        Some(wavelengths) => wavelengths.upsample(&rgb),
+        None => rgb,
+    }
+}
+
+fn upsample_illuminant(rgb: Color, wavelengths: Option<&SampledWavelengths>) -> Color {
+    match wavelengths {
+        Some(wavelengths) => wavelengths.upsample_illuminant(&rgb),
+        None => rgb,
     }
 }
 
 fn log(text: &str) {
     eprint!("{}", text);
 }
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::{
+        hittable_list::HittableList,
+        light::{AreaLight, LinkSet, LinkedLight},
+        material::{Dielectric, LambertianMaterial, LightDiffuser, Material},
+        object::Object,
+        sphere::Sphere,
+    };
+
+    fn sphere(center: &Point3, radius: f64, material: Box<dyn Material>) -> Box<Sphere> {
+        Box::new(Sphere::new(center, center, radius, 0.0, 0.0, material))
+    }
+
+    // The mean brightness of a floor under a lamp, seen from beside it, with the lamp
+    // linked as given and optionally enclosed in a shell.
+    fn floor_brightness(illumination: LinkSet, shadowing: LinkSet, shell: bool) -> f64 {
+        let lamp_center = Point3::new_with(0.0, 3.0, 0.0);
+        let grey = || Box::new(LambertianMaterial::new(&Color::new_with(0.5, 0.5, 0.5)));
+        let lamp = LightDiffuser::new(&Color::new_with(10.0, 10.0, 10.0));
+
+        let mut world = HittableList::new();
+        let ground = Point3::new_with(0.0, -1000.0, 0.0);
+        world.add(Box::new(Object::new(
+            "floor",
+            sphere(&ground, 1000.0, grey()),
+        )));
+        let lamp: Rc<dyn Hittable> = Rc::new(Object::new(
+            "lamp",
+            sphere(&lamp_center, 0.5, Box::new(lamp)),
+        ));
+        world.add(Box::new(lamp.clone()));
+        if shell {
+            world.add(Box::new(Object::new(
+                "shell",
+                sphere(&lamp_center, 1.0, grey()),
+            )));
+        }
+
+        let mut lights = LightList::new();
+        let light = AreaLight::new(lamp);
+        lights.add(Box::new(
+            LinkedLight::new("lamp", Box::new(light))
+                .with_illumination(illumination)
+                .with_shadowing(shadowing),
+        ));
+
+        let mut camera = Camera::new();
+        camera.image_width = 8;
+        camera.aspect_ratio = 1.0;
+        camera.samples_per_pixel = 8;
+        camera.max_bounces = 4;
+        camera.vfov = 30.0;
+        camera.lookfrom = Point3::new_with(0.0, 0.5, 2.0);
+        camera.lookat = Point3::new_with(0.0, 0.0, 0.0);
+        let image = camera.render_image(&world, &lights);
+
+        let mut total = 0.0;
+        for y in 0..image.height() {
+            for x in 0..image.width() {
+                total += image.get(x, y).luminance();
+            }
+        }
+        total / (image.width() * image.height()) as f64
+    }
+
+    #[test]
+    fn linked_area_lights_only_light_their_objects() {
+        assert!(floor_brightness(LinkSet::All, LinkSet::All, false) > 0.0);
+        assert!(floor_brightness(LinkSet::include(&["floor"]), LinkSet::All, false) > 0.0);
+        assert_eq!(
+            floor_brightness(LinkSet::exclude(&["floor"]), LinkSet::All, false),
+            0.0
+        );
+    }
+
+    #[test]
+    fn linked_area_lights_pass_through_unlinked_shadows() {
+        assert_eq!(floor_brightness(LinkSet::All, LinkSet::All, true), 0.0);
+        assert!(floor_brightness(LinkSet::All, LinkSet::exclude(&["shell"]), true) > 0.0);
+    }
+
+    // The brightness of a translucent ball filling the view, lit evenly from all sides.
+    fn furnace_brightness(ball: Dielectric) -> f64 {
+        let mut world = HittableList::new();
+        let sky = LightDiffuser::new(&Color::new_with(1.0, 1.0, 1.0));
+        world.add(sphere(&Point3::new(), 10.0, Box::new(sky)));
+        world.add(sphere(&Point3::new(), 1.0, Box::new(ball)));
+
+        let mut camera = Camera::new();
+        camera.image_width = 4;
+        camera.aspect_ratio = 1.0;
+        camera.samples_per_pixel = 64;
+        camera.max_bounces = 64;
+        camera.vfov = 10.0;
+        camera.lookfrom = Point3::new_with(0.0, 0.0, 3.0);
+        camera.lookat = Point3::new();
+        let image = camera.render_image(&world, &LightList::new());
+
+        let mut total = 0.0;
+        for y in 0..image.height() {
+            for x in 0..image.width() {
+                total += image.get(x, y).luminance();
+            }
+        }
+        total / (image.width() * image.height()) as f64
+    }
+
+    #[test]
+    fn subsurface_walks_only_lose_what_is_absorbed() {
+        let scattering = Color::new_with(2.0, 2.0, 2.0);
+        let clear = Dielectric::new(1.0).with_scattering(&scattering);
+        let clear = furnace_brightness(clear);
+        assert!((clear - 1.0).abs() < 0.02, "{clear}");
+
+        let absorbing = Dielectric::new(1.0)
+            .with_scattering(&scattering)
+            .with_absorption(&Color::new_with(0.5, 0.5, 0.5));
+        let absorbing = furnace_brightness(absorbing);
+        assert!(absorbing < 0.8 && absorbing > 0.1, "{absorbing}");
+    }
+}
@@ -0,0 +1,198 @@
+use rand::Rng;
+
+use crate::{
+    color::Color,
+    hittable::HitRecord,
+    material::Material,
+    microfacet::{self, TrowbridgeReitz},
+    ray::Ray,
+    vec3::Vec3,
+};
+
+const MAX_COAT_BOUNCES: usize = 32;
+
+// A dielectric coat over an arbitrary base material. Light is followed as a random
+// walk between the coat interface and the base (position-free, in the spirit of Guo
+// et al. 2018), so every internal reflection is accounted for and no energy is
+// created or lost beyond what the coat absorbs. The walk has no closed form to
+// evaluate, so lights are only reached by scattering.
+pub struct Coated {
+    base: Box<dyn Material>,
+    refraction_index: f64,
+    distribution: TrowbridgeReitz,
+    thickness: f64,
+    absorption: Color,
+}
+
+impl Coated {
+    pub fn new(base: Box<dyn Material>, refraction_index: f64) -> Coated {
+        Coated {
+            base,
+            refraction_index,
+            distribution: TrowbridgeReitz::new(0.0, 0.0),
+            thickness: 0.0,
+            absorption: Color::new(),
+        }
+    }
+
+    pub fn with_roughness(mut self, roughness: f64) -> Coated {
+        self.distribution = TrowbridgeReitz::from_roughness(roughness, roughness);
+        self
+    }
+
+    pub fn with_thickness(mut self, thickness: f64) -> Coated {
+        self.thickness = thickness.max(0.0);
+        self
+    }
+
+    pub fn with_absorption(mut self, absorption: &Color) -> Coated {
+        self.absorption = absorption.clone();
+        self
+    }
+
+    // Reflects or refracts `wo` at the coat interface, choosing by Fresnel. `eta` is the
+    // IOR on the far side over the near side, and the near side is +z.
+    fn cross_interface(&self, wo: &Vec3, eta: f64) -> Option<(Vec3, f64)> {
+        let normal = Vec3::new_with(0.0, 0.0, 1.0);
+        let wm = if self.distribution.is_smooth() {
+            normal
+        } else {
+            self.distribution.sample_wm(wo)
+        };
+        let reflectance = microfacet::fresnel_dielectric(wo.dot(&wm), eta);
+
+        let mut rng = rand::thread_rng();
+        let wi = if rng.gen_range(0.0..1.0) < reflectance {
+            microfacet::reflect(wo, &wm)
+        } else {
+            microfacet::refract(wo, &wm, eta)?
+        };
+        if wi.z() == 0.0 {
+            return None;
+        }
+
+        let weight = if self.distribution.is_smooth() {
+            1.0
+        } else {
+            self.distribution.g(wo, &wi) / self.distribution.g1(wo)
+        };
+        Some((wi, weight))
+    }
+
+    fn transmittance(&self, w: &Vec3) -> Color {
+        let distance = self.thickness / w.z().abs().max(1e-4);
+        let channel = |absorption: f64| (-absorption.max(0.0) * distance).exp();
+        Color::new_with(
+            channel(self.absorption.x()),
+            channel(self.absorption.y()),
+            channel(self.absorption.z()),
+        )
+    }
+}
+
+impl Material for Coated {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let uvw = rec.shading_frame();
+        let wo = uvw.to_local(&(-&ray_in.direction().unit_vector()));
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        let (mut w, weight) = self.cross_interface(&wo, self.refraction_index)?;
+        let mut throughput = Color::new_with(weight, weight, weight);
+        if w.z() > 0.0 {
+            let scattered = Ray::new(rec.p.clone(), uvw.transform(&w), ray_in.time());
+            return Some((throughput, scattered));
+        }
+
+        // The base sees light arriving through the coat rather than from outside.
+        let mut base_rec = rec.clone();
+        base_rec.outer_refraction_index = self.refraction_index;
+        let flip = |v: &Vec3| Vec3::new_with(v.x(), v.y(), -v.z());
+
+        for _ in 0..MAX_COAT_BOUNCES {
+            throughput = &throughput * &self.transmittance(&w);
+            let incoming = Ray::new(rec.p.clone(), uvw.transform(&w), ray_in.time());
+            let (attenuation, scattered) = self.base.scatter(&incoming, &base_rec)?;
+            w = uvw.to_local(&scattered.direction().unit_vector());
+            if w.z() <= 0.0 {
+                return None;
+            }
+            throughput = &(&throughput * &attenuation) * &self.transmittance(&w);
+
+            // Seen from inside the coat the interface normal points down, so work in a
+            // mirrored frame where the incident side is again +z.
+            let (wi, weight) = self.cross_interface(&flip(&(-&w)), 1.0 / self.refraction_index)?;
+            throughput = weight * &throughput;
+            w = flip(&wi);
+            if w.z() > 0.0 {
+                let scattered = Ray::new(rec.p.clone(), uvw.transform(&w), ray_in.time());
+                return Some((throughput, scattered));
+            }
+        }
+        None
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.base.is_dispersive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hittable::Hittable, material::LambertianMaterial, sphere::Sphere, utils, vec3::Point3,
+    };
+
+    fn albedo(material: Box<dyn Material>, direction: &Vec3) -> f64 {
+        let center = Point3::new_with(0.0, 0.0, -3.0);
+        let sphere = Sphere::new(&center, &center, 1.0, 0.0, 0.0, material);
+        let ray = Ray::new(Point3::new(), direction.clone(), 0.0);
+        let rec = sphere.hits(&ray, 0.001..utils::INFINITY).unwrap();
+
+        let samples = 20_000;
+        let mut total = 0.0;
+        for _ in 0..samples {
+            if let Some((attenuation, _)) = rec.material.scatter(&ray, &rec) {
+                total += attenuation.y();
+            }
+        }
+        total / samples as f64
+    }
+
+    #[test]
+    fn clear_coats_over_white_create_no_energy() {
+        let white = || Box::new(LambertianMaterial::new(&Color::new_with(1.0, 1.0, 1.0)));
+        for direction in [
+            Vec3::new_with(0.0, 0.0, -1.0),
+            Vec3::new_with(0.25, 0.1, -1.0),
+        ] {
+            let smooth = albedo(Box::new(Coated::new(white(), 1.5)), &direction);
+            assert!((smooth - 1.0).abs() < 0.01, "{smooth}");
+            // A rough interface loses what its microfacets shadow, but never adds any.
+            let rough = Coated::new(white(), 1.5).with_roughness(0.5);
+            let rough = albedo(Box::new(rough), &direction);
+            assert!(rough < smooth && rough > 0.75, "{rough}");
+        }
+    }
+
+    #[test]
+    fn black_bases_leave_the_fresnel_reflection() {
+        let black = Box::new(LambertianMaterial::new(&Color::new()));
+        let coat = albedo(
+            Box::new(Coated::new(black, 1.5)),
+            &Vec3::new_with(0.0, 0.0, -1.0),
+        );
+        let expected = microfacet::fresnel_dielectric(1.0, 1.5);
+        assert!((coat - expected).abs() < 0.01, "{coat} {expected}");
+
+        // Absorption in the coat darkens a white base.
+        let white = Box::new(LambertianMaterial::new(&Color::new_with(1.0, 1.0, 1.0)));
+        let tinted = Coated::new(white, 1.5)
+            .with_thickness(1.0)
+            .with_absorption(&Color::new_with(0.5, 0.5, 0.5));
+        let tinted = albedo(Box::new(tinted), &Vec3::new_with(0.0, 0.0, -1.0));
+        assert!(tinted < 0.6, "{tinted}");
+    }
+}
@@ -1,9 +1,11 @@
+use std::io::{self, Write};
+
 use crate::{camera::MAX_COLOR, utils::Interval, vec3::Vec3};
 
 pub type Color = Vec3;
 
 impl Color {
-    pub fn print(&self) {
+    pub fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
         let r = Color::linear_to_gamma(self.x());
         let g = Color::linear_to_gamma(self.y());
         let b = Color::linear_to_gamma(self.z());
@@ -13,7 +15,12 @@ impl Color {
         let gu = MAX_COLOR as f64 * intensity.make_fit(g);
         let bu = MAX_COLOR as f64 * intensity.make_fit(b);
 
-        println!("{ru} {gu} {bu}");
+        writeln!(out, "{ru} {gu} {bu}")
+    }
+
+    // Relative luminance Y of linear sRGB.
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.x() + 0.7152 * self.y() + 0.0722 * self.z()
     }
 
     fn linear_to_gamma(linear_component: f64) -> f64 {
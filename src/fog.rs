@@ -0,0 +1,216 @@
+use rand::Rng;
+
+use crate::{color::Color, ray::Ray, spectrum::SampledWavelengths};
+
+pub enum FogDensity {
+    Homogeneous,
+    ExponentialHeight { base_height: f64, falloff: f64 },
+}
+
+// Coefficients are per unit distance and per RGB channel, so that haze can scatter
+// blue more than red.
+pub struct Fog {
+    absorption: Color,
+    scattering: Color,
+    density: FogDensity,
+}
+
+impl Fog {
+    pub fn homogeneous(absorption: &Color, scattering: &Color) -> Fog {
+        Fog {
+            absorption: non_negative(absorption),
+            scattering: non_negative(scattering),
+            density: FogDensity::Homogeneous,
+        }
+    }
+
+    // Coefficients are given at `base_height` and decay as exp(-falloff * height above it).
+    pub fn exponential_height(
+        absorption: &Color,
+        scattering: &Color,
+        base_height: f64,
+        falloff: f64,
+    ) -> Fog {
+        Fog {
+            absorption: non_negative(absorption),
+            scattering: non_negative(scattering),
+            density: FogDensity::ExponentialHeight {
+                base_height,
+                falloff: falloff.max(0.0),
+            },
+        }
+    }
+
+    fn coefficients(&self, wavelengths: Option<&SampledWavelengths>) -> (Color, Color) {
+        let extinction = &self.absorption + &self.scattering;
+        match wavelengths {
+            Some(wavelengths) => (
+                wavelengths.upsample(&self.scattering),
+                wavelengths.upsample(&extinction),
+            ),
+            None => (self.scattering.clone(), extinction),
+        }
+    }
+
+    // The density along `ray` relative to the coefficients, at its origin and how fast
+    // it decays with the ray parameter.
+    fn density_along(&self, ray: &Ray) -> (f64, f64) {
+        match self.density {
+            FogDensity::Homogeneous => (1.0, 0.0),
+            FogDensity::ExponentialHeight {
+                base_height,
+                falloff,
+            } => (
+                (-falloff * (ray.origin().y() - base_height)).exp(),
+                falloff * ray.direction().y(),
+            ),
+        }
+    }
+
+    // The integral of the relative density along `ray` up to `t`.
+    fn depth(&self, ray: &Ray, t: f64) -> f64 {
+        let (density, k) = self.density_along(ray);
+        let scale = density * ray.direction().len();
+        if k.abs() < 1e-9 {
+            scale * t
+        } else {
+            scale * (1.0 - (-k * t).exp()) / k
+        }
+    }
+
+    // The ray parameter at which `depth` reaches `depth`, if it ever does.
+    fn inverse_depth(&self, ray: &Ray, depth: f64) -> Option<f64> {
+        let (density, k) = self.density_along(ray);
+        let scale = density * ray.direction().len();
+        if scale <= 0.0 {
+            return None;
+        }
+        if k.abs() < 1e-9 {
+            return Some(depth / scale);
+        }
+        let remaining = 1.0 - depth * k / scale;
+        if remaining <= 0.0 {
+            return None;
+        }
+        Some(-remaining.ln() / k)
+    }
+
+    // Fraction of light that crosses the fog along `ray` up to `t_max`, per channel.
+    pub fn transmittance(
+        &self,
+        ray: &Ray,
+        t_max: f64,
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> Color {
+        let (_, extinction) = self.coefficients(wavelengths);
+        let depth = self.depth(ray, t_max);
+        channels(&extinction, |sigma| {
+            if sigma > 0.0 {
+                (-sigma * depth).exp()
+            } else {
+                1.0
+            }
+        })
+    }
+
+    // Samples a free-flight distance along `ray`, returning the ray parameter of the
+    // collision if it happens before `t_max`, with the weight to apply either way. As
+    // for `Interior::sample_free_flight`, the distance follows a random channel's
+    // extinction and is weighed by the density averaged over the channels.
+    pub fn sample_distance(
+        &self,
+        ray: &Ray,
+        t_max: f64,
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> (Option<f64>, Color) {
+        let (scattering, extinction) = self.coefficients(wavelengths);
+        let sigma = [extinction.x(), extinction.y(), extinction.z()];
+
+        let mut rng = rand::thread_rng();
+        let channel = rng.gen_range(0..3);
+        let t = if sigma[channel] > 0.0 {
+            let optical_depth = -(1.0 - rng.gen_range(0.0..1.0_f64)).ln();
+            self.inverse_depth(ray, optical_depth / sigma[channel])
+        } else {
+            None
+        };
+        let t = t.filter(|&t| t < t_max);
+
+        let transmittance = self.transmittance(ray, t.unwrap_or(t_max), wavelengths);
+        let average = |color: &Color| (color.x() + color.y() + color.z()) / 3.0;
+        match t {
+            // The relative density at the collision scales both the scattering and the
+            // pdf, so it cancels.
+            Some(t) => {
+                let pdf = average(&(&extinction * &transmittance));
+                (Some(t), &(&scattering * &transmittance) / pdf)
+            }
+            None => (None, &transmittance / average(&transmittance)),
+        }
+    }
+}
+
+fn non_negative(color: &Color) -> Color {
+    channels(color, |value| value.max(0.0))
+}
+
+fn channels(color: &Color, f: impl Fn(f64) -> f64) -> Color {
+    Color::new_with(f(color.x()), f(color.y()), f(color.z()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::{Point3, Vec3};
+
+    #[test]
+    fn transmittance_follows_each_channel() {
+        let fog = Fog::exponential_height(
+            &Color::new_with(0.1, 0.1, 0.1),
+            &Color::new_with(0.1, 0.3, 0.9),
+            0.0,
+            0.5,
+        );
+        let ray = Ray::new(
+            Point3::new_with(0.0, 1.0, 0.0),
+            Vec3::new_with(1.0, 1.0, 0.0),
+            0.0,
+        );
+
+        // Midpoint rule along the ray.
+        let steps = 10000;
+        let t_max = 3.0;
+        let depth = (0..steps)
+            .map(|i| {
+                let p = ray.at((i as f64 + 0.5) * t_max / steps as f64);
+                (-0.5 * p.y()).exp() * ray.direction().len() * t_max / steps as f64
+            })
+            .sum::<f64>();
+        let transmittance = fog.transmittance(&ray, t_max, None);
+        for (sigma, channel) in [(0.2, transmittance.x()), (1.0, transmittance.z())] {
+            assert!((channel - (-sigma * depth).exp()).abs() < 1e-6, "{channel}");
+        }
+    }
+
+    // Passing through the fog with the weights from `sample_distance` should let
+    // through as much of each channel as the transmittance says.
+    #[test]
+    fn sampled_distances_are_unbiased_per_channel() {
+        let fog = Fog::homogeneous(
+            &Color::new_with(0.0, 0.0, 0.0),
+            &Color::new_with(0.1, 0.5, 2.0),
+        );
+        let ray = Ray::new(Point3::new(), Vec3::new_with(0.0, 0.0, -1.0), 0.0);
+
+        let samples = 200000;
+        let mut passed = Color::new();
+        for _ in 0..samples {
+            if let (None, weight) = fog.sample_distance(&ray, 1.0, None) {
+                passed += weight;
+            }
+        }
+        let passed = &passed / samples as f64;
+        let expected = fog.transmittance(&ray, 1.0, None);
+        assert!((&passed - &expected).len() < 0.01, "{}", passed.z());
+    }
+}
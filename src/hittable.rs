@@ -1,27 +1,46 @@
-use std::ops::Range;
+use std::{ops::Range, rc::Rc};
 
 use crate::{
+    aabb::Aabb,
+    color::Color,
     material::Material,
+    onb::Onb,
     ray::Ray,
     vec3::{Point3, Vec3},
 };
 
+#[derive(Clone)]
 pub struct HitRecord<'a> {
     pub p: Point3,
     pub normal: Vec3,
     pub t: f64,
+    pub u: f64,
+    pub v: f64,
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
     pub front_face: bool,
+    pub outer_refraction_index: f64,
+    pub wavelength: Option<f64>,
     pub material: &'a dyn Material,
+    // The name of the object that was hit, for light linking.
+    pub object: Option<&'a str>,
 }
 
 impl<'a> HitRecord<'a> {
-    pub fn new(p: Point3, normal: Vec3, t: f64, material: &dyn Material) -> HitRecord {
+    pub fn new(p: Point3, normal: Vec3, t: f64, material: &dyn Material) -> HitRecord<'_> {
         HitRecord {
             p,
             normal,
             t,
             material,
+            u: 0.0,
+            v: 0.0,
+            dpdu: Vec3::new(),
+            dpdv: Vec3::new(),
             front_face: false,
+            outer_refraction_index: 1.0,
+            wavelength: None,
+            object: None,
         }
     }
 
@@ -34,8 +53,101 @@ impl<'a> HitRecord<'a> {
             self.normal = -&self.normal;
         }
     }
+
+    // An orthonormal tangent and bitangent around the normal, following the surface's
+    // u and v directions where it has them.
+    pub fn tangent_frame(&self) -> (Vec3, Vec3) {
+        let tangent = &self.dpdu - &(self.dpdu.dot(&self.normal) * &self.normal);
+        if tangent.is_near_zero() {
+            let uvw = Onb::new(&self.normal);
+            return (uvw.u().clone(), uvw.v().clone());
+        }
+        let tangent = tangent.unit_vector();
+
+        let bitangent = &(&self.dpdv - &(self.dpdv.dot(&self.normal) * &self.normal))
+            - &(self.dpdv.dot(&tangent) * &tangent);
+        let bitangent = if bitangent.is_near_zero() {
+            self.normal.cross(&tangent)
+        } else {
+            bitangent.unit_vector()
+        };
+        (tangent, bitangent)
+    }
+
+    // The local frame for anisotropic shading, with u along the surface's tangent so
+    // that roughness along u and v follows the surface parametrisation.
+    pub fn shading_frame(&self) -> Onb {
+        let (tangent, _) = self.tangent_frame();
+        Onb::with_reference(&self.normal, &tangent)
+    }
 }
 
 pub trait Hittable {
-    fn hits(&mut self, ray: &Ray, ray_t: Range<f64>) -> Option<HitRecord>;
+    fn hits(&self, ray: &Ray, ray_t: Range<f64>) -> Option<HitRecord<'_>>;
+
+    // Surface area, for emitters specified by their power.
+    fn area(&self) -> f64 {
+        0.0
+    }
+
+    // The radiance the object emits, averaged over its surface, for weighing the
+    // lights standing for emitters against each other.
+    fn average_emission(&self) -> Color {
+        Color::new()
+    }
+
+    // A box around everything the object may hit over the shutter interval, or None if
+    // it is unbounded.
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+
+    // The fraction of light let through by participating media along `ray`, which
+    // shadow rays pass through rather than hit. Surfaces block shadow rays through
+    // `hits` instead and let everything through here.
+    #[allow(unused_variables)]
+    fn transmittance(&self, ray: &Ray, ray_t: Range<f64>) -> f64 {
+        1.0
+    }
+
+    #[allow(unused_variables)]
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    #[allow(unused_variables)]
+    fn random(&self, origin: &Point3) -> Vec3 {
+        Vec3::new_with(1.0, 0.0, 0.0)
+    }
+}
+
+// A shape shared between the world and the area light sampling it.
+impl<H: Hittable + ?Sized> Hittable for Rc<H> {
+    fn hits(&self, ray: &Ray, ray_t: Range<f64>) -> Option<HitRecord<'_>> {
+        self.as_ref().hits(ray, ray_t)
+    }
+
+    fn area(&self) -> f64 {
+        self.as_ref().area()
+    }
+
+    fn average_emission(&self) -> Color {
+        self.as_ref().average_emission()
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.as_ref().bounding_box()
+    }
+
+    fn transmittance(&self, ray: &Ray, ray_t: Range<f64>) -> f64 {
+        self.as_ref().transmittance(ray, ray_t)
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.as_ref().pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        self.as_ref().random(origin)
+    }
 }
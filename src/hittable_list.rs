@@ -1,8 +1,11 @@
-use std::ops::Range;
 use crate::{
+    aabb::Aabb,
     hittable::{HitRecord, Hittable},
     ray::Ray,
+    vec3::{Point3, Vec3},
 };
+use rand::Rng;
+use std::ops::Range;
 
 pub struct HittableList {
     objects: Vec<Box<dyn Hittable>>,
@@ -19,16 +22,17 @@ impl HittableList {
         self.objects.push(object);
     }
 
+    #[allow(dead_code, reason = "no scene rebuilds its world yet")]
     pub fn clear(&mut self) {
         self.objects.clear();
     }
 }
 
 impl Hittable for HittableList {
-    fn hits(&mut self, ray: &Ray, ray_t: Range<f64>) -> Option<HitRecord> {
+    fn hits(&self, ray: &Ray, ray_t: Range<f64>) -> Option<HitRecord<'_>> {
         let mut closest_so_far = ray_t.end;
         let mut temp_hit_record = None;
-        for object in self.objects.iter_mut() {
+        for object in self.objects.iter() {
             if let Some(hit_record) = object.hits(ray, ray_t.start..closest_so_far) {
                 closest_so_far = hit_record.t;
                 temp_hit_record = Some(hit_record);
@@ -36,4 +40,38 @@ impl Hittable for HittableList {
         }
         temp_hit_record
     }
+
+    fn transmittance(&self, ray: &Ray, ray_t: Range<f64>) -> f64 {
+        self.objects
+            .iter()
+            .map(|object| object.transmittance(ray, ray_t.clone()))
+            .product()
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut boxes = self.objects.iter().map(|object| object.bounding_box());
+        let first = boxes.next()??;
+        boxes.try_fold(first, |bounds, next| {
+            Some(Aabb::surrounding(&bounds, &next?))
+        })
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+        let weight = 1.0 / self.objects.len() as f64;
+        self.objects
+            .iter()
+            .map(|object| weight * object.pdf_value(origin, direction))
+            .sum()
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        if self.objects.is_empty() {
+            return Vec3::random_unit_vector();
+        }
+        let index = rand::thread_rng().gen_range(0..self.objects.len());
+        self.objects[index].random(origin)
+    }
 }
@@ -0,0 +1,254 @@
+use std::{fs, io, path::Path};
+
+// Photometric type of goniometers that measure vertical angles from the nadir, the
+// only kind used for building luminaires in practice.
+const PHOTOMETRY_TYPE_C: u32 = 1;
+
+// The candela distribution of a luminaire from an IES LM-63 file. Vertical angles run
+// from the nadir (0) to the zenith (180), and horizontal angles counter-clockwise
+// around the nadir axis when seen from above.
+pub struct IesProfile {
+    vertical_angles: Vec<f64>,
+    horizontal_angles: Vec<f64>,
+    // One row of vertical samples per horizontal angle.
+    candela: Vec<Vec<f64>>,
+}
+
+impl IesProfile {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<IesProfile> {
+        let bytes = fs::read(path)?;
+        IesProfile::parse(&String::from_utf8_lossy(&bytes))
+    }
+
+    pub fn parse(text: &str) -> io::Result<IesProfile> {
+        let mut lines = text.lines();
+        let tilt = loop {
+            let line = lines
+                .next()
+                .ok_or_else(|| invalid_data("IES file has no TILT line"))?;
+            if let Some(tilt) = line.trim().strip_prefix("TILT=") {
+                break tilt.trim().to_string();
+            }
+        };
+
+        let mut numbers = lines
+            .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+            .filter(|token| !token.is_empty())
+            .map(|token| {
+                token
+                    .parse::<f64>()
+                    .map_err(|_| invalid_data("malformed number in IES file"))
+            });
+        let mut next = || {
+            numbers
+                .next()
+                .unwrap_or_else(|| Err(invalid_data("IES file is truncated")))
+        };
+
+        // Lamp tilt factors describe output changes with mounting angle, which a fixed
+        // light does not need; skip past them. Tilt data kept in a separate file is not
+        // read.
+        match tilt.as_str() {
+            "NONE" => {}
+            "INCLUDE" => {
+                next()?;
+                let pairs = next()? as usize;
+                let values = pairs
+                    .checked_mul(2)
+                    .ok_or_else(|| invalid_data("IES tilt table is too large"))?;
+                for _ in 0..values {
+                    next()?;
+                }
+            }
+            _ => return Err(invalid_data("unsupported TILT in IES file")),
+        }
+
+        let _lamps = next()?;
+        let _lumens_per_lamp = next()?;
+        let multiplier = next()?;
+        let vertical_count = next()? as usize;
+        let horizontal_count = next()? as usize;
+        if next()? as u32 != PHOTOMETRY_TYPE_C {
+            return Err(invalid_data("only type C photometry is supported"));
+        }
+        for _ in 0..4 {
+            next()?; // units, width, length, height
+        }
+        let ballast_factor = next()?;
+        next()?; // file generation type
+        next()?; // input watts
+
+        if vertical_count == 0 || horizontal_count == 0 {
+            return Err(invalid_data("IES file has an empty candela table"));
+        }
+        let vertical_angles = (0..vertical_count)
+            .map(|_| next())
+            .collect::<io::Result<Vec<f64>>>()?;
+        let horizontal_angles = (0..horizontal_count)
+            .map(|_| next())
+            .collect::<io::Result<Vec<f64>>>()?;
+        let scale = multiplier * ballast_factor;
+        let candela = (0..horizontal_count)
+            .map(|_| {
+                (0..vertical_count)
+                    .map(|_| Ok(scale * next()?))
+                    .collect::<io::Result<Vec<f64>>>()
+            })
+            .collect::<io::Result<Vec<Vec<f64>>>>()?;
+
+        let ascending = |angles: &[f64]| angles.windows(2).all(|pair| pair[0] < pair[1]);
+        if !ascending(&vertical_angles) || !ascending(&horizontal_angles) {
+            return Err(invalid_data("IES angles are not in ascending order"));
+        }
+
+        Ok(IesProfile {
+            vertical_angles,
+            horizontal_angles,
+            candela,
+        })
+    }
+
+    // Luminous intensity towards the given angles, in degrees.
+    pub fn candela(&self, vertical: f64, horizontal: f64) -> f64 {
+        let (first, last) = (
+            self.vertical_angles[0],
+            self.vertical_angles[self.vertical_angles.len() - 1],
+        );
+        if vertical < first || vertical > last {
+            return 0.0;
+        }
+
+        let (row, t) = bracket(&self.horizontal_angles, self.fold_horizontal(horizontal));
+        let (column, s) = bracket(&self.vertical_angles, vertical);
+        let sample = |row: usize| {
+            let values = &self.candela[row];
+            let next = (column + 1).min(values.len() - 1);
+            (1.0 - s) * values[column] + s * values[next]
+        };
+        let next_row = (row + 1).min(self.candela.len() - 1);
+        (1.0 - t) * sample(row) + t * sample(next_row)
+    }
+
+    pub fn max_candela(&self) -> f64 {
+        self.candela
+            .iter()
+            .flatten()
+            .fold(0.0, |max, &value| f64::max(max, value))
+    }
+
+    // Files only store as much of the distribution as its symmetry requires: a single
+    // plane, one quadrant, one half, or the full circle.
+    fn fold_horizontal(&self, horizontal: f64) -> f64 {
+        let first = self.horizontal_angles[0];
+        let last = self.horizontal_angles[self.horizontal_angles.len() - 1];
+        let phi = horizontal.rem_euclid(360.0);
+        if self.horizontal_angles.len() == 1 {
+            first
+        } else if first == 0.0 && last == 90.0 {
+            let phi = if phi > 180.0 { 360.0 - phi } else { phi };
+            if phi > 90.0 {
+                180.0 - phi
+            } else {
+                phi
+            }
+        } else if first == 0.0 && last == 180.0 {
+            if phi > 180.0 {
+                360.0 - phi
+            } else {
+                phi
+            }
+        } else if first == 90.0 && last == 270.0 {
+            if phi < 90.0 {
+                180.0 - phi
+            } else if phi > 270.0 {
+                540.0 - phi
+            } else {
+                phi
+            }
+        } else {
+            phi.clamp(first, last)
+        }
+    }
+}
+
+// The index of the sample at or below `x` and the fraction of the way to the next one.
+fn bracket(angles: &[f64], x: f64) -> (usize, f64) {
+    let upper = angles.partition_point(|&angle| angle <= x);
+    if upper == 0 {
+        return (0, 0.0);
+    }
+    if upper == angles.len() {
+        return (angles.len() - 1, 0.0);
+    }
+    let lower = upper - 1;
+    (lower, (x - angles[lower]) / (angles[upper] - angles[lower]))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A quadrant-symmetric profile, brightest straight down and dimmer across the 90
+    // degree plane, with the multiplier and ballast factor halving every value.
+    const QUADRANT: &str = "IESNA:LM-63-2002
+[TEST] quadrant
+TILT=NONE
+1 1000 0.5 3 2 1 1 0 0 0
+1.0 1 100
+0 45 90
+0 90
+800 400 0
+600 200 0
+";
+
+    #[test]
+    fn candela_interpolates_the_table() {
+        let profile = IesProfile::parse(QUADRANT).unwrap();
+        assert_eq!(profile.max_candela(), 400.0);
+        assert_eq!(profile.candela(0.0, 0.0), 400.0);
+        assert_eq!(profile.candela(45.0, 90.0), 100.0);
+        assert_eq!(profile.candela(22.5, 0.0), 300.0);
+        assert_eq!(profile.candela(45.0, 45.0), 150.0);
+        // Nothing is measured above the horizon.
+        assert_eq!(profile.candela(120.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn candela_unfolds_symmetric_profiles() {
+        let profile = IesProfile::parse(QUADRANT).unwrap();
+        for horizontal in [90.0, 270.0, -90.0, 450.0] {
+            assert_eq!(profile.candela(45.0, horizontal), 100.0, "{horizontal}");
+        }
+        for horizontal in [180.0, 360.0] {
+            assert_eq!(profile.candela(45.0, horizontal), 200.0, "{horizontal}");
+        }
+        assert_eq!(profile.candela(45.0, 135.0), profile.candela(45.0, 45.0));
+    }
+
+    #[test]
+    fn included_tilt_tables_are_skipped() {
+        let included =
+            QUADRANT.replace("TILT=NONE\n", "TILT=INCLUDE\n1\n3\n0 45 90\n1.0 0.9 0.8\n");
+        let profile = IesProfile::parse(&included).unwrap();
+        assert_eq!(profile.candela(45.0, 0.0), 200.0);
+    }
+
+    #[test]
+    fn malformed_files_are_rejected() {
+        let external = QUADRANT.replace("TILT=NONE", "TILT=lamp.tlt");
+        let error = IesProfile::parse(&external).err().unwrap();
+        assert!(error.to_string().contains("TILT"), "{error}");
+
+        let huge = QUADRANT.replace("TILT=NONE\n", "TILT=INCLUDE\n1\n1e30\n");
+        let error = IesProfile::parse(&huge).err().unwrap();
+        assert!(error.to_string().contains("too large"), "{error}");
+
+        let truncated = &QUADRANT[..QUADRANT.len() - 8];
+        assert!(IesProfile::parse(truncated).is_err());
+        assert!(IesProfile::parse("no tilt here").is_err());
+    }
+}
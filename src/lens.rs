@@ -0,0 +1,538 @@
+use std::{
+    cell::{OnceCell, RefCell},
+    fs, io,
+    path::Path,
+    rc::Rc,
+};
+
+use rand::Rng;
+
+use crate::{
+    projection::{CameraRay, Projection, View},
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
+
+// Prescriptions are written in millimetres and scenes in metres.
+const MILLIMETRES: f64 = 0.001;
+
+// Film radii at which the exit pupil is bounded, and the rays traced to bound each.
+const PUPIL_SEGMENTS: usize = 64;
+const PUPIL_SAMPLES: usize = 65536;
+
+#[derive(Clone)]
+struct LensElement {
+    curvature_radius: f64,
+    // Distance along the axis to the next interface towards the film.
+    thickness: f64,
+    // Index of refraction behind the interface, towards the film.
+    eta: f64,
+    aperture_radius: f64,
+}
+
+impl LensElement {
+    // The aperture stop is written as a flat interface.
+    fn is_stop(&self) -> bool {
+        self.curvature_radius == 0.0
+    }
+
+    // The distance along `ray` to the interface whose vertex sits at `z`, with the
+    // surface normal facing back along the ray; the stop has no normal.
+    fn intersect(&self, z: f64, ray: &Ray) -> Option<(f64, Option<Vec3>)> {
+        let d = ray.direction();
+        if self.is_stop() {
+            let t = (z - ray.origin().z()) / d.z();
+            return (t >= 0.0).then_some((t, None));
+        }
+
+        let radius = self.curvature_radius;
+        let oc = ray.origin() - &Point3::new_with(0.0, 0.0, z + radius);
+        let a = d.len_squared();
+        let b = 2.0 * oc.dot(d);
+        let c = oc.len_squared() - radius * radius;
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let root = discriminant.sqrt();
+        // The lens surface is the near side of the sphere when the ray travels from
+        // its open side, and the far side otherwise.
+        let t = if (d.z() > 0.0) ^ (radius < 0.0) {
+            (-b - root) / (2.0 * a)
+        } else {
+            (-b + root) / (2.0 * a)
+        };
+        if t < 0.0 {
+            return None;
+        }
+
+        let normal = (&oc + &(t * d)).unit_vector();
+        let normal = if normal.dot(d) > 0.0 {
+            -&normal
+        } else {
+            normal
+        };
+        Some((t, Some(normal)))
+    }
+}
+
+// The cardinal points of the lens seen from one side: the principal and focal planes.
+struct CardinalPoints {
+    principal: f64,
+    focal: f64,
+}
+
+struct Focus {
+    focus_dist: f64,
+    // The prescription with the back focal distance set for the focus distance.
+    elements: Vec<LensElement>,
+    focal_length: f64,
+    film_radius: f64,
+    // Bounded on the first camera ray, as tessellation only needs the focal length.
+    exit_pupils: OnceCell<Vec<PupilBounds>>,
+}
+
+impl Focus {
+    fn exit_pupils(&self) -> &[PupilBounds] {
+        self.exit_pupils.get_or_init(|| {
+            (0..PUPIL_SEGMENTS)
+                .map(|i| {
+                    let r0 = i as f64 / PUPIL_SEGMENTS as f64 * self.film_radius;
+                    let r1 = (i + 1) as f64 / PUPIL_SEGMENTS as f64 * self.film_radius;
+                    bound_exit_pupil(&self.elements, r0, r1)
+                })
+                .collect()
+        })
+    }
+}
+
+// A camera that traces rays from the film through a real lens design, in the manner
+// of pbrt's realistic camera. Elements run from the front of the lens to the film, and
+// rays are sent towards the rear element through bounds on its exit pupil, so the
+// vignetting, distortion and bokeh are those of the design. Focuses at the camera's
+// focus distance when first used, and again whenever it changes.
+pub struct RealisticLens {
+    elements: Vec<LensElement>,
+    film_diagonal: f64,
+    focus: RefCell<Option<Rc<Focus>>>,
+}
+
+impl RealisticLens {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<RealisticLens> {
+        let bytes = fs::read(path)?;
+        RealisticLens::parse(&String::from_utf8_lossy(&bytes))
+    }
+
+    // One interface per line: curvature radius, thickness, index of refraction and
+    // aperture diameter, in millimetres. A radius of zero marks the aperture stop.
+    pub fn parse(text: &str) -> io::Result<RealisticLens> {
+        let mut elements = Vec::new();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let values = line
+                .split_whitespace()
+                .map(|token| {
+                    token
+                        .parse::<f64>()
+                        .map_err(|_| invalid_data("malformed number in lens prescription"))
+                })
+                .collect::<io::Result<Vec<f64>>>()?;
+            let [curvature_radius, thickness, eta, aperture_diameter] = values[..] else {
+                return Err(invalid_data("lens prescription lines need four values"));
+            };
+            elements.push(LensElement {
+                curvature_radius: curvature_radius * MILLIMETRES,
+                thickness: thickness * MILLIMETRES,
+                eta: if eta == 0.0 { 1.0 } else { eta },
+                aperture_radius: aperture_diameter * MILLIMETRES / 2.0,
+            });
+        }
+
+        if elements.is_empty() {
+            return Err(invalid_data("lens prescription has no elements"));
+        }
+        Ok(RealisticLens {
+            elements,
+            film_diagonal: 35.0 * MILLIMETRES,
+            focus: RefCell::new(None),
+        })
+    }
+
+    pub fn with_film_diagonal(mut self, millimetres: f64) -> RealisticLens {
+        self.film_diagonal = millimetres * MILLIMETRES;
+        self
+    }
+
+    // Opens or closes the aperture stop, where the design has one.
+    pub fn with_aperture_diameter(mut self, millimetres: f64) -> RealisticLens {
+        if let Some(stop) = self.elements.iter_mut().find(|element| element.is_stop()) {
+            stop.aperture_radius = millimetres * MILLIMETRES / 2.0;
+        }
+        self
+    }
+
+    fn focus(&self, view: &View) -> Rc<Focus> {
+        if let Some(focus) = self.focus.borrow().as_ref() {
+            if focus.focus_dist == view.focus_dist {
+                return Rc::clone(focus);
+            }
+        }
+        let focus = Rc::new(self.focused_at(view.focus_dist));
+        *self.focus.borrow_mut() = Some(Rc::clone(&focus));
+        focus
+    }
+
+    fn focused_at(&self, focus_dist: f64) -> Focus {
+        let elements = self.refocused(focus_dist);
+        Focus {
+            focus_dist,
+            focal_length: self.focal_length(&elements),
+            elements,
+            film_radius: self.film_diagonal / 2.0,
+            exit_pupils: OnceCell::new(),
+        }
+    }
+
+    // The prescription with the film moved so that the thick lens images the focus
+    // plane onto it; lenses that cannot focus that close keep their own spacing.
+    fn refocused(&self, focus_dist: f64) -> Vec<LensElement> {
+        let mut elements = self.elements.clone();
+        if let Some((front, rear)) = self.cardinal_points(&elements) {
+            let f = front.focal - front.principal;
+            let z = -focus_dist;
+            let c = (rear.principal - z - front.principal)
+                * (rear.principal - z - 4.0 * f - front.principal);
+            if c >= 0.0 {
+                let delta = 0.5 * (rear.principal - z + front.principal - c.sqrt());
+                elements.last_mut().unwrap().thickness += delta;
+            }
+        }
+
+        elements
+    }
+
+    fn focal_length(&self, elements: &[LensElement]) -> f64 {
+        self.cardinal_points(elements)
+            .map_or(self.film_diagonal, |(front, _)| {
+                (front.focal - front.principal).abs()
+            })
+    }
+
+    // Traces rays parallel to the axis through the lens from each side to find where
+    // it behaves like a thick lens.
+    fn cardinal_points(
+        &self,
+        elements: &[LensElement],
+    ) -> Option<(CardinalPoints, CardinalPoints)> {
+        let x = 0.001 * self.film_diagonal;
+        let from_scene = Ray::new(
+            Point3::new_with(x, 0.0, front_z(elements) + 1.0),
+            Vec3::new_with(0.0, 0.0, -1.0),
+            0.0,
+        );
+        let front = cardinal_points_of(&from_scene, &trace_from_scene(elements, &from_scene)?);
+        let from_film = Ray::new(
+            Point3::new_with(x, 0.0, rear_z(elements) - 1.0),
+            Vec3::new_with(0.0, 0.0, 1.0),
+            0.0,
+        );
+        let rear = cardinal_points_of(&from_film, &trace_from_film(elements, &from_film)?);
+        Some((front, rear))
+    }
+
+    fn film_extent(&self, view: &View) -> (f64, f64) {
+        let width =
+            self.film_diagonal / (1.0 + 1.0 / (view.aspect_ratio * view.aspect_ratio)).sqrt();
+        (width, width / view.aspect_ratio)
+    }
+}
+
+impl Projection for RealisticLens {
+    fn generate(&self, view: &View, x: f64, y: f64) -> Option<CameraRay> {
+        let focus = self.focus(view);
+        let (width, height) = self.film_extent(view);
+        // The lens forms an inverted image, so the film is flipped both ways.
+        let film = Point3::new_with(-(x - 0.5) * width, (y - 0.5) * height, 0.0);
+
+        let r_film = film.x().hypot(film.y());
+        let segment = (r_film / (self.film_diagonal / 2.0) * PUPIL_SEGMENTS as f64) as usize;
+        let exit_pupils = focus.exit_pupils();
+        let pupil = &exit_pupils[segment.min(PUPIL_SEGMENTS - 1)];
+        let mut rng = rand::thread_rng();
+        let (px, py) = pupil.lerp(rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0));
+        // Pupil bounds are found along +x; rotate them round to the film point.
+        let (sin, cos) = if r_film > 0.0 {
+            (film.y() / r_film, film.x() / r_film)
+        } else {
+            (0.0, 1.0)
+        };
+        let rear = Point3::new_with(
+            cos * px - sin * py,
+            sin * px + cos * py,
+            rear_z(&focus.elements),
+        );
+
+        let film_ray = Ray::new(film.clone(), &rear - &film, 0.0);
+        let out = trace_from_film(&focus.elements, &film_ray)?;
+        let cos_theta = film_ray.direction().unit_vector().z();
+        // Weighted relative to the centre of the film, which a closed rear opening
+        // leaves no light to compare against.
+        let on_axis = exit_pupils[0].area();
+        if on_axis <= 0.0 {
+            return None;
+        }
+        let weight = cos_theta.powi(4) * pupil.area() / on_axis;
+
+        // Lenses are traced looking down +z, and the camera looks down -z.
+        let origin = Point3::new_with(out.origin().x(), out.origin().y(), -out.origin().z());
+        let direction = Vec3::new_with(
+            out.direction().x(),
+            out.direction().y(),
+            -out.direction().z(),
+        );
+        Some(CameraRay::new(origin, direction.unit_vector()).with_weight(weight))
+    }
+
+    fn pixel_angle(&self, view: &View, image_height: f64) -> f64 {
+        let (_, height) = self.film_extent(view);
+        height / self.focus(view).focal_length / image_height
+    }
+}
+
+// An axis-aligned rectangle on the plane of the rear element.
+struct PupilBounds {
+    min: (f64, f64),
+    max: (f64, f64),
+}
+
+impl PupilBounds {
+    fn point(x: f64, y: f64) -> PupilBounds {
+        PupilBounds {
+            min: (x, y),
+            max: (x, y),
+        }
+    }
+
+    fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.min.0 && x <= self.max.0 && y >= self.min.1 && y <= self.max.1
+    }
+
+    fn include(&mut self, x: f64, y: f64) {
+        self.min = (self.min.0.min(x), self.min.1.min(y));
+        self.max = (self.max.0.max(x), self.max.1.max(y));
+    }
+
+    fn expanded(&self, delta: f64) -> PupilBounds {
+        PupilBounds {
+            min: (self.min.0 - delta, self.min.1 - delta),
+            max: (self.max.0 + delta, self.max.1 + delta),
+        }
+    }
+
+    fn area(&self) -> f64 {
+        (self.max.0 - self.min.0) * (self.max.1 - self.min.1)
+    }
+
+    fn lerp(&self, u: f64, v: f64) -> (f64, f64) {
+        (
+            self.min.0 + u * (self.max.0 - self.min.0),
+            self.min.1 + v * (self.max.1 - self.min.1),
+        )
+    }
+}
+
+// Bounds the points on the rear element through which light from film points between
+// radii `r0` and `r1` along +x makes it out of the front of the lens.
+fn bound_exit_pupil(elements: &[LensElement], r0: f64, r1: f64) -> PupilBounds {
+    let extent = 1.5 * elements[elements.len() - 1].aperture_radius;
+    let rear = PupilBounds {
+        min: (-extent, -extent),
+        max: (extent, extent),
+    };
+    let z = rear_z(elements);
+
+    let mut rng = rand::thread_rng();
+    let mut bounds: Option<PupilBounds> = None;
+    for i in 0..PUPIL_SAMPLES {
+        let film = Point3::new_with(
+            r0 + (i as f64 + 0.5) / PUPIL_SAMPLES as f64 * (r1 - r0),
+            0.0,
+            0.0,
+        );
+        let (x, y) = rear.lerp(rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0));
+        if bounds.as_ref().is_some_and(|bounds| bounds.contains(x, y)) {
+            continue;
+        }
+        let target = Point3::new_with(x, y, z);
+        if trace_from_film(elements, &Ray::new(film.clone(), &target - &film, 0.0)).is_some() {
+            match &mut bounds {
+                Some(bounds) => bounds.include(x, y),
+                None => bounds = Some(PupilBounds::point(x, y)),
+            }
+        }
+    }
+
+    // Pad by the sample spacing so that thin slivers of the pupil are not cut off.
+    let spacing = 2.0 * 2.0_f64.sqrt() * extent / (PUPIL_SAMPLES as f64).sqrt();
+    bounds.map_or(rear, |bounds| bounds.expanded(2.0 * spacing))
+}
+
+fn cardinal_points_of(ray_in: &Ray, ray_out: &Ray) -> CardinalPoints {
+    let t_focal = -ray_out.origin().x() / ray_out.direction().x();
+    let t_principal = (ray_in.origin().x() - ray_out.origin().x()) / ray_out.direction().x();
+    CardinalPoints {
+        principal: -ray_out.at(t_principal).z(),
+        focal: -ray_out.at(t_focal).z(),
+    }
+}
+
+fn front_z(elements: &[LensElement]) -> f64 {
+    elements.iter().map(|element| element.thickness).sum()
+}
+
+fn rear_z(elements: &[LensElement]) -> f64 {
+    elements[elements.len() - 1].thickness
+}
+
+// Traces a ray in camera space from the film out through the front of the lens.
+fn trace_from_film(elements: &[LensElement], ray: &Ray) -> Option<Ray> {
+    let mut ray = flip_z(ray);
+    let mut element_z = 0.0;
+    for (i, element) in elements.iter().enumerate().rev() {
+        element_z -= element.thickness;
+        let eta_t = if i > 0 { elements[i - 1].eta } else { 1.0 };
+        ray = cross_interface(element, element_z, &ray, element.eta / eta_t)?;
+    }
+    Some(flip_z(&ray))
+}
+
+// Traces a ray in camera space from the scene in through the lens to the film.
+fn trace_from_scene(elements: &[LensElement], ray: &Ray) -> Option<Ray> {
+    let mut ray = flip_z(ray);
+    let mut element_z = -front_z(elements);
+    for (i, element) in elements.iter().enumerate() {
+        let eta_i = if i > 0 { elements[i - 1].eta } else { 1.0 };
+        ray = cross_interface(element, element_z, &ray, eta_i / element.eta)?;
+        element_z += element.thickness;
+    }
+    Some(flip_z(&ray))
+}
+
+// Carries a lens-space ray across one interface, or None where the element's rim
+// blocks it or it is totally internally reflected.
+fn cross_interface(element: &LensElement, z: f64, ray: &Ray, eta: f64) -> Option<Ray> {
+    let (t, normal) = element.intersect(z, ray)?;
+    let p = ray.at(t);
+    if p.x() * p.x() + p.y() * p.y() > element.aperture_radius * element.aperture_radius {
+        return None;
+    }
+    let direction = match normal {
+        None => ray.direction().clone(),
+        Some(normal) => {
+            let d = ray.direction().unit_vector();
+            let cos_i = -d.dot(&normal);
+            if eta * eta * (1.0 - cos_i * cos_i) >= 1.0 {
+                return None;
+            }
+            d.refract(&normal, eta)
+        }
+    };
+    Some(Ray::new(p, direction, 0.0))
+}
+
+// Lens space has the scene towards -z, opposite to the film-to-scene camera space.
+fn flip_z(ray: &Ray) -> Ray {
+    let (o, d) = (ray.origin(), ray.direction());
+    Ray::new(
+        Point3::new_with(o.x(), o.y(), -o.z()),
+        Vec3::new_with(d.x(), d.y(), -d.z()),
+        0.0,
+    )
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aperture::Aperture;
+
+    // The double-Gauss design of pbrt's scenes, scaled to a 50mm focal length.
+    const DGAUSS_50MM: &str = "
+        # radius  thickness  eta  aperture
+        29.475   3.76   1.67   25.2
+        84.83    0.12   1      25.2
+        19.275   4.025  1.67   23
+        40.77    3.275  1.699  23
+        12.75    5.705  1      18
+        0        4.5    0      17.1
+        -14.495  1.18   1.603  17
+        40.77    6.065  1.658  20
+        -20.385  0.19   1      20
+        437.065  3.22   1.717  20
+        -39.73   0      1      20
+    ";
+
+    fn view(aperture: &Aperture, focus_dist: f64) -> View<'_> {
+        View {
+            vfov: 40.0,
+            aspect_ratio: 1.5,
+            focus_dist,
+            defocus_angle: 0.0,
+            aperture,
+            cat_eye: 0.0,
+        }
+    }
+
+    #[test]
+    fn parses_prescription_with_its_focal_length() {
+        let lens = RealisticLens::parse(DGAUSS_50MM).unwrap();
+        assert_eq!(lens.elements.len(), 11);
+        assert!(lens.elements[5].is_stop());
+        assert_eq!(lens.elements[5].eta, 1.0);
+
+        let focal_length = lens.focus(&view(&Aperture::Circle, 10.0)).focal_length / MILLIMETRES;
+        assert!((focal_length - 50.0).abs() < 1.0, "{focal_length}mm");
+    }
+
+    #[test]
+    fn rejects_malformed_prescriptions() {
+        assert!(RealisticLens::parse("").is_err());
+        assert!(RealisticLens::parse("29.475 3.76 1.67").is_err());
+        assert!(RealisticLens::parse("29.475 3.76 glass 25.2").is_err());
+    }
+
+    // Focusing closer moves the film away from the lens, and the lens should follow
+    // the camera's focus distance however often it changes.
+    #[test]
+    fn refocuses_when_focus_distance_changes() {
+        let lens = RealisticLens::parse(DGAUSS_50MM).unwrap();
+        let aperture = Aperture::Circle;
+        let film_distance = |focus_dist| {
+            let focus = lens.focus(&view(&aperture, focus_dist));
+            assert_eq!(focus.focus_dist, focus_dist);
+            focus.elements.last().unwrap().thickness
+        };
+        let far = film_distance(10.0);
+        let near = film_distance(1.0);
+        assert!(near > far);
+        assert_eq!(film_distance(10.0), far);
+    }
+
+    #[test]
+    fn closed_rear_stops_let_no_rays_through() {
+        let lens = RealisticLens::parse("50 5 1.5 20\n-50 2 1 20\n0 40 0 10")
+            .unwrap()
+            .with_aperture_diameter(0.0);
+        let aperture = Aperture::Circle;
+        let view = view(&aperture, 10.0);
+        for (x, y) in [(0.5, 0.5), (0.2, 0.7)] {
+            assert!(lens.generate(&view, x, y).is_none());
+        }
+    }
+}
@@ -0,0 +1,657 @@
+use std::{cell::OnceCell, rc::Rc};
+
+use crate::{
+    aabb::Aabb,
+    color::Color,
+    hittable::Hittable,
+    ies::IesProfile,
+    light_sampler::{LightBounds, LightSampler, LightSelection},
+    onb::Onb,
+    utils,
+    vec3::{Point3, Vec3},
+};
+
+pub trait Light {
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64;
+    fn random(&self, origin: &Point3) -> Vec3;
+
+    // For choosing between many lights; unknown lights are treated as being at infinity.
+    fn light_bounds(&self) -> LightBounds {
+        LightBounds::infinite()
+    }
+
+    // Whether the light reaches surfaces of the named object; unnamed objects are
+    // `None`.
+    #[allow(unused_variables)]
+    fn illuminates(&self, object: Option<&str>) -> bool {
+        true
+    }
+
+    // Whether the named object blocks the light.
+    #[allow(unused_variables)]
+    fn shadowed_by(&self, object: Option<&str>) -> bool {
+        true
+    }
+
+    // The object in the scene whose emission the light stands for, when the light is
+    // linked. Its emission is then only gathered by sampling the light, where the links
+    // can be followed.
+    fn linked_emitter(&self) -> Option<&str> {
+        None
+    }
+}
+
+// Samples an emitting object of the scene, which the world shares with the light. It
+// is weighed against other lights by what the object's material emits.
+pub struct AreaLight {
+    shape: Rc<dyn Hittable>,
+}
+
+impl AreaLight {
+    pub fn new(shape: Rc<dyn Hittable>) -> AreaLight {
+        AreaLight { shape }
+    }
+}
+
+impl Light for AreaLight {
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.shape.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        self.shape.random(origin)
+    }
+
+    fn light_bounds(&self) -> LightBounds {
+        match self.shape.bounding_box() {
+            Some(bounds) => {
+                let power =
+                    utils::PI * self.shape.area() * self.shape.average_emission().luminance();
+                LightBounds::omnidirectional(bounds, power)
+            }
+            None => LightBounds::infinite(),
+        }
+    }
+}
+
+// Incident light from a punctual light at a shading point.
+pub struct LightSample {
+    pub direction: Vec3,
+    pub distance: f64,
+    pub radiance: Color,
+}
+
+// A light with no surface, which rays can never hit. It is only reached by sampling it
+// explicitly from a shading point and tracing a shadow ray.
+pub trait PunctualLight {
+    fn sample(&self, p: &Point3) -> Option<LightSample>;
+
+    fn light_bounds(&self) -> LightBounds;
+
+    // Whether the light reaches surfaces of the named object; unnamed objects are
+    // `None`.
+    #[allow(unused_variables)]
+    fn illuminates(&self, object: Option<&str>) -> bool {
+        true
+    }
+
+    // Whether the named object blocks the light.
+    #[allow(unused_variables)]
+    fn shadowed_by(&self, object: Option<&str>) -> bool {
+        true
+    }
+}
+
+// A set of objects picked by name.
+pub enum LinkSet {
+    All,
+    Include(Vec<String>),
+    Exclude(Vec<String>),
+}
+
+impl LinkSet {
+    pub fn include(names: &[&str]) -> LinkSet {
+        LinkSet::Include(names.iter().map(|name| name.to_string()).collect())
+    }
+
+    pub fn exclude(names: &[&str]) -> LinkSet {
+        LinkSet::Exclude(names.iter().map(|name| name.to_string()).collect())
+    }
+
+    pub fn contains(&self, object: Option<&str>) -> bool {
+        let listed =
+            |names: &[String]| object.is_some_and(|object| names.iter().any(|name| name == object));
+        match self {
+            LinkSet::All => true,
+            LinkSet::Include(names) => listed(names),
+            LinkSet::Exclude(names) => !listed(names),
+        }
+    }
+}
+
+// A named light restricted to the objects it illuminates and the objects that shadow
+// it, such as a key light that only lights the product and passes through the set. An
+// area light takes the name of the object whose emission it samples.
+pub struct LinkedLight<L: ?Sized> {
+    name: String,
+    light: Box<L>,
+    illumination: LinkSet,
+    shadowing: LinkSet,
+}
+
+impl<L: ?Sized> LinkedLight<L> {
+    pub fn new(name: &str, light: Box<L>) -> LinkedLight<L> {
+        LinkedLight {
+            name: name.to_string(),
+            light,
+            illumination: LinkSet::All,
+            shadowing: LinkSet::All,
+        }
+    }
+
+    pub fn with_illumination(mut self, illumination: LinkSet) -> LinkedLight<L> {
+        self.illumination = illumination;
+        self
+    }
+
+    pub fn with_shadowing(mut self, shadowing: LinkSet) -> LinkedLight<L> {
+        self.shadowing = shadowing;
+        self
+    }
+}
+
+impl<L: PunctualLight + ?Sized> PunctualLight for LinkedLight<L> {
+    fn sample(&self, p: &Point3) -> Option<LightSample> {
+        self.light.sample(p)
+    }
+
+    fn light_bounds(&self) -> LightBounds {
+        self.light.light_bounds()
+    }
+
+    fn illuminates(&self, object: Option<&str>) -> bool {
+        self.illumination.contains(object) && self.light.illuminates(object)
+    }
+
+    fn shadowed_by(&self, object: Option<&str>) -> bool {
+        self.shadowing.contains(object) && self.light.shadowed_by(object)
+    }
+}
+
+impl<L: Light + ?Sized> Light for LinkedLight<L> {
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.light.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        self.light.random(origin)
+    }
+
+    fn light_bounds(&self) -> LightBounds {
+        self.light.light_bounds()
+    }
+
+    fn illuminates(&self, object: Option<&str>) -> bool {
+        self.illumination.contains(object) && self.light.illuminates(object)
+    }
+
+    fn shadowed_by(&self, object: Option<&str>) -> bool {
+        self.shadowing.contains(object) && self.light.shadowed_by(object)
+    }
+
+    fn linked_emitter(&self) -> Option<&str> {
+        Some(&self.name)
+    }
+}
+
+// An IES profile placed in the scene. `nadir` is the direction of vertical angle 0 and
+// `zero_azimuth` that of horizontal angle 0 around it.
+struct Photometry {
+    profile: Rc<IesProfile>,
+    frame: Onb,
+}
+
+impl Photometry {
+    fn new(profile: Rc<IesProfile>, nadir: &Vec3, zero_azimuth: &Vec3) -> Photometry {
+        Photometry {
+            profile,
+            frame: Onb::with_reference(nadir, zero_azimuth),
+        }
+    }
+
+    fn candela(&self, emitted: &Vec3) -> f64 {
+        let local = self.frame.to_local(emitted);
+        let vertical = local.z().clamp(-1.0, 1.0).acos().to_degrees();
+        // Counter-clockwise seen from above is clockwise about the nadir.
+        let horizontal = (-local.y()).atan2(local.x()).to_degrees();
+        self.profile.candela(vertical, horizontal)
+    }
+}
+
+fn peak_candela(photometry: &Option<Photometry>) -> f64 {
+    photometry
+        .as_ref()
+        .map_or(1.0, |photometry| photometry.profile.max_candela())
+}
+
+pub struct PointLight {
+    position: Point3,
+    intensity: Color,
+    photometry: Option<Photometry>,
+}
+
+impl PointLight {
+    pub fn new(position: &Point3, intensity: &Color) -> PointLight {
+        PointLight {
+            position: position.clone(),
+            intensity: intensity.clone(),
+            photometry: None,
+        }
+    }
+
+    // Shapes the light by a measured distribution, whose candela values are then
+    // scaled by the intensity.
+    pub fn with_profile(
+        mut self,
+        profile: Rc<IesProfile>,
+        nadir: &Vec3,
+        zero_azimuth: &Vec3,
+    ) -> PointLight {
+        self.photometry = Some(Photometry::new(profile, nadir, zero_azimuth));
+        self
+    }
+}
+
+impl PunctualLight for PointLight {
+    fn sample(&self, p: &Point3) -> Option<LightSample> {
+        let sample = inverse_square(&self.position, p, &self.intensity)?;
+        profiled(sample, &self.photometry)
+    }
+
+    fn light_bounds(&self) -> LightBounds {
+        let power = 4.0 * utils::PI * self.intensity.luminance() * peak_candela(&self.photometry);
+        LightBounds::omnidirectional(Aabb::new(&self.position, &self.position), power)
+    }
+}
+
+// A point light restricted to a cone, fading out smoothly between `falloff_start` and
+// `cone_angle`, both measured in degrees from the axis.
+pub struct SpotLight {
+    position: Point3,
+    axis: Vec3,
+    intensity: Color,
+    cos_cone: f64,
+    cos_falloff_start: f64,
+    photometry: Option<Photometry>,
+}
+
+impl SpotLight {
+    pub fn new(
+        position: &Point3,
+        target: &Point3,
+        intensity: &Color,
+        cone_angle: f64,
+        falloff_start: f64,
+    ) -> SpotLight {
+        let cone_angle = cone_angle.clamp(0.0, 180.0);
+        SpotLight {
+            position: position.clone(),
+            axis: (target - position).unit_vector(),
+            intensity: intensity.clone(),
+            cos_cone: utils::degrees_to_radians(cone_angle).cos(),
+            cos_falloff_start: utils::degrees_to_radians(falloff_start.clamp(0.0, cone_angle))
+                .cos(),
+            photometry: None,
+        }
+    }
+
+    pub fn with_profile(
+        mut self,
+        profile: Rc<IesProfile>,
+        nadir: &Vec3,
+        zero_azimuth: &Vec3,
+    ) -> SpotLight {
+        self.photometry = Some(Photometry::new(profile, nadir, zero_azimuth));
+        self
+    }
+
+    fn falloff(&self, cos_theta: f64) -> f64 {
+        if cos_theta >= self.cos_falloff_start {
+            return 1.0;
+        }
+        let t = ((cos_theta - self.cos_cone) / (self.cos_falloff_start - self.cos_cone))
+            .clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl PunctualLight for SpotLight {
+    fn sample(&self, p: &Point3) -> Option<LightSample> {
+        let mut sample = inverse_square(&self.position, p, &self.intensity)?;
+        let falloff = self.falloff((-&sample.direction).dot(&self.axis));
+        if falloff <= 0.0 {
+            return None;
+        }
+        sample.radiance = falloff * &sample.radiance;
+        profiled(sample, &self.photometry)
+    }
+
+    // The full cone lies within the falloff start, with the smooth falloff spreading a
+    // little further.
+    fn light_bounds(&self) -> LightBounds {
+        let solid_angle = 2.0
+            * utils::PI
+            * ((1.0 - self.cos_falloff_start) + (self.cos_falloff_start - self.cos_cone) / 2.0);
+        let power = solid_angle * self.intensity.luminance() * peak_candela(&self.photometry);
+        let theta_o = self.cos_falloff_start.clamp(-1.0, 1.0).acos();
+        let theta_e = self.cos_cone.clamp(-1.0, 1.0).acos() - theta_o;
+        LightBounds::new(
+            Aabb::new(&self.position, &self.position),
+            power,
+            &self.axis,
+            self.cos_falloff_start,
+            theta_e.cos(),
+        )
+    }
+}
+
+// A light at infinity, such as the sun, shining along `direction` with the given
+// irradiance on a surface facing it.
+pub struct DirectionalLight {
+    direction: Vec3,
+    irradiance: Color,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: &Vec3, irradiance: &Color) -> DirectionalLight {
+        DirectionalLight {
+            direction: direction.unit_vector(),
+            irradiance: irradiance.clone(),
+        }
+    }
+}
+
+impl PunctualLight for DirectionalLight {
+    fn sample(&self, _p: &Point3) -> Option<LightSample> {
+        Some(LightSample {
+            direction: -&self.direction,
+            distance: utils::INFINITY,
+            radiance: self.irradiance.clone(),
+        })
+    }
+
+    fn light_bounds(&self) -> LightBounds {
+        LightBounds::infinite()
+    }
+}
+
+fn profiled(mut sample: LightSample, photometry: &Option<Photometry>) -> Option<LightSample> {
+    if let Some(photometry) = photometry {
+        let candela = photometry.candela(&(-&sample.direction));
+        if candela <= 0.0 {
+            return None;
+        }
+        sample.radiance = candela * &sample.radiance;
+    }
+    Some(sample)
+}
+
+fn inverse_square(position: &Point3, p: &Point3, intensity: &Color) -> Option<LightSample> {
+    let offset = position - p;
+    let distance_squared = offset.len_squared();
+    if distance_squared <= 0.0 {
+        return None;
+    }
+    let distance = distance_squared.sqrt();
+    Some(LightSample {
+        direction: &offset / distance,
+        distance,
+        radiance: intensity / distance_squared,
+    })
+}
+
+pub struct LightList {
+    lights: Vec<Box<dyn Light>>,
+    punctual: Vec<Box<dyn PunctualLight>>,
+    // The area lights standing for a linked emitter.
+    linked: Vec<usize>,
+    selection: LightSelection,
+    // Built on first use, once every light has been added.
+    area_sampler: OnceCell<LightSampler>,
+    punctual_sampler: OnceCell<LightSampler>,
+}
+
+impl LightList {
+    pub fn new() -> LightList {
+        LightList {
+            lights: Vec::new(),
+            punctual: Vec::new(),
+            linked: Vec::new(),
+            selection: LightSelection::All,
+            area_sampler: OnceCell::new(),
+            punctual_sampler: OnceCell::new(),
+        }
+    }
+
+    pub fn with_selection(mut self, selection: LightSelection) -> LightList {
+        self.selection = selection;
+        self.invalidate();
+        self
+    }
+
+    pub fn add(&mut self, light: Box<dyn Light>) {
+        if light.linked_emitter().is_some() {
+            self.linked.push(self.lights.len());
+        }
+        self.lights.push(light);
+        self.invalidate();
+    }
+
+    pub fn add_punctual(&mut self, light: Box<dyn PunctualLight>) {
+        self.punctual.push(light);
+        self.invalidate();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    pub fn has_linked(&self) -> bool {
+        !self.linked.is_empty()
+    }
+
+    // The linked area light standing for the named object's emission, if there is one.
+    pub fn linked(&self, object: Option<&str>) -> Option<&dyn Light> {
+        let object = object?;
+        self.linked
+            .iter()
+            .map(|&index| self.lights[index].as_ref())
+            .find(|light| light.linked_emitter() == Some(object))
+    }
+
+    // The punctual lights to connect to from `p`, on a surface facing `n` if there is
+    // one, each with the weight that makes the estimate unbiased.
+    pub fn select_punctual(&self, p: &Point3, n: Option<&Vec3>) -> Vec<(&dyn PunctualLight, f64)> {
+        if self.selection == LightSelection::All {
+            return self
+                .punctual
+                .iter()
+                .map(|light| (light.as_ref(), 1.0))
+                .collect();
+        }
+        let sampler = self.punctual_sampler.get_or_init(|| {
+            let bounds: Vec<LightBounds> = self
+                .punctual
+                .iter()
+                .map(|light| light.light_bounds())
+                .collect();
+            LightSampler::new(self.selection, &bounds)
+        });
+        match sampler.sample(p, n) {
+            Some((index, pmf)) if pmf > 0.0 => vec![(self.punctual[index].as_ref(), 1.0 / pmf)],
+            _ => Vec::new(),
+        }
+    }
+
+    // A unit direction from `p`, on a surface facing `n` if there is one, towards a
+    // point on one of the area lights, with the density of `area_pdf` for it.
+    pub fn sample_area(&self, p: &Point3, n: Option<&Vec3>) -> Option<(Vec3, f64)> {
+        let (index, _) = self.area_sampler().sample(p, n)?;
+        let direction = self.lights[index].random(p).unit_vector();
+        let pdf = self.area_pdf(p, n, &direction);
+        Some((direction, pdf))
+    }
+
+    // The density of `sample_area` picking `direction`, over every light that could
+    // have been picked towards it.
+    pub fn area_pdf(&self, p: &Point3, n: Option<&Vec3>, direction: &Vec3) -> f64 {
+        let sampler = self.area_sampler();
+        self.lights
+            .iter()
+            .enumerate()
+            .map(|(index, light)| {
+                let pdf = light.pdf_value(p, direction);
+                if pdf > 0.0 {
+                    sampler.pmf(p, n, index) * pdf
+                } else {
+                    0.0
+                }
+            })
+            .sum()
+    }
+
+    fn area_sampler(&self) -> &LightSampler {
+        self.area_sampler.get_or_init(|| {
+            let bounds: Vec<LightBounds> = self
+                .lights
+                .iter()
+                .map(|light| light.light_bounds())
+                .collect();
+            LightSampler::new(self.selection, &bounds)
+        })
+    }
+
+    fn invalidate(&mut self) {
+        self.area_sampler = OnceCell::new();
+        self.punctual_sampler = OnceCell::new();
+    }
+}
+
+impl Light for LightList {
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.area_pdf(origin, None, direction)
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        match self.sample_area(origin, None) {
+            Some((direction, _)) => direction,
+            None => Vec3::new_with(1.0, 0.0, 0.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::{LightDiffuser, Power},
+        sphere::Sphere,
+    };
+
+    fn lamp(center: &Point3, material: LightDiffuser) -> AreaLight {
+        AreaLight::new(Rc::new(Sphere::new(
+            center,
+            center,
+            0.5,
+            0.0,
+            0.0,
+            Box::new(material),
+        )))
+    }
+
+    #[test]
+    fn area_lights_are_weighed_by_what_their_shapes_emit() {
+        let bright = lamp(
+            &Point3::new_with(-2.0, 0.0, 0.0),
+            LightDiffuser::new(&Color::new_with(3.0, 3.0, 3.0)),
+        );
+        let dim = lamp(
+            &Point3::new_with(2.0, 0.0, 0.0),
+            LightDiffuser::new(&Color::new_with(1.0, 1.0, 1.0)),
+        );
+        let sampler = LightSampler::new(
+            LightSelection::Power,
+            &[bright.light_bounds(), dim.light_bounds()],
+        );
+        let p = Point3::new();
+        assert!((sampler.pmf(&p, None, 0) - 0.75).abs() < 1e-12);
+
+        // An emitter given by its power weighs in with that power.
+        let rated = lamp(
+            &Point3::new(),
+            LightDiffuser::new(&Color::new_with(0.2, 0.9, 0.4))
+                .with_power(Power::Lumens(500.0))
+                .with_two_sided(false),
+        );
+        let sampler = LightSampler::new(
+            LightSelection::Power,
+            &[rated.light_bounds(), dim.light_bounds()],
+        );
+        let dim_power = utils::PI * dim.shape.area();
+        let expected = 500.0 / (500.0 + dim_power);
+        assert!((sampler.pmf(&p, None, 0) - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn point_lights_fall_off_with_the_square_of_distance() {
+        let light = PointLight::new(
+            &Point3::new_with(0.0, 4.0, 0.0),
+            &Color::new_with(8.0, 4.0, 2.0),
+        );
+        let sample = light.sample(&Point3::new_with(0.0, 2.0, 0.0)).unwrap();
+        assert_eq!(sample.distance, 2.0);
+        assert!((sample.direction.y() - 1.0).abs() < 1e-12);
+        assert_eq!(sample.radiance.x(), 2.0);
+        assert_eq!(sample.radiance.z(), 0.5);
+
+        let farther = light.sample(&Point3::new_with(0.0, 0.0, 0.0)).unwrap();
+        assert_eq!(farther.radiance.x(), 0.5);
+        assert!(light.sample(&Point3::new_with(0.0, 4.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn spot_lights_fade_between_their_falloff_and_cone() {
+        let light = SpotLight::new(
+            &Point3::new_with(0.0, 1.0, 0.0),
+            &Point3::new(),
+            &Color::new_with(1.0, 1.0, 1.0),
+            40.0,
+            20.0,
+        );
+        let at_angle = |degrees: f64| {
+            let theta = utils::degrees_to_radians(degrees);
+            let p = Point3::new_with(theta.sin(), 1.0 - theta.cos(), 0.0);
+            light.sample(&p).map_or(0.0, |sample| sample.radiance.y())
+        };
+        assert!((at_angle(0.0) - 1.0).abs() < 1e-12);
+        assert!((at_angle(19.0) - 1.0).abs() < 1e-12);
+        let fading = at_angle(30.0);
+        assert!(fading > 0.0 && fading < 1.0, "{fading}");
+        assert!(at_angle(35.0) < fading);
+        assert_eq!(at_angle(41.0), 0.0);
+    }
+
+    #[test]
+    fn directional_lights_shine_evenly_from_infinity() {
+        let light = DirectionalLight::new(
+            &Vec3::new_with(0.0, -2.0, 0.0),
+            &Color::new_with(3.0, 3.0, 3.0),
+        );
+        for p in [Point3::new(), Point3::new_with(100.0, -50.0, 7.0)] {
+            let sample = light.sample(&p).unwrap();
+            assert!((sample.direction.y() - 1.0).abs() < 1e-12);
+            assert_eq!(sample.distance, utils::INFINITY);
+            assert_eq!(sample.radiance.y(), 3.0);
+        }
+    }
+}
@@ -0,0 +1,447 @@
+use rand::Rng;
+
+use crate::{
+    aabb::Aabb,
+    utils,
+    vec3::{Point3, Vec3},
+};
+
+// How lights are chosen at each scattering vertex.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LightSelection {
+    // Every punctual light is connected, and one area light is picked uniformly, which
+    // suits scenes with a handful of lights.
+    All,
+    // One light, picked in proportion to its power.
+    Power,
+    // One light, picked by its estimated contribution at the shading point through a
+    // light BVH (Conty Estevez and Kulla 2018).
+    Bvh,
+}
+
+// Where a light emits from, how strongly, and in which directions. Emission leaves
+// the bounds within `theta_o` of `axis`, spreading a further `theta_e` around each
+// emitting direction.
+#[derive(Clone)]
+pub struct LightBounds {
+    // None for lights at infinity, which have no position to be importance sampled by.
+    bounds: Option<Aabb>,
+    power: f64,
+    axis: Vec3,
+    cos_theta_o: f64,
+    cos_theta_e: f64,
+}
+
+impl LightBounds {
+    pub fn new(
+        bounds: Aabb,
+        power: f64,
+        axis: &Vec3,
+        cos_theta_o: f64,
+        cos_theta_e: f64,
+    ) -> LightBounds {
+        LightBounds {
+            bounds: Some(bounds),
+            power: power.max(0.0),
+            axis: axis.unit_vector(),
+            cos_theta_o,
+            cos_theta_e,
+        }
+    }
+
+    pub fn omnidirectional(bounds: Aabb, power: f64) -> LightBounds {
+        LightBounds::new(bounds, power, &Vec3::new_with(0.0, 0.0, 1.0), -1.0, 0.0)
+    }
+
+    pub fn infinite() -> LightBounds {
+        LightBounds {
+            bounds: None,
+            power: 0.0,
+            axis: Vec3::new_with(0.0, 0.0, 1.0),
+            cos_theta_o: -1.0,
+            cos_theta_e: 0.0,
+        }
+    }
+
+    fn union(a: &LightBounds, b: &LightBounds) -> LightBounds {
+        if a.power == 0.0 {
+            return b.clone();
+        }
+        if b.power == 0.0 {
+            return a.clone();
+        }
+        let bounds = match (&a.bounds, &b.bounds) {
+            (Some(a), Some(b)) => Some(Aabb::surrounding(a, b)),
+            _ => None,
+        };
+        let (axis, cos_theta_o) = union_cones(&a.axis, a.cos_theta_o, &b.axis, b.cos_theta_o);
+        LightBounds {
+            bounds,
+            power: a.power + b.power,
+            axis,
+            cos_theta_o,
+            cos_theta_e: a.cos_theta_e.min(b.cos_theta_e),
+        }
+    }
+
+    // A conservative estimate of the light reaching `p`, whose surface faces along `n`
+    // where there is one. Follows the bound of pbrt-v4.
+    fn importance(&self, p: &Point3, n: Option<&Vec3>) -> f64 {
+        let Some(bounds) = &self.bounds else {
+            return 0.0;
+        };
+        if self.power == 0.0 {
+            return 0.0;
+        }
+
+        let center = bounds.centroid();
+        let radius = (bounds.max() - &center).len();
+        let offset = p - &center;
+        let distance_squared = offset.len_squared();
+        let to_p = if distance_squared > 0.0 {
+            &offset / distance_squared.sqrt()
+        } else {
+            Vec3::new_with(0.0, 0.0, 1.0)
+        };
+
+        // The cone of directions from `p` towards the bounds.
+        let (sin_theta_b, cos_theta_b) = if distance_squared < radius.powi(2) {
+            (0.0, -1.0)
+        } else {
+            let sin2_theta_b = radius.powi(2) / distance_squared;
+            (sin2_theta_b.sqrt(), (1.0 - sin2_theta_b).sqrt())
+        };
+
+        // The smallest angle between an emitted direction and the direction to `p`.
+        let cos_theta_w = self.axis.dot(&to_p);
+        let sin_theta_w = (1.0 - cos_theta_w.powi(2)).max(0.0).sqrt();
+        let sin_theta_o = (1.0 - self.cos_theta_o.powi(2)).max(0.0).sqrt();
+        let cos_theta_x = cos_sub_clamped(sin_theta_w, cos_theta_w, sin_theta_o, self.cos_theta_o);
+        let sin_theta_x = sin_sub_clamped(sin_theta_w, cos_theta_w, sin_theta_o, self.cos_theta_o);
+        let cos_theta_p = cos_sub_clamped(sin_theta_x, cos_theta_x, sin_theta_b, cos_theta_b);
+        if cos_theta_p <= self.cos_theta_e {
+            return 0.0;
+        }
+
+        let mut importance = self.power * cos_theta_p / distance_squared.max(radius).max(1e-8);
+        if let Some(n) = n {
+            let cos_theta_i = to_p.dot(n).abs();
+            let sin_theta_i = (1.0 - cos_theta_i.powi(2)).max(0.0).sqrt();
+            importance *= cos_sub_clamped(sin_theta_i, cos_theta_i, sin_theta_b, cos_theta_b);
+        }
+        importance.max(0.0)
+    }
+}
+
+// cos(max(0, a - b)) and sin(max(0, a - b)) from the sines and cosines of a and b.
+fn cos_sub_clamped(sin_a: f64, cos_a: f64, sin_b: f64, cos_b: f64) -> f64 {
+    if cos_a > cos_b {
+        1.0
+    } else {
+        cos_a * cos_b + sin_a * sin_b
+    }
+}
+
+fn sin_sub_clamped(sin_a: f64, cos_a: f64, sin_b: f64, cos_b: f64) -> f64 {
+    if cos_a > cos_b {
+        0.0
+    } else {
+        sin_a * cos_b - cos_a * sin_b
+    }
+}
+
+// The smallest cone around two cones of directions.
+fn union_cones(a: &Vec3, cos_a: f64, b: &Vec3, cos_b: f64) -> (Vec3, f64) {
+    let theta_a = cos_a.clamp(-1.0, 1.0).acos();
+    let theta_b = cos_b.clamp(-1.0, 1.0).acos();
+    let theta_d = a.dot(b).clamp(-1.0, 1.0).acos();
+    if (theta_d + theta_b).min(utils::PI) <= theta_a {
+        return (a.clone(), cos_a);
+    }
+    if (theta_d + theta_a).min(utils::PI) <= theta_b {
+        return (b.clone(), cos_b);
+    }
+
+    let theta_o = (theta_a + theta_d + theta_b) / 2.0;
+    let rotation_axis = a.cross(b);
+    if theta_o >= utils::PI || rotation_axis.is_near_zero() {
+        return (a.clone(), -1.0);
+    }
+
+    // Rotate `a` towards `b` so the new cone just touches the far side of `a`.
+    let k = rotation_axis.unit_vector();
+    let theta_r = theta_o - theta_a;
+    let axis = &(theta_r.cos() * a) + &(theta_r.sin() * &k.cross(a));
+    (axis.unit_vector(), theta_o.cos())
+}
+
+// Samples indices in constant time from a fixed discrete distribution (Vose 1991).
+pub struct AliasTable {
+    probability: Vec<f64>,
+    alias: Vec<usize>,
+    pmf: Vec<f64>,
+}
+
+impl AliasTable {
+    pub fn new(weights: &[f64]) -> AliasTable {
+        let count = weights.len();
+        let total: f64 = weights.iter().sum();
+        let pmf: Vec<f64> = if total > 0.0 {
+            weights.iter().map(|weight| weight / total).collect()
+        } else {
+            vec![1.0 / count as f64; count]
+        };
+
+        let mut scaled: Vec<f64> = pmf.iter().map(|p| p * count as f64).collect();
+        let (mut small, mut large): (Vec<usize>, Vec<usize>) =
+            (0..count).partition(|&i| scaled[i] < 1.0);
+        let mut probability = vec![1.0; count];
+        let mut alias: Vec<usize> = (0..count).collect();
+        // Whatever is left over once either list runs out is 1 up to rounding.
+        while let (Some(&s), Some(&l)) = (small.last(), large.last()) {
+            small.pop();
+            large.pop();
+            probability[s] = scaled[s];
+            alias[s] = l;
+            scaled[l] += scaled[s] - 1.0;
+            if scaled[l] < 1.0 {
+                small.push(l);
+            } else {
+                large.push(l);
+            }
+        }
+
+        AliasTable {
+            probability,
+            alias,
+            pmf,
+        }
+    }
+
+    pub fn sample(&self) -> (usize, f64) {
+        let mut rng = rand::thread_rng();
+        let i = rng.gen_range(0..self.pmf.len());
+        let index = if rng.gen_range(0.0..1.0) < self.probability[i] {
+            i
+        } else {
+            self.alias[i]
+        };
+        (index, self.pmf[index])
+    }
+}
+
+enum Slot {
+    Infinite,
+    // Position among the bounded lights, and the branches taken to reach the light's
+    // leaf in the BVH, one bit per level from the root.
+    Bounded { position: usize, trail: u64 },
+}
+
+enum LightNode {
+    Leaf { bounds: LightBounds, light: usize },
+    // The left child directly follows its parent.
+    Inner { bounds: LightBounds, right: usize },
+}
+
+impl LightNode {
+    fn bounds(&self) -> &LightBounds {
+        match self {
+            LightNode::Leaf { bounds, .. } | LightNode::Inner { bounds, .. } => bounds,
+        }
+    }
+}
+
+// Chooses one light out of many. Lights at infinity cannot be ranked against the
+// others, so they are chosen uniformly as if they were one more light beside all the
+// bounded ones together.
+pub struct LightSampler {
+    selection: LightSelection,
+    slots: Vec<Slot>,
+    infinite: Vec<usize>,
+    bounded: Vec<usize>,
+    alias: Option<AliasTable>,
+    nodes: Vec<LightNode>,
+}
+
+impl LightSampler {
+    pub fn new(selection: LightSelection, lights: &[LightBounds]) -> LightSampler {
+        let mut sampler = LightSampler {
+            selection,
+            slots: Vec::with_capacity(lights.len()),
+            infinite: Vec::new(),
+            bounded: Vec::new(),
+            alias: None,
+            nodes: Vec::new(),
+        };
+        for (light, bounds) in lights.iter().enumerate() {
+            if bounds.bounds.is_some() {
+                sampler.slots.push(Slot::Bounded {
+                    position: sampler.bounded.len(),
+                    trail: 0,
+                });
+                sampler.bounded.push(light);
+            } else {
+                sampler.slots.push(Slot::Infinite);
+                sampler.infinite.push(light);
+            }
+        }
+
+        if sampler.bounded.is_empty() {
+            return sampler;
+        }
+        match selection {
+            LightSelection::All => {}
+            LightSelection::Power => {
+                let powers: Vec<f64> = sampler.bounded.iter().map(|&i| lights[i].power).collect();
+                sampler.alias = Some(AliasTable::new(&powers));
+            }
+            LightSelection::Bvh => {
+                let mut items: Vec<usize> = sampler.bounded.clone();
+                sampler.build(lights, &mut items, 0, 0);
+            }
+        }
+        sampler
+    }
+
+    fn build(&mut self, lights: &[LightBounds], items: &mut [usize], trail: u64, depth: u32) {
+        if let [light] = items {
+            if let Slot::Bounded { trail: slot, .. } = &mut self.slots[*light] {
+                *slot = trail;
+            }
+            self.nodes.push(LightNode::Leaf {
+                bounds: lights[*light].clone(),
+                light: *light,
+            });
+            return;
+        }
+
+        let centroid = |light: usize| lights[light].bounds.as_ref().map(Aabb::centroid);
+        let centroids = items
+            .iter()
+            .filter_map(|&light| centroid(light))
+            .map(|c| Aabb::new(&c, &c))
+            .reduce(|a, b| Aabb::surrounding(&a, &b));
+        if let Some(centroids) = centroids {
+            let axis = centroids.longest_axis();
+            let key = |light: usize| centroid(light).map_or(0.0, |c| c[axis]);
+            items.sort_by(|&a, &b| key(a).total_cmp(&key(b)));
+        }
+        let bounds = items
+            .iter()
+            .skip(1)
+            .fold(lights[items[0]].clone(), |bounds, &light| {
+                LightBounds::union(&bounds, &lights[light])
+            });
+
+        let index = self.nodes.len();
+        self.nodes.push(LightNode::Inner { bounds, right: 0 });
+        let (left, right) = items.split_at_mut(items.len() / 2);
+        self.build(lights, left, trail, depth + 1);
+        let right_child = self.nodes.len();
+        self.build(lights, right, trail | (1 << depth), depth + 1);
+        if let LightNode::Inner { right, .. } = &mut self.nodes[index] {
+            *right = right_child;
+        }
+    }
+
+    fn infinite_probability(&self) -> f64 {
+        let infinite = self.infinite.len() as f64;
+        let bounded = if self.bounded.is_empty() { 0.0 } else { 1.0 };
+        infinite / (infinite + bounded)
+    }
+
+    // Picks a light for the shading point `p` with normal `n`, returning its index and
+    // the probability of having picked it.
+    pub fn sample(&self, p: &Point3, n: Option<&Vec3>) -> Option<(usize, f64)> {
+        let count = self.slots.len();
+        if count == 0 {
+            return None;
+        }
+        let mut rng = rand::thread_rng();
+        if self.selection == LightSelection::All {
+            return Some((rng.gen_range(0..count), 1.0 / count as f64));
+        }
+
+        let p_infinite = self.infinite_probability();
+        if rng.gen_range(0.0..1.0) < p_infinite {
+            let light = self.infinite[rng.gen_range(0..self.infinite.len())];
+            return Some((light, p_infinite / self.infinite.len() as f64));
+        }
+
+        let (light, pmf) = match &self.alias {
+            Some(alias) => {
+                let (position, pmf) = alias.sample();
+                (self.bounded[position], pmf)
+            }
+            None => self.traverse(p, n),
+        };
+        Some((light, (1.0 - p_infinite) * pmf))
+    }
+
+    // The probability that `sample` picks `light` at the same shading point.
+    pub fn pmf(&self, p: &Point3, n: Option<&Vec3>, light: usize) -> f64 {
+        if self.selection == LightSelection::All {
+            return 1.0 / self.slots.len() as f64;
+        }
+
+        let p_infinite = self.infinite_probability();
+        let (position, trail) = match self.slots[light] {
+            Slot::Infinite => return p_infinite / self.infinite.len() as f64,
+            Slot::Bounded { position, trail } => (position, trail),
+        };
+        let pmf = match &self.alias {
+            Some(alias) => alias.pmf[position],
+            None => {
+                let mut pmf = 1.0;
+                let mut index = 0;
+                let mut depth = 0;
+                while let LightNode::Inner { right, .. } = self.nodes[index] {
+                    let weights = self.child_weights(p, n, index + 1, right);
+                    let branch = ((trail >> depth) & 1) as usize;
+                    pmf *= weights[branch];
+                    index = if branch == 0 { index + 1 } else { right };
+                    depth += 1;
+                }
+                pmf
+            }
+        };
+        (1.0 - p_infinite) * pmf
+    }
+
+    fn traverse(&self, p: &Point3, n: Option<&Vec3>) -> (usize, f64) {
+        let mut rng = rand::thread_rng();
+        let mut pmf = 1.0;
+        let mut index = 0;
+        loop {
+            match self.nodes[index] {
+                LightNode::Leaf { light, .. } => return (light, pmf),
+                LightNode::Inner { right, .. } => {
+                    let weights = self.child_weights(p, n, index + 1, right);
+                    if rng.gen_range(0.0..1.0) < weights[0] {
+                        pmf *= weights[0];
+                        index += 1;
+                    } else {
+                        pmf *= weights[1];
+                        index = right;
+                    }
+                }
+            }
+        }
+    }
+
+    // Probabilities of descending into either child. Where neither child seems to
+    // reach the shading point, fall back on power and then on a coin flip, so that
+    // every light keeps a chance of being picked.
+    fn child_weights(&self, p: &Point3, n: Option<&Vec3>, left: usize, right: usize) -> [f64; 2] {
+        let (left, right) = (self.nodes[left].bounds(), self.nodes[right].bounds());
+        let mut weights = [left.importance(p, n), right.importance(p, n)];
+        if weights[0] + weights[1] <= 0.0 {
+            weights = [left.power, right.power];
+        }
+        let total = weights[0] + weights[1];
+        if total <= 0.0 {
+            return [0.5, 0.5];
+        }
+        [weights[0] / total, weights[1] / total]
+    }
+}
@@ -1,12 +1,40 @@
+mod aabb;
+mod aperture;
+mod blackbody;
+mod bump;
+mod bvh;
 mod camera;
+mod coated;
 mod color;
+mod fog;
 mod hittable;
 mod hittable_list;
+mod ies;
+mod image;
+mod lens;
+mod light;
+mod light_sampler;
 mod material;
+mod medium;
+mod mesh;
+mod object;
+mod microfacet;
+mod onb;
+mod perlin;
+mod principled;
+mod projection;
 mod ray;
+mod scenes;
+mod spectrum;
 mod sphere;
+mod stereo;
+mod texture;
+mod thin_film;
 mod utils;
 mod vec3;
+mod volume;
+
+use std::{io, rc::Rc};
 
 use material::{Dielectric, LightDiffuser};
 use rand::Rng;
@@ -15,12 +43,37 @@ use crate::{
     camera::Camera,
     color::Color,
     hittable_list::HittableList,
+    light::{AreaLight, LightList},
     material::{LambertianMaterial, Metal, Smoke},
     sphere::Sphere,
     vec3::{Point3, Vec3},
 };
 
-fn main() {
+// Renders the scene named by the first argument, the spheres by default, as a PPM
+// image on standard output. The other scenes show off features one area at a time;
+// see `scenes` for their arguments.
+fn main() -> io::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let arg = |i: usize| args.get(i).copied();
+    match arg(0) {
+        None | Some("spheres") => {
+            spheres();
+            Ok(())
+        }
+        Some("materials") => scenes::materials(arg(1), arg(2)),
+        Some("lights") => scenes::lights(arg(1), arg(2)),
+        Some("volumes") => scenes::volumes(arg(1)),
+        Some("meshes") => scenes::meshes(arg(1)),
+        Some("cameras") => scenes::cameras(arg(1), args.get(2..).unwrap_or_default()),
+        Some(scene) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unknown scene {scene}; try spheres, materials, lights, volumes, meshes or cameras"),
+        )),
+    }
+}
+
+fn spheres() {
     let mut world = HittableList::new();
 
     let material_of_the_ground = LambertianMaterial::new(&Color::new_with(0.5, 0.5, 0.5));
@@ -57,8 +110,8 @@ fn main() {
                     )));
                 } else if chosen_material < 0.90 {
                     let albedo = &Color::random_within(0.5, 1.0);
-                    let fuzz = rng.gen_range(0.0..0.5);
-                    let sphere_material = Box::new(Metal::new(&albedo, fuzz));
+                    let roughness = rng.gen_range(0.0..0.5);
+                    let sphere_material = Box::new(Metal::new(albedo, roughness));
                     world.add(Box::new(Sphere::new(
                         &center_start,
                         &center_start,
@@ -95,7 +148,7 @@ fn main() {
     let left_ball_material = LightDiffuser::new(&Color::new_with(0.9, 0.9, 0.9));
     let right_ball_material = Metal::new(&Color::new_with(0.8, 0.6, 0.2), 0.0);
     let middle_ball_material = Dielectric::new(1.50);
-    let middle_ball_component_material = Dielectric::new(1.0 / 1.50);
+    let middle_ball_component_material = Dielectric::new(1.0).with_priority(1);
 
     world.add(Box::new(Sphere::new(
         &Point3::new_with(0.0, 1.0, 0.0),
@@ -130,6 +183,16 @@ fn main() {
         Box::new(right_ball_material),
     )));
 
+    let mut lights = LightList::new();
+    lights.add(Box::new(AreaLight::new(Rc::new(Sphere::new(
+        &Point3::new_with(0.0, 1.0, 0.0),
+        &Point3::new_with(0.0, 1.0, 0.0),
+        1.0,
+        0.0,
+        0.0,
+        Box::new(LambertianMaterial::new(&Color::new())),
+    )))));
+
     let mut camera = Camera::new();
     camera.image_width = 240;
     camera.samples_per_pixel = 50;
@@ -142,5 +205,5 @@ fn main() {
     camera.focus_dist = 10.0;
     camera.start_time = 0.0;
     camera.end_time = 1.0;
-    camera.render(&mut world);
+    camera.render(&world, &lights);
 }
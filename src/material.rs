@@ -1,7 +1,19 @@
-use crate::{color::Color, hittable::HitRecord, ray::Ray, vec3::Vec3};
-use rand::Rng;
+use std::rc::Rc;
 
-use std::{thread, time};
+use crate::{
+    blackbody::{self, Normalization},
+    color::Color,
+    hittable::HitRecord,
+    medium::Interior,
+    microfacet::{self, TrowbridgeReitz},
+    ray::Ray,
+    spectrum::RefractiveIndex,
+    texture::{SolidColor, Texture},
+    thin_film::ThinFilm,
+    utils,
+    vec3::{Point3, Vec3},
+};
+use rand::Rng;
 
 pub trait Material {
     #[allow(unused_variables)]
@@ -9,10 +21,70 @@ pub trait Material {
         None
     }
 
+    // The BSDF times |cos| towards `direction`, for connecting to lights that can only
+    // be sampled explicitly. Materials that scatter in discrete directions have none.
+    #[allow(unused_variables)]
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        Color::new()
+    }
+
+    // The solid-angle density with which `scatter` picks `direction`, for weighing it
+    // against sampling the lights. None where the material scatters in discrete
+    // directions at this hit, whichever direction is asked about.
+    #[allow(unused_variables)]
+    fn pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Option<f64> {
+        None
+    }
+
     #[allow(unused_variables)]
     fn emit(&self, ray_in: &Ray, rec: &HitRecord) -> Color {
         Color::new()
     }
+
+    fn interior(&self) -> Option<Interior> {
+        None
+    }
+
+    // Whether the material scatters each wavelength differently in a way RGB cannot
+    // carry, as dispersion and thin-film interference do. Spectral paths then go on
+    // with their hero wavelength alone.
+    fn is_dispersive(&self) -> bool {
+        self.interior()
+            .is_some_and(|interior| interior.refraction_index.is_dispersive())
+    }
+
+    // Told about the surface of the shape that owns the material once the shape is
+    // built, for emitters whose output is given as a total power.
+    #[allow(unused_variables)]
+    fn set_emitter_surface(&mut self, surface: &EmitterSurface) {}
+
+    // The radiance `emit` gives averaged over the owning shape's surface, as far as the
+    // material has been told about it.
+    fn average_emission(&self) -> Color {
+        Color::new()
+    }
+
+    // Whether a hit on this material counts. Cut-out materials reject hits where they
+    // are transparent, and the hit test moves on to the next intersection.
+    #[allow(unused_variables)]
+    fn alpha_test(&self, rec: &HitRecord) -> bool {
+        true
+    }
+}
+
+// The surface of a shape as an emitter sees it: its area, and points spread over it
+// with the share of the area each stands for, so that emission textures can be
+// averaged over the surface.
+pub struct EmitterSurface {
+    pub area: f64,
+    pub samples: Vec<SurfaceSample>,
+}
+
+pub struct SurfaceSample {
+    pub u: f64,
+    pub v: f64,
+    pub p: Point3,
+    pub weight: f64,
 }
 
 pub struct LambertianMaterial {
@@ -39,39 +111,356 @@ impl Material for LambertianMaterial {
         let attenuation = self.albedo.clone();
         Some((attenuation, scattered))
     }
+
+    fn eval(&self, _ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let cos_theta = direction.unit_vector().dot(&rec.normal);
+        if cos_theta <= 0.0 {
+            return Color::new();
+        }
+        (cos_theta / utils::PI) * &self.albedo
+    }
+
+    fn pdf(&self, _ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Option<f64> {
+        let cos_theta = direction.unit_vector().dot(&rec.normal);
+        Some(cos_theta.max(0.0) / utils::PI)
+    }
+}
+
+enum MetalFresnel {
+    Schlick(Color),
+    Conductor { eta: Color, k: Color },
 }
 
 pub struct Metal {
-    albedo: Color,
-    fuzz: f64,
+    fresnel: MetalFresnel,
+    distribution: TrowbridgeReitz,
+    thin_film: Option<ThinFilm>,
 }
 
 impl Metal {
-    pub fn new(albedo: &Color, fuzz: f64) -> Metal {
+    pub fn new(albedo: &Color, roughness: f64) -> Metal {
         Metal {
-            albedo: albedo.clone(),
-            fuzz: fuzz.min(1.0),
+            fresnel: MetalFresnel::Schlick(albedo.clone()),
+            distribution: TrowbridgeReitz::from_roughness(roughness, roughness),
+            thin_film: None,
+        }
+    }
+
+    pub fn conductor(eta: &Color, k: &Color, roughness_u: f64, roughness_v: f64) -> Metal {
+        Metal {
+            fresnel: MetalFresnel::Conductor {
+                eta: eta.clone(),
+                k: k.clone(),
+            },
+            distribution: TrowbridgeReitz::from_roughness(roughness_u, roughness_v),
+            thin_film: None,
+        }
+    }
+
+    pub fn gold(roughness: f64) -> Metal {
+        Metal::conductor(
+            &Color::new_with(0.143, 0.374, 1.442),
+            &Color::new_with(3.983, 2.385, 1.603),
+            roughness,
+            roughness,
+        )
+    }
+
+    pub fn copper(roughness: f64) -> Metal {
+        Metal::conductor(
+            &Color::new_with(0.200, 0.924, 1.102),
+            &Color::new_with(3.912, 2.452, 2.142),
+            roughness,
+            roughness,
+        )
+    }
+
+    pub fn aluminium(roughness: f64) -> Metal {
+        Metal::conductor(
+            &Color::new_with(1.657, 0.880, 0.521),
+            &Color::new_with(9.224, 6.270, 4.837),
+            roughness,
+            roughness,
+        )
+    }
+
+    // An oxide or oil layer on the metal; `thickness` is in nanometres.
+    pub fn with_thin_film(mut self, thickness: Rc<dyn Texture>, refraction_index: f64) -> Metal {
+        self.thin_film = Some(ThinFilm::new(thickness, refraction_index));
+        self
+    }
+
+    fn fresnel(&self, rec: &HitRecord, cos_theta: f64) -> Color {
+        if let Some(film) = &self.thin_film {
+            let (eta, k) = match &self.fresnel {
+                MetalFresnel::Schlick(f0) => (schlick_to_eta(f0), Color::new()),
+                MetalFresnel::Conductor { eta, k } => (eta.clone(), k.clone()),
+            };
+            return film.reflectance(rec, cos_theta, rec.outer_refraction_index, &eta, &k);
+        }
+        match &self.fresnel {
+            MetalFresnel::Schlick(f0) => microfacet::schlick(cos_theta, f0),
+            MetalFresnel::Conductor { eta, k } => {
+                microfacet::fresnel_complex_rgb(cos_theta, eta, k)
+            }
         }
     }
 }
 
 impl Material for Metal {
     fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-        let reflected = ray_in.direction().reflect(&rec.normal);
-        let reflected = &reflected.unit_vector() + &(self.fuzz * &Vec3::random_unit_vector());
-        let scattered = Ray::new(rec.p.clone(), reflected, ray_in.time());
-        let attenuation = self.albedo.clone();
+        let uvw = rec.shading_frame();
+        let wo = uvw.to_local(&(-&ray_in.direction().unit_vector()));
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        if self.distribution.is_smooth() {
+            let wi = microfacet::reflect(&wo, &Vec3::new_with(0.0, 0.0, 1.0));
+            let scattered = Ray::new(rec.p.clone(), uvw.transform(&wi), ray_in.time());
+            return Some((self.fresnel(rec, wo.z()), scattered));
+        }
+
+        let wm = self.distribution.sample_wm(&wo);
+        let wi = microfacet::reflect(&wo, &wm);
+        if wi.z() <= 0.0 {
+            return None;
+        }
+
+        let weight = self.distribution.g(&wo, &wi) / self.distribution.g1(&wo);
+        let attenuation = weight * &self.fresnel(rec, wo.dot(&wm));
+        let scattered = Ray::new(rec.p.clone(), uvw.transform(&wi), ray_in.time());
         Some((attenuation, scattered))
     }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        if self.distribution.is_smooth() {
+            return Color::new();
+        }
+        let uvw = rec.shading_frame();
+        let wo = uvw.to_local(&(-&ray_in.direction().unit_vector()));
+        let wi = uvw.to_local(&direction.unit_vector());
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Color::new();
+        }
+
+        let wm = (&wo + &wi).unit_vector();
+        let f = self.distribution.d(&wm) * self.distribution.g(&wo, &wi) / (4.0 * wo.z());
+        f * &self.fresnel(rec, wo.dot(&wm))
+    }
+
+    fn pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Option<f64> {
+        if self.distribution.is_smooth() {
+            return None;
+        }
+        let uvw = rec.shading_frame();
+        let wo = uvw.to_local(&(-&ray_in.direction().unit_vector()));
+        let wi = uvw.to_local(&direction.unit_vector());
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Some(0.0);
+        }
+        let wm = (&wo + &wi).unit_vector();
+        Some(self.distribution.d_visible(&wo, &wm) / (4.0 * wo.dot(&wm)))
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.thin_film.is_some()
+    }
 }
 
 pub struct Dielectric {
-    refraction_index: f64,
+    refraction_index: RefractiveIndex,
+    absorption: Color,
+    scattering: Color,
+    priority: u32,
+    distribution: TrowbridgeReitz,
+    thin_film: Option<ThinFilm>,
 }
 
 impl Dielectric {
     pub fn new(refraction_index: f64) -> Dielectric {
-        Dielectric { refraction_index }
+        Dielectric::from_refraction_index(RefractiveIndex::Constant(refraction_index))
+    }
+
+    pub fn cauchy(a: f64, b: f64) -> Dielectric {
+        Dielectric::from_refraction_index(RefractiveIndex::Cauchy { a, b })
+    }
+
+    pub fn sellmeier(b: [f64; 3], c: [f64; 3]) -> Dielectric {
+        Dielectric::from_refraction_index(RefractiveIndex::Sellmeier { b, c })
+    }
+
+    // A translucent material such as skin, wax, marble or milk: a dielectric boundary
+    // around a volume that scatters with the given single-scattering albedo and mean
+    // free path per channel.
+    pub fn subsurface(refraction_index: f64, albedo: &Color, mean_free_path: &Color) -> Dielectric {
+        let extinction = |mean_free_path: f64| {
+            if mean_free_path > 0.0 {
+                1.0 / mean_free_path
+            } else {
+                0.0
+            }
+        };
+        let extinction = Color::new_with(
+            extinction(mean_free_path.x()),
+            extinction(mean_free_path.y()),
+            extinction(mean_free_path.z()),
+        );
+        let scattering = &extinction * albedo;
+        Dielectric::new(refraction_index)
+            .with_absorption(&(&extinction - &scattering))
+            .with_scattering(&scattering)
+    }
+
+    fn from_refraction_index(refraction_index: RefractiveIndex) -> Dielectric {
+        Dielectric {
+            refraction_index,
+            absorption: Color::new(),
+            scattering: Color::new(),
+            priority: 0,
+            distribution: TrowbridgeReitz::new(0.0, 0.0),
+            thin_film: None,
+        }
+    }
+
+    pub fn with_absorption(mut self, absorption: &Color) -> Dielectric {
+        self.absorption = absorption.clone();
+        self
+    }
+
+    pub fn with_scattering(mut self, scattering: &Color) -> Dielectric {
+        self.scattering = scattering.clone();
+        self
+    }
+
+    // Where dielectrics overlap, the one with the highest priority fills the overlap.
+    pub fn with_priority(mut self, priority: u32) -> Dielectric {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_roughness(mut self, roughness: f64) -> Dielectric {
+        self.distribution = TrowbridgeReitz::from_roughness(roughness, roughness);
+        self
+    }
+
+    // A soap or oil film on the surface; `thickness` is in nanometres.
+    pub fn with_thin_film(
+        mut self,
+        thickness: Rc<dyn Texture>,
+        refraction_index: f64,
+    ) -> Dielectric {
+        self.thin_film = Some(ThinFilm::new(thickness, refraction_index));
+        self
+    }
+
+    // Indices of refraction on the incident and transmitted sides of the hit.
+    fn etas(&self, rec: &HitRecord) -> (f64, f64) {
+        let refraction_index = self.refraction_index.at(rec.wavelength);
+        if rec.front_face {
+            (rec.outer_refraction_index, refraction_index)
+        } else {
+            (refraction_index, rec.outer_refraction_index)
+        }
+    }
+
+    fn fresnel(&self, rec: &HitRecord, cos_theta: f64, eta_i: f64, eta_t: f64) -> Option<Color> {
+        let film = self.thin_film.as_ref()?;
+        let eta_t = Color::new_with(eta_t, eta_t, eta_t);
+        Some(film.reflectance(rec, cos_theta, eta_i, &eta_t, &Color::new()))
+    }
+
+    // Walter et al. (2007) microfacet reflection and transmission, sampled through the
+    // visible normals.
+    fn scatter_rough(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        eta_i: f64,
+        eta_t: f64,
+    ) -> Option<(Color, Ray)> {
+        let uvw = rec.shading_frame();
+        let wo = uvw.to_local(&(-&ray_in.direction().unit_vector()));
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        let eta = eta_t / eta_i;
+        let wm = self.distribution.sample_wm(&wo);
+        let reflectance = self
+            .fresnel(rec, wo.dot(&wm), eta_i, eta_t)
+            .unwrap_or_else(|| grey(microfacet::fresnel_dielectric(wo.dot(&wm), eta)));
+
+        let (reflected, fresnel_weight) = choose_fresnel(&reflectance);
+        let wi = if reflected {
+            let wi = microfacet::reflect(&wo, &wm);
+            if wi.z() <= 0.0 {
+                return None;
+            }
+            wi
+        } else {
+            let wi = microfacet::refract(&wo, &wm, eta)?;
+            if wi.z() >= 0.0 {
+                return None;
+            }
+            wi
+        };
+
+        let weight = self.distribution.g(&wo, &wi) / self.distribution.g1(&wo);
+        let scattered = Ray::new(rec.p.clone(), uvw.transform(&wi), ray_in.time());
+        Some((weight * &fresnel_weight, scattered))
+    }
+
+    // The rough BSDF times |cos| towards `direction`, with the density `scatter_rough`
+    // picks it with. Transmission follows Walter et al., without the eta^2 radiance
+    // scaling that `scatter` leaves out as well.
+    fn eval_rough(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Option<(Color, f64)> {
+        if self.distribution.is_smooth() {
+            return None;
+        }
+        let uvw = rec.shading_frame();
+        let wo = uvw.to_local(&(-&ray_in.direction().unit_vector()));
+        let wi = uvw.to_local(&direction.unit_vector());
+        if wo.z() <= 0.0 || wi.z() == 0.0 {
+            return None;
+        }
+
+        let (eta_i, eta_t) = self.etas(rec);
+        let eta = eta_t / eta_i;
+        let reflected = wi.z() > 0.0;
+        let wm = if reflected {
+            &wo + &wi
+        } else {
+            &(eta * &wi) + &wo
+        }
+        .unit_vector();
+        let wm = if wm.z() < 0.0 { -&wm } else { wm };
+        // Microfacets facing away from either direction cannot connect them.
+        if wm.dot(&wo) <= 0.0 || wm.dot(&wi) * wi.z() <= 0.0 {
+            return None;
+        }
+
+        let reflectance = self
+            .fresnel(rec, wo.dot(&wm), eta_i, eta_t)
+            .unwrap_or_else(|| grey(microfacet::fresnel_dielectric(wo.dot(&wm), eta)));
+        let probability = (reflectance.x() + reflectance.y() + reflectance.z()) / 3.0;
+        let d = self.distribution.d(&wm);
+        let g = self.distribution.g(&wo, &wi);
+        let visible = self.distribution.d_visible(&wo, &wm);
+
+        if reflected {
+            let f = d * g / (4.0 * wo.z());
+            Some((
+                f * &reflectance,
+                probability * visible / (4.0 * wo.dot(&wm)),
+            ))
+        } else {
+            let denominator = (wi.dot(&wm) + wo.dot(&wm) / eta).powi(2);
+            let jacobian = wi.dot(&wm).abs() / denominator;
+            let transmittance = &grey(1.0) - &reflectance;
+            let f = d * g * wo.dot(&wm) * jacobian / wo.z();
+            Some((f * &transmittance, (1.0 - probability) * visible * jacobian))
+        }
     }
 
     pub fn reflectance(cosine: f64, refraction_index: f64) -> f64 {
@@ -83,11 +472,12 @@ impl Dielectric {
 
 impl Material for Dielectric {
     fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-        let ri = if rec.front_face {
-            1.0 / self.refraction_index
-        } else {
-            self.refraction_index
-        };
+        let (eta_i, eta_t) = self.etas(rec);
+        let ri = eta_i / eta_t;
+
+        if !self.distribution.is_smooth() {
+            return self.scatter_rough(ray_in, rec, eta_i, eta_t);
+        }
 
         let unit_direction = ray_in.direction().unit_vector();
 
@@ -95,40 +485,353 @@ impl Material for Dielectric {
         let sin_theta = (1.0 - cos_theta.powi(2)).sqrt();
         let cannot_refract = ri * sin_theta > 1.0;
 
-        let mut rng = rand::thread_rng();
-        let random_number = rng.gen_range(0.0..1.0);
-        let direction = if cannot_refract || Dielectric::reflectance(cos_theta, ri) > random_number
-        {
+        let (reflected, attenuation) = if cannot_refract {
+            (true, Color::new_with(1.0, 1.0, 1.0))
+        } else {
+            let reflectance = self
+                .fresnel(rec, cos_theta, eta_i, eta_t)
+                .unwrap_or_else(|| grey(Dielectric::reflectance(cos_theta, ri)));
+            choose_fresnel(&reflectance)
+        };
+        let direction = if reflected {
             unit_direction.reflect(&rec.normal)
         } else {
             unit_direction.refract(&rec.normal, ri)
         };
 
         let scattered = Ray::new(rec.p.clone(), direction, ray_in.time());
-        let attenuation = Color::new_with(1.0, 1.0, 1.0);
         Some((attenuation, scattered))
     }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        self.eval_rough(ray_in, rec, direction)
+            .map_or_else(Color::new, |(f, _)| f)
+    }
+
+    fn pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Option<f64> {
+        if self.distribution.is_smooth() {
+            return None;
+        }
+        Some(
+            self.eval_rough(ray_in, rec, direction)
+                .map_or(0.0, |(_, pdf)| pdf),
+        )
+    }
+
+    fn interior(&self) -> Option<Interior> {
+        Some(Interior {
+            refraction_index: self.refraction_index.clone(),
+            priority: self.priority,
+            absorption: self.absorption.clone(),
+            scattering: self.scattering.clone(),
+        })
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.thin_film.is_some() || self.refraction_index.is_dispersive()
+    }
+}
+
+// Picks reflection with the average of a possibly coloured reflectance, and weights
+// either choice so that the estimate stays unbiased per channel.
+fn choose_fresnel(reflectance: &Color) -> (bool, Color) {
+    let probability = (reflectance.x() + reflectance.y() + reflectance.z()) / 3.0;
+    let mut rng = rand::thread_rng();
+    if rng.gen_range(0.0..1.0) < probability {
+        (true, reflectance / probability)
+    } else {
+        let transmittance = &Color::new_with(1.0, 1.0, 1.0) - reflectance;
+        (false, &transmittance / (1.0 - probability))
+    }
+}
+
+// The real index whose normal-incidence reflectance is `f0`, per channel.
+fn schlick_to_eta(f0: &Color) -> Color {
+    let eta = |f0: f64| {
+        let r = f0.clamp(0.0, 0.99).sqrt();
+        (1.0 + r) / (1.0 - r)
+    };
+    Color::new_with(eta(f0.x()), eta(f0.y()), eta(f0.z()))
+}
+
+fn grey(value: f64) -> Color {
+    Color::new_with(value, value, value)
+}
+
+// Blends two materials by a mask, e.g. rust over metal or dirt over paint. Each hit
+// picks one of them with the mask as the probability of `other`.
+pub struct MixMaterial {
+    base: Box<dyn Material>,
+    other: Box<dyn Material>,
+    factor: Rc<dyn Texture>,
+}
+
+impl MixMaterial {
+    pub fn new(
+        base: Box<dyn Material>,
+        other: Box<dyn Material>,
+        factor: Rc<dyn Texture>,
+    ) -> MixMaterial {
+        MixMaterial {
+            base,
+            other,
+            factor,
+        }
+    }
+
+    fn factor(&self, rec: &HitRecord) -> f64 {
+        self.factor.value(rec.u, rec.v, &rec.p).x().clamp(0.0, 1.0)
+    }
+
+    // The material a hit uses. The choice hashes where the hit landed rather than
+    // drawing a fresh random number, so that its coverage, scattering and lighting
+    // all come from the same material.
+    fn chosen(&self, rec: &HitRecord) -> &dyn Material {
+        if hash_hit(rec) < self.factor(rec) {
+            self.other.as_ref()
+        } else {
+            self.base.as_ref()
+        }
+    }
+
+    // Both materials are evaluated together where both can be, otherwise the hit's
+    // own choice stands in for the mix, as it does when scattering.
+    fn blends(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> bool {
+        self.base.pdf(ray_in, rec, direction).is_some()
+            && self.other.pdf(ray_in, rec, direction).is_some()
+    }
+}
+
+impl Material for MixMaterial {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        self.chosen(rec).scatter(ray_in, rec)
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        if !self.blends(ray_in, rec, direction) {
+            return self.chosen(rec).eval(ray_in, rec, direction);
+        }
+        let factor = self.factor(rec);
+        &((1.0 - factor) * &self.base.eval(ray_in, rec, direction))
+            + &(factor * &self.other.eval(ray_in, rec, direction))
+    }
+
+    fn pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Option<f64> {
+        if !self.blends(ray_in, rec, direction) {
+            return self.chosen(rec).pdf(ray_in, rec, direction);
+        }
+        let factor = self.factor(rec);
+        let base = self.base.pdf(ray_in, rec, direction)?;
+        let other = self.other.pdf(ray_in, rec, direction)?;
+        Some((1.0 - factor) * base + factor * other)
+    }
+
+    fn emit(&self, ray_in: &Ray, rec: &HitRecord) -> Color {
+        let factor = self.factor(rec);
+        &((1.0 - factor) * &self.base.emit(ray_in, rec)) + &(factor * &self.other.emit(ray_in, rec))
+    }
+
+    fn set_emitter_surface(&mut self, surface: &EmitterSurface) {
+        self.base.set_emitter_surface(surface);
+        self.other.set_emitter_surface(surface);
+    }
+
+    // The mask is not averaged over the surface, so both count for half.
+    fn average_emission(&self) -> Color {
+        0.5 * &(&self.base.average_emission() + &self.other.average_emission())
+    }
+
+    // The interior cannot vary over the surface, so the base material's wins.
+    fn interior(&self) -> Option<Interior> {
+        self.base.interior().or_else(|| self.other.interior())
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.base.is_dispersive() || self.other.is_dispersive()
+    }
+
+    fn alpha_test(&self, rec: &HitRecord) -> bool {
+        self.chosen(rec).alpha_test(rec)
+    }
+}
+
+// A number in [0, 1) that looks random but is the same every time a hit is asked
+// about, from the bits of its position and texture coordinates.
+fn hash_hit(rec: &HitRecord) -> f64 {
+    let mut hash = 0x9e37_79b9_7f4a_7c15_u64;
+    for value in [rec.p.x(), rec.p.y(), rec.p.z(), rec.u, rec.v] {
+        hash ^= value.to_bits();
+        // SplitMix64's finaliser.
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        hash ^= hash >> 31;
+    }
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+// Masks another material by an opacity texture, for leaves, fences and the like.
+// Without a threshold, hits are kept with probability equal to the opacity, so partial
+// coverage comes out right on average; with one, the mask is binary.
+pub struct Cutout {
+    material: Box<dyn Material>,
+    opacity: Rc<dyn Texture>,
+    threshold: Option<f64>,
+}
+
+impl Cutout {
+    pub fn new(material: Box<dyn Material>, opacity: Rc<dyn Texture>) -> Cutout {
+        Cutout {
+            material,
+            opacity,
+            threshold: None,
+        }
+    }
+
+    pub fn with_threshold(mut self, threshold: f64) -> Cutout {
+        self.threshold = Some(threshold);
+        self
+    }
+}
+
+impl Material for Cutout {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        self.material.scatter(ray_in, rec)
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        self.material.eval(ray_in, rec, direction)
+    }
+
+    fn pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Option<f64> {
+        self.material.pdf(ray_in, rec, direction)
+    }
+
+    fn emit(&self, ray_in: &Ray, rec: &HitRecord) -> Color {
+        self.material.emit(ray_in, rec)
+    }
+
+    fn set_emitter_surface(&mut self, surface: &EmitterSurface) {
+        self.material.set_emitter_surface(surface);
+    }
+
+    fn average_emission(&self) -> Color {
+        self.material.average_emission()
+    }
+
+    fn interior(&self) -> Option<Interior> {
+        self.material.interior()
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.material.is_dispersive()
+    }
+
+    fn alpha_test(&self, rec: &HitRecord) -> bool {
+        let opacity = self.opacity.value(rec.u, rec.v, &rec.p).x();
+        match self.threshold {
+            Some(threshold) => opacity >= threshold,
+            None => rand::thread_rng().gen_range(0.0..1.0) < opacity,
+        }
+    }
+}
+
+// Light output of an emitter, spread evenly over its surface.
+#[derive(Clone, Copy)]
+pub enum Power {
+    // Radiant flux, taking the RGB channels as equal parts of the spectrum, each watt
+    // worth the peak luminous efficacy in the units `blackbody` uses.
+    Watts(f64),
+    // Luminous flux, with luminance in cd/m^2 as for `blackbody`.
+    Lumens(f64),
 }
 
 pub struct LightDiffuser {
-    color: Color,
+    emission: Rc<dyn Texture>,
+    scale: f64,
+    two_sided: bool,
+    power: Option<Power>,
+    // The emission texture averaged over the owning shape's surface, before scaling.
+    average: Color,
 }
 
 impl LightDiffuser {
     pub fn new(color: &Color) -> LightDiffuser {
+        LightDiffuser::textured(Rc::new(SolidColor::new(color)))
+    }
+
+    // A black body at `kelvin`, e.g. 2700 K for a warm bulb or 6500 K for daylight.
+    pub fn blackbody(kelvin: f64, normalization: Normalization) -> LightDiffuser {
+        LightDiffuser::new(&blackbody::blackbody_normalized(kelvin, normalization))
+    }
+
+    pub fn textured(emission: Rc<dyn Texture>) -> LightDiffuser {
         LightDiffuser {
-            color: color.clone(),
+            emission,
+            scale: 1.0,
+            two_sided: true,
+            power: None,
+            average: Color::new(),
+        }
+    }
+
+    pub fn with_two_sided(mut self, two_sided: bool) -> LightDiffuser {
+        self.two_sided = two_sided;
+        self
+    }
+
+    // Scales the emission so that the shape the material is given to puts out `power`
+    // over its surface, keeping the tint of the emission. The scale is worked out from
+    // the shape's surface when it is built.
+    pub fn with_power(mut self, power: Power) -> LightDiffuser {
+        self.power = Some(power);
+        self
+    }
+
+    // The scale for a given power, from the emission averaged over the surface.
+    fn power_scale(&self, power: Power, area: f64) -> f64 {
+        let average = &self.average;
+        let (flux, reference) = match power {
+            Power::Watts(watts) => (
+                blackbody::LUMINOUS_EFFICACY * watts,
+                (average.x() + average.y() + average.z()) / 3.0,
+            ),
+            Power::Lumens(lumens) => (lumens, average.luminance()),
+        };
+        // A Lambertian surface of radiance L emits pi * L per unit area and side.
+        let sides = if self.two_sided { 2.0 } else { 1.0 };
+        let radiance = flux / (utils::PI * area * sides);
+        if reference > 0.0 && area > 0.0 {
+            radiance / reference
+        } else {
+            0.0
         }
     }
 }
 
 impl Material for LightDiffuser {
+    fn set_emitter_surface(&mut self, surface: &EmitterSurface) {
+        self.average = Color::new();
+        for sample in surface.samples.iter() {
+            self.average += sample.weight * &self.emission.value(sample.u, sample.v, &sample.p);
+        }
+        if let Some(power) = self.power {
+            self.scale = self.power_scale(power, surface.area);
+        }
+    }
+
+    fn average_emission(&self) -> Color {
+        self.scale * &self.average
+    }
+
     fn scatter(&self, _ray_in: &Ray, _rec: &HitRecord) -> Option<(Color, Ray)> {
         None
     }
 
-    fn emit(&self, _ray_in: &Ray, _rec: &HitRecord) -> Color {
-        self.color.clone()
+    fn emit(&self, _ray_in: &Ray, rec: &HitRecord) -> Color {
+        if !rec.front_face && !self.two_sided {
+            return Color::new();
+        }
+        self.scale * &self.emission.value(rec.u, rec.v, &rec.p)
     }
 }
 
@@ -168,3 +871,223 @@ impl Material for Smoke {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hittable::Hittable, mesh::TriangleMesh, principled::Principled, sphere::Sphere};
+
+    // Each scattered direction's weight should be the BSDF over the density reported
+    // for it, or light sampling would be weighed against the wrong density.
+    #[test]
+    fn pdf_matches_scatter_weights() {
+        let materials: Vec<Box<dyn Material>> = vec![
+            Box::new(LambertianMaterial::new(&Color::new_with(0.5, 0.6, 0.7))),
+            Box::new(Metal::conductor(
+                &Color::new_with(0.2, 0.9, 1.1),
+                &Color::new_with(3.9, 2.4, 2.1),
+                0.2,
+                0.5,
+            )),
+            Box::new(
+                Principled::new(Rc::new(SolidColor::new(&Color::new_with(0.8, 0.3, 0.2))))
+                    .with_metallic(Rc::new(SolidColor::scalar(0.3)))
+                    .with_clearcoat(
+                        Rc::new(SolidColor::scalar(0.5)),
+                        Rc::new(SolidColor::scalar(0.5)),
+                    ),
+            ),
+            Box::new(Dielectric::new(1.5).with_roughness(0.5)),
+        ];
+        for material in materials {
+            let center = Point3::new_with(0.0, 0.0, -3.0);
+            let sphere = Sphere::new(&center, &center, 1.0, 0.0, 0.0, material);
+            let ray = Ray::new(Point3::new(), Vec3::new_with(0.1, 0.2, -1.0), 0.0);
+            let rec = sphere.hits(&ray, 0.001..utils::INFINITY).unwrap();
+
+            for _ in 0..1000 {
+                let Some((attenuation, scattered)) = rec.material.scatter(&ray, &rec) else {
+                    continue;
+                };
+                let direction = scattered.direction();
+                let pdf = rec.material.pdf(&ray, &rec, direction).unwrap();
+                let expected = &rec.material.eval(&ray, &rec, direction) / pdf;
+                assert!((&expected - &attenuation).len() < 1e-6 * attenuation.len().max(1.0));
+            }
+        }
+    }
+
+    // What `eval` integrates to over all directions, reflected and transmitted, is the
+    // energy `scatter` carries on average.
+    #[test]
+    fn rough_glass_eval_accounts_for_the_scattered_energy() {
+        let glass = Dielectric::new(1.5).with_roughness(0.7);
+        let center = Point3::new_with(0.0, 0.0, -3.0);
+        let sphere = Sphere::new(&center, &center, 1.0, 0.0, 0.0, Box::new(glass));
+        let ray = Ray::new(Point3::new(), Vec3::new_with(0.3, 0.1, -1.0), 0.0);
+        let rec = sphere.hits(&ray, 0.001..utils::INFINITY).unwrap();
+
+        let samples = 200_000;
+        let mut scattered = 0.0;
+        let mut evaluated = 0.0;
+        for _ in 0..samples {
+            if let Some((attenuation, _)) = rec.material.scatter(&ray, &rec) {
+                scattered += attenuation.y();
+            }
+            let direction = Vec3::random_unit_vector();
+            evaluated += 4.0 * utils::PI * rec.material.eval(&ray, &rec, &direction).y();
+        }
+        let (scattered, evaluated) = (scattered / samples as f64, evaluated / samples as f64);
+        assert!(scattered <= 1.0 && scattered > 0.8, "{scattered}");
+        assert!(
+            (scattered - evaluated).abs() < 0.05,
+            "{scattered} {evaluated}"
+        );
+    }
+
+    fn mix(base: Box<dyn Material>, other: Box<dyn Material>, factor: f64) -> MixMaterial {
+        MixMaterial::new(base, other, Rc::new(SolidColor::scalar(factor)))
+    }
+
+    #[test]
+    fn mixes_use_one_material_per_hit() {
+        let white = Box::new(LambertianMaterial::new(&Color::new_with(1.0, 1.0, 1.0)));
+        let hole = Cutout::new(
+            Box::new(LambertianMaterial::new(&Color::new())),
+            Rc::new(SolidColor::scalar(0.0)),
+        );
+        let material = mix(white, Box::new(hole), 0.3);
+        let ray = Ray::new(
+            Point3::new_with(0.0, 0.0, 1.0),
+            Vec3::new_with(0.0, 0.0, -1.0),
+            0.0,
+        );
+
+        let hits = 10_000;
+        let mut holes = 0;
+        for i in 0..hits {
+            let p = Point3::new_with(i as f64 * 0.001, 0.5, 0.0);
+            let mut rec = HitRecord::new(p, Vec3::new_with(0.0, 0.0, 1.0), 1.0, &material);
+            rec.front_face = true;
+            let covered = material.alpha_test(&rec);
+            for _ in 0..4 {
+                let (attenuation, _) = material.scatter(&ray, &rec).unwrap();
+                assert_eq!(attenuation.y() > 0.0, covered);
+            }
+            holes += usize::from(!covered);
+        }
+        let fraction = holes as f64 / hits as f64;
+        assert!((fraction - 0.3).abs() < 0.02, "{fraction}");
+    }
+
+    #[test]
+    fn mixes_keep_light_sampling_for_their_diffuse_hits() {
+        let diffuse = || Box::new(LambertianMaterial::new(&Color::new_with(0.5, 0.5, 0.5)));
+        let ray = Ray::new(
+            Point3::new_with(0.0, 0.0, 1.0),
+            Vec3::new_with(0.0, 0.0, -1.0),
+            0.0,
+        );
+        let direction = Vec3::new_with(0.0, 0.6, 0.8);
+        let lambertian = 0.8 / utils::PI;
+
+        // Two continuous materials blend their densities.
+        let rough = Box::new(Metal::new(&Color::new_with(0.9, 0.9, 0.9), 0.6));
+        let blended = mix(diffuse(), rough, 0.25);
+        let rec = HitRecord::new(Point3::new(), Vec3::new_with(0.0, 0.0, 1.0), 1.0, &blended);
+        let metal = Metal::new(&Color::new_with(0.9, 0.9, 0.9), 0.6);
+        let expected = 0.75 * lambertian + 0.25 * metal.pdf(&ray, &rec, &direction).unwrap();
+        let pdf = blended.pdf(&ray, &rec, &direction).unwrap();
+        assert!((pdf - expected).abs() < 1e-12, "{pdf} {expected}");
+
+        // With a mirror in the mix, the hits that are diffuse can still sample lights.
+        let mirror = Box::new(Metal::new(&Color::new_with(0.9, 0.9, 0.9), 0.0));
+        let material = mix(diffuse(), mirror, 0.5);
+        let mut diffuse_hits = 0;
+        for i in 0..1000 {
+            let p = Point3::new_with(i as f64 * 0.01, 0.0, 0.0);
+            let rec = HitRecord::new(p, Vec3::new_with(0.0, 0.0, 1.0), 1.0, &material);
+            if let Some(pdf) = material.pdf(&ray, &rec, &direction) {
+                assert!((pdf - lambertian).abs() < 1e-12);
+                diffuse_hits += 1;
+            }
+        }
+        assert!(diffuse_hits > 400 && diffuse_hits < 600, "{diffuse_hits}");
+    }
+
+    #[test]
+    fn power_is_spread_over_the_owning_shape() {
+        let material = LightDiffuser::new(&Color::new_with(0.2, 0.4, 0.6))
+            .with_power(Power::Watts(100.0))
+            .with_two_sided(false);
+        let center = Point3::new_with(0.0, 0.0, -3.0);
+        let sphere = Sphere::new(&center, &center, 0.5, 0.0, 0.0, Box::new(material));
+
+        let ray = Ray::new(Point3::new(), Vec3::new_with(0.0, 0.0, -1.0), 0.0);
+        let rec = sphere.hits(&ray, 0.001..utils::INFINITY).unwrap();
+        let radiance = rec.material.emit(&ray, &rec);
+        let flux = utils::PI * sphere.area() * (radiance.x() + radiance.y() + radiance.z()) / 3.0;
+        let expected = 100.0 * blackbody::LUMINOUS_EFFICACY;
+        assert!((flux - expected).abs() < 1e-6 * expected, "{flux}");
+        // The tint survives the scaling.
+        assert!((radiance.z() / radiance.x() - 3.0).abs() < 1e-9);
+    }
+
+    // Lights only the half of the surface above a height.
+    struct Above(f64);
+
+    impl Texture for Above {
+        fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+            let lit = if p.y() > self.0 { 1.0 } else { 0.0 };
+            Color::new_with(lit, lit, lit)
+        }
+    }
+
+    #[test]
+    fn power_follows_emission_textures_over_the_surface() {
+        let material = LightDiffuser::textured(Rc::new(Above(0.0)))
+            .with_power(Power::Lumens(100.0))
+            .with_two_sided(false);
+        let sphere = Sphere::new(
+            &Point3::new(),
+            &Point3::new(),
+            0.5,
+            0.0,
+            0.0,
+            Box::new(material),
+        );
+
+        // All the power leaves through the lit half.
+        let ray = Ray::new(
+            Point3::new_with(0.0, 2.0, 0.0),
+            Vec3::new_with(0.0, -1.0, 0.0),
+            0.0,
+        );
+        let rec = sphere.hits(&ray, 0.001..utils::INFINITY).unwrap();
+        let radiance = rec.material.emit(&ray, &rec).luminance();
+        let flux = utils::PI * radiance * sphere.area() / 2.0;
+        assert!((flux - 100.0).abs() < 1.0, "{flux}");
+
+        // The same holds for a mesh, whatever the uvs.
+        let positions = vec![
+            Point3::new_with(-1.0, 1.0, 0.0),
+            Point3::new_with(1.0, 1.0, 0.0),
+            Point3::new_with(1.0, -1.0, 0.0),
+            Point3::new_with(-1.0, -1.0, 0.0),
+        ];
+        let material = LightDiffuser::textured(Rc::new(Above(0.0)))
+            .with_power(Power::Lumens(100.0))
+            .with_two_sided(false);
+        let quad = TriangleMesh::new(positions, vec![[0, 1, 2], [0, 2, 3]], Box::new(material))
+            .with_uvs(vec![(0.0, 0.0); 4]);
+        let ray = Ray::new(
+            Point3::new_with(0.0, 0.5, -1.0),
+            Vec3::new_with(0.0, 0.0, 1.0),
+            0.0,
+        );
+        let rec = quad.hits(&ray, 0.001..utils::INFINITY).unwrap();
+        let radiance = rec.material.emit(&ray, &rec).luminance();
+        let flux = utils::PI * radiance * quad.area() / 2.0;
+        assert!((flux - 100.0).abs() < 1.0, "{flux}");
+    }
+}
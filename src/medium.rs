@@ -0,0 +1,232 @@
+use rand::Rng;
+
+use crate::{
+    color::Color,
+    material::Material,
+    spectrum::{RefractiveIndex, SampledWavelengths},
+};
+
+#[derive(Clone)]
+pub struct Interior {
+    pub refraction_index: RefractiveIndex,
+    pub priority: u32,
+    pub absorption: Color,
+    pub scattering: Color,
+}
+
+impl Interior {
+    // The fraction of light that crosses `distance` of the interior without being
+    // absorbed or scattered.
+    pub fn transmittance(&self, distance: f64, wavelengths: Option<&SampledWavelengths>) -> Color {
+        let extinction = &self.absorption + &self.scattering;
+        let extinction = match wavelengths {
+            Some(wavelengths) => wavelengths.upsample(&extinction),
+            None => extinction,
+        };
+        let channel = |extinction: f64| {
+            if extinction > 0.0 {
+                (-extinction * distance).exp()
+            } else {
+                1.0
+            }
+        };
+        Color::new_with(
+            channel(extinction.x()),
+            channel(extinction.y()),
+            channel(extinction.z()),
+        )
+    }
+
+    pub fn is_scattering(&self) -> bool {
+        self.scattering
+            .x()
+            .max(self.scattering.y())
+            .max(self.scattering.z())
+            > 0.0
+    }
+
+    // Samples where a path travelling up to `max_distance` through the medium scatters
+    // next, if it does, with the weight to apply. The distance is drawn from a
+    // randomly chosen channel's extinction and weighted by the pdf averaged over all
+    // channels, so that media with very different mean free paths per channel stay
+    // unbiased.
+    pub fn sample_free_flight(
+        &self,
+        max_distance: f64,
+        wavelengths: Option<&SampledWavelengths>,
+    ) -> (Option<f64>, Color) {
+        let upsample = |rgb: &Color| match wavelengths {
+            Some(wavelengths) => wavelengths.upsample(rgb),
+            None => rgb.clone(),
+        };
+        let scattering = upsample(&self.scattering);
+        let extinction = &upsample(&self.absorption) + &scattering;
+        let extinction = [extinction.x(), extinction.y(), extinction.z()].map(|e| e.max(0.0));
+
+        let mut rng = rand::thread_rng();
+        let channel = rng.gen_range(0..3);
+        let distance = if extinction[channel] > 0.0 {
+            -(1.0 - rng.gen_range(0.0..1.0_f64)).ln() / extinction[channel]
+        } else {
+            f64::INFINITY
+        };
+
+        let flight = distance.min(max_distance);
+        let transmittance = extinction.map(|e| if e > 0.0 { (-e * flight).exp() } else { 1.0 });
+        let transmittance = Color::new_with(transmittance[0], transmittance[1], transmittance[2]);
+        if distance < max_distance {
+            let pdf = (0..3)
+                .map(|c| extinction[c] * transmittance[c])
+                .sum::<f64>()
+                / 3.0;
+            (Some(distance), &(&scattering * &transmittance) / pdf)
+        } else {
+            let probability = (transmittance.x() + transmittance.y() + transmittance.z()) / 3.0;
+            (None, &transmittance / probability)
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Entry<'a> {
+    material: &'a dyn Material,
+    interior: Interior,
+}
+
+// The closed objects a path is currently inside. The one with the highest priority
+// (the latest entered on ties) defines the medium the path travels through, in the
+// spirit of Schmidt and Budge's priority-based nested dielectrics.
+#[derive(Clone)]
+pub struct MediumStack<'a> {
+    entries: Vec<Entry<'a>>,
+}
+
+impl<'a> MediumStack<'a> {
+    pub fn new() -> MediumStack<'a> {
+        MediumStack {
+            entries: Vec::new(),
+        }
+    }
+
+    fn current_entry(&self) -> Option<&Entry<'a>> {
+        let mut current: Option<&Entry<'a>> = None;
+        for entry in self.entries.iter() {
+            if current.is_none_or(|c| entry.interior.priority >= c.interior.priority) {
+                current = Some(entry);
+            }
+        }
+        current
+    }
+
+    pub fn current(&self) -> Option<&Interior> {
+        self.current_entry().map(|entry| &entry.interior)
+    }
+
+    pub fn refraction_index(&self, wavelength: Option<f64>) -> f64 {
+        self.current()
+            .map_or(1.0, |interior| interior.refraction_index.at(wavelength))
+    }
+
+    // The stack a path arriving at an interface of `material` travelled through. A path
+    // reaching the back of an object it never entered, such as one from a camera inside
+    // it, was inside it all along.
+    pub fn arriving(
+        &self,
+        material: &'a dyn Material,
+        interior: &Interior,
+        front_face: bool,
+    ) -> Option<MediumStack<'a>> {
+        let entered = self
+            .entries
+            .iter()
+            .any(|entry| std::ptr::addr_eq(entry.material, material));
+        if front_face || entered {
+            return None;
+        }
+        Some(self.crossed(material, interior.clone(), true))
+    }
+
+    // The stack on the other side of an interface of `material`, given which side
+    // of it the path arrives from.
+    pub fn crossed(
+        &self,
+        material: &'a dyn Material,
+        interior: Interior,
+        front_face: bool,
+    ) -> MediumStack<'a> {
+        let mut crossed = self.clone();
+        if front_face {
+            crossed.entries.push(Entry { material, interior });
+        } else if let Some(index) = crossed
+            .entries
+            .iter()
+            .rposition(|entry| std::ptr::addr_eq(entry.material, material))
+        {
+            crossed.entries.remove(index);
+        }
+        crossed
+    }
+
+    // Crossing an interface only matters if it changes the winning medium; otherwise
+    // the hit is a false intersection with a lower-priority object.
+    pub fn same_medium_as(&self, other: &MediumStack<'a>) -> bool {
+        match (self.current_entry(), other.current_entry()) {
+            (None, None) => true,
+            (Some(a), Some(b)) => std::ptr::addr_eq(a.material, b.material),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Dielectric;
+
+    #[test]
+    fn absorption_follows_beer_lambert() {
+        let glass = Dielectric::new(1.5).with_absorption(&Color::new_with(0.5, 1.0, 2.0));
+        let interior = glass.interior().unwrap();
+        let transmittance = interior.transmittance(2.0, None);
+        let expected = [(-1.0_f64).exp(), (-2.0_f64).exp(), (-4.0_f64).exp()];
+        let channels = [transmittance.x(), transmittance.y(), transmittance.z()];
+        for (channel, expected) in channels.into_iter().zip(expected) {
+            assert!((channel - expected).abs() < 1e-12, "{channel}");
+        }
+
+        // Clear glass lets everything through however far the light travels.
+        let clear = Dielectric::new(1.5).interior().unwrap();
+        assert_eq!(clear.transmittance(100.0, None).y(), 1.0);
+    }
+
+    #[test]
+    fn lower_priority_interfaces_are_false_intersections() {
+        let glass = Dielectric::new(1.5).with_priority(1);
+        let water = Dielectric::new(1.33);
+        let outside = MediumStack::new();
+        let in_glass = outside.crossed(&glass, glass.interior().unwrap(), true);
+        assert_eq!(in_glass.refraction_index(None), 1.5);
+
+        // Water inside the glass does not change the medium until the glass is left.
+        let in_both = in_glass.crossed(&water, water.interior().unwrap(), true);
+        assert!(in_glass.same_medium_as(&in_both));
+        let in_water = in_both.crossed(&glass, glass.interior().unwrap(), false);
+        assert!(!in_both.same_medium_as(&in_water));
+        assert_eq!(in_water.refraction_index(None), 1.33);
+    }
+
+    #[test]
+    fn leaving_an_object_never_entered_is_a_real_crossing() {
+        let glass = Dielectric::new(1.5);
+        let interior = glass.interior().unwrap();
+        let outside = MediumStack::new();
+        assert!(outside.arriving(&glass, &interior, true).is_none());
+
+        // A camera inside the glass sees its back face first.
+        let inside = outside.arriving(&glass, &interior, false).unwrap();
+        assert_eq!(inside.refraction_index(None), 1.5);
+        let beyond = inside.crossed(&glass, interior.clone(), false);
+        assert!(!inside.same_medium_as(&beyond));
+        assert!(inside.arriving(&glass, &interior, false).is_none());
+    }
+}
@@ -0,0 +1,670 @@
+use std::{collections::HashMap, fs, io, ops::Range, path::Path};
+
+use rand::Rng;
+
+use crate::{
+    aabb::Aabb,
+    bvh::Bvh,
+    color::Color,
+    hittable::{HitRecord, Hittable},
+    light_sampler::AliasTable,
+    material::{EmitterSurface, Material, SurfaceSample},
+    ray::Ray,
+    texture::Texture,
+    utils::{self, Interval},
+    vec3::{Point3, Vec3},
+};
+
+// Refinement passes stop here even if some edges are still too long.
+const MAX_TESSELLATION_PASSES: usize = 16;
+
+// How finely to tessellate a mesh before displacing it.
+pub enum Tessellation {
+    // Split edges until none is longer than this.
+    EdgeLength(f64),
+    // Split edges until none subtends more than `angle` radians seen from `eye`; see
+    // `Camera::tessellation`.
+    ScreenSpace { eye: Point3, angle: f64 },
+}
+
+impl Tessellation {
+    fn max_edge_length(&self, midpoint: &Point3) -> f64 {
+        match self {
+            Tessellation::EdgeLength(length) => *length,
+            Tessellation::ScreenSpace { eye, angle } => (midpoint - eye).len() * angle,
+        }
+    }
+}
+
+pub struct TriangleMesh {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    triangles: Vec<[usize; 3]>,
+    bvh: Bvh,
+    // Picks triangles in proportion to their area, for sampling the mesh as a light.
+    areas: AliasTable,
+    material: Box<dyn Material>,
+}
+
+impl TriangleMesh {
+    pub fn new(
+        positions: Vec<Point3>,
+        triangles: Vec<[usize; 3]>,
+        material: Box<dyn Material>,
+    ) -> TriangleMesh {
+        let boxes: Vec<Aabb> = triangles
+            .iter()
+            .map(|triangle| {
+                let [p0, p1, p2] = triangle.map(|i| &positions[i]);
+                Aabb::surrounding(&Aabb::new(p0, p1), &Aabb::new(p2, p2)).padded(1e-4)
+            })
+            .collect();
+        let areas: Vec<f64> = triangles
+            .iter()
+            .map(|triangle| triangle_area(&positions, triangle))
+            .collect();
+        let mut mesh = TriangleMesh {
+            bvh: Bvh::new(&boxes),
+            areas: AliasTable::new(&areas),
+            positions,
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            triangles,
+            material,
+        };
+        mesh.material
+            .set_emitter_surface(&mesh.emitter_surface(&areas));
+        mesh
+    }
+
+    // Per-vertex normals, interpolated for smooth shading.
+    pub fn with_normals(mut self, normals: Vec<Vec3>) -> TriangleMesh {
+        if normals.len() == self.positions.len() {
+            self.normals = normals;
+        }
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: Vec<(f64, f64)>) -> TriangleMesh {
+        if uvs.len() == self.positions.len() {
+            self.uvs = uvs;
+            let areas: Vec<f64> = self
+                .triangles
+                .iter()
+                .map(|triangle| triangle_area(&self.positions, triangle))
+                .collect();
+            let surface = self.emitter_surface(&areas);
+            self.material.set_emitter_surface(&surface);
+        }
+        self
+    }
+
+    // Three points inside each triangle, where a quadratic over the triangle averages
+    // exactly, with the triangle's share of the area between them.
+    fn emitter_surface(&self, areas: &[f64]) -> EmitterSurface {
+        let area: f64 = areas.iter().sum();
+        let mut samples = Vec::with_capacity(3 * self.triangles.len());
+        for (triangle, triangle_area) in self.triangles.iter().zip(areas) {
+            let [p0, p1, p2] = triangle.map(|i| &self.positions[i]);
+            for (b1, b2) in [
+                (1.0 / 6.0, 1.0 / 6.0),
+                (2.0 / 3.0, 1.0 / 6.0),
+                (1.0 / 6.0, 2.0 / 3.0),
+            ] {
+                let b0 = 1.0 - b1 - b2;
+                let (u, v) = self.uv_at(triangle, b1, b2);
+                samples.push(SurfaceSample {
+                    u,
+                    v,
+                    p: &(&(b0 * p0) + &(b1 * p1)) + &(b2 * p2),
+                    weight: triangle_area / (3.0 * area),
+                });
+            }
+        }
+        EmitterSurface { area, samples }
+    }
+
+    // Texture coordinates inside a triangle from the barycentrics of its second and
+    // third vertices.
+    fn uv_at(&self, triangle: &[usize; 3], b1: f64, b2: f64) -> (f64, f64) {
+        let b0 = 1.0 - b1 - b2;
+        let [uv0, uv1, uv2] = if self.uvs.is_empty() {
+            [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)]
+        } else {
+            triangle.map(|i| self.uvs[i])
+        };
+        (
+            b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0,
+            b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1,
+        )
+    }
+
+    // Loads the `v`, `vt`, `vn` and `f` records of a Wavefront OBJ file. Polygons are
+    // split into triangle fans.
+    pub fn load_obj<P: AsRef<Path>>(
+        path: P,
+        material: Box<dyn Material>,
+    ) -> io::Result<TriangleMesh> {
+        let text = fs::read_to_string(path)?;
+        let mut obj_positions = Vec::new();
+        let mut obj_uvs = Vec::new();
+        let mut obj_normals = Vec::new();
+
+        let mut corners: HashMap<(usize, Option<usize>, Option<usize>), usize> = HashMap::new();
+        let mut positions = Vec::new();
+        let mut uvs = Vec::new();
+        let mut normals = Vec::new();
+        let mut triangles = Vec::new();
+
+        for line in text.lines() {
+            let mut fields = line.split_whitespace();
+            match fields.next() {
+                Some("v") => obj_positions.push(parse_vector(fields)?),
+                Some("vn") => obj_normals.push(parse_vector(fields)?),
+                Some("vt") => {
+                    let uv = parse_vector(fields.chain(["0", "0"]))?;
+                    obj_uvs.push((uv.x(), uv.y()));
+                }
+                Some("f") => {
+                    let mut face = Vec::new();
+                    for corner in fields {
+                        let mut indices = corner.split('/');
+                        let mut index = |count: usize| -> io::Result<Option<usize>> {
+                            match indices.next() {
+                                Some(index) if !index.is_empty() => {
+                                    obj_index(index, count).map(Some)
+                                }
+                                _ => Ok(None),
+                            }
+                        };
+                        let key = (
+                            index(obj_positions.len())?
+                                .ok_or_else(|| invalid_data("face corner without a position"))?,
+                            index(obj_uvs.len())?,
+                            index(obj_normals.len())?,
+                        );
+                        let vertex = *corners.entry(key).or_insert_with(|| {
+                            positions.push(obj_positions[key.0].clone());
+                            uvs.push(key.1.map(|i| obj_uvs[i]));
+                            normals.push(key.2.map(|i| obj_normals[i].clone()));
+                            positions.len() - 1
+                        });
+                        face.push(vertex);
+                    }
+                    for i in 1..face.len().saturating_sub(1) {
+                        triangles.push([face[0], face[i], face[i + 1]]);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut mesh = TriangleMesh::new(positions, triangles, material);
+        if let Some(uvs) = uvs.into_iter().collect::<Option<Vec<_>>>() {
+            mesh = mesh.with_uvs(uvs);
+        }
+        if let Some(normals) = normals.into_iter().collect::<Option<Vec<_>>>() {
+            mesh = mesh.with_normals(normals);
+        }
+        Ok(mesh)
+    }
+
+    // Splits edges until they are short enough, moves every vertex along its normal by
+    // `scale` times the height texture, and recomputes the normals. Vertices that share
+    // a position, as OBJ corners with different uvs or normals do, are welded first:
+    // edges are split by their welded ends and each position moves once, along the
+    // average of its vertices' normals by the average of their heights, so neighbouring
+    // triangles agree and no cracks open.
+    pub fn displaced(
+        self,
+        height: &dyn Texture,
+        scale: f64,
+        tessellation: &Tessellation,
+    ) -> TriangleMesh {
+        let (mut points, mut vertex_points) = weld(&self.positions);
+        let mut uvs = if self.uvs.is_empty() {
+            vec![(0.0, 0.0); self.positions.len()]
+        } else {
+            self.uvs
+        };
+        let mut normals = if self.normals.is_empty() {
+            vertex_normals(&self.positions, &self.triangles)
+        } else {
+            self.normals
+        };
+        let mut triangles = self.triangles;
+
+        for _ in 0..MAX_TESSELLATION_PASSES {
+            let mut midpoints: HashMap<(usize, usize), usize> = HashMap::new();
+            let mut point_midpoints: HashMap<(usize, usize), usize> = HashMap::new();
+            let mut midpoint = |a: usize, b: usize| -> Option<usize> {
+                let key = (a.min(b), a.max(b));
+                if let Some(&index) = midpoints.get(&key) {
+                    return Some(index);
+                }
+                let (pa, pb) = (vertex_points[a], vertex_points[b]);
+                let middle = 0.5 * &(&points[pa] + &points[pb]);
+                let length = (&points[pa] - &points[pb]).len();
+                if length <= tessellation.max_edge_length(&middle).max(1e-6) {
+                    return None;
+                }
+                let point = *point_midpoints
+                    .entry((pa.min(pb), pa.max(pb)))
+                    .or_insert_with(|| {
+                        points.push(middle);
+                        points.len() - 1
+                    });
+                vertex_points.push(point);
+                uvs.push((0.5 * (uvs[a].0 + uvs[b].0), 0.5 * (uvs[a].1 + uvs[b].1)));
+                normals.push((&normals[a] + &normals[b]).unit_vector());
+                midpoints.insert(key, vertex_points.len() - 1);
+                Some(vertex_points.len() - 1)
+            };
+
+            let mut refined = Vec::with_capacity(triangles.len());
+            let mut split_any = false;
+            for triangle in triangles.iter() {
+                let middles = [0, 1, 2].map(|i| midpoint(triangle[i], triangle[(i + 1) % 3]));
+                split_any |= middles.iter().any(Option::is_some);
+                split_triangle(triangle, &middles, &mut refined);
+            }
+            triangles = refined;
+            if !split_any {
+                break;
+            }
+        }
+
+        let mut directions = vec![Vec3::new(); points.len()];
+        let mut heights = vec![(0.0, 0); points.len()];
+        for (vertex, &point) in vertex_points.iter().enumerate() {
+            directions[point] += normals[vertex].clone();
+            let (u, v) = uvs[vertex];
+            heights[point].0 += height.value(u, v, &points[point]).x();
+            heights[point].1 += 1;
+        }
+        for (point, p) in points.iter_mut().enumerate() {
+            let (total, count) = heights[point];
+            if count == 0 || directions[point].is_near_zero() {
+                continue;
+            }
+            let offset = scale * total / count as f64;
+            *p = &p.clone() + &(offset * &directions[point].unit_vector());
+        }
+
+        let welded: Vec<[usize; 3]> = triangles
+            .iter()
+            .map(|triangle| triangle.map(|vertex| vertex_points[vertex]))
+            .collect();
+        let point_normals = vertex_normals(&points, &welded);
+        let positions = vertex_points
+            .iter()
+            .map(|&point| points[point].clone())
+            .collect();
+        let normals = vertex_points
+            .iter()
+            .map(|&point| point_normals[point].clone())
+            .collect();
+        TriangleMesh::new(positions, triangles, self.material)
+            .with_uvs(uvs)
+            .with_normals(normals)
+    }
+
+    // Möller-Trumbore; returns the distance and the barycentrics of the second and
+    // third vertices.
+    fn intersect(
+        &self,
+        ray: &Ray,
+        triangle: &[usize; 3],
+        ray_t: &Range<f64>,
+    ) -> Option<(f64, f64, f64)> {
+        let p0 = &self.positions[triangle[0]];
+        let edge1 = &self.positions[triangle[1]] - p0;
+        let edge2 = &self.positions[triangle[2]] - p0;
+
+        let pvec = ray.direction().cross(&edge2);
+        let determinant = edge1.dot(&pvec);
+        if determinant.abs() < 1e-12 {
+            return None;
+        }
+        let inverse_determinant = 1.0 / determinant;
+
+        let tvec = ray.origin() - p0;
+        let b1 = tvec.dot(&pvec) * inverse_determinant;
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+        let qvec = tvec.cross(&edge1);
+        let b2 = ray.direction().dot(&qvec) * inverse_determinant;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+        let t = edge2.dot(&qvec) * inverse_determinant;
+        ray_t.surrounds(t).then_some((t, b1, b2))
+    }
+
+    fn hit_record(
+        &self,
+        ray: &Ray,
+        triangle: &[usize; 3],
+        t: f64,
+        b1: f64,
+        b2: f64,
+    ) -> HitRecord<'_> {
+        let b0 = 1.0 - b1 - b2;
+        let [p0, p1, p2] = triangle.map(|i| &self.positions[i]);
+        let geometric_normal = (p1 - p0).cross(&(p2 - p0)).unit_vector();
+
+        let [uv0, uv1, uv2] = if self.uvs.is_empty() {
+            [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)]
+        } else {
+            triangle.map(|i| self.uvs[i])
+        };
+
+        let mut rec = HitRecord::new(ray.at(t), geometric_normal, t, self.material.as_ref());
+        (rec.u, rec.v) = self.uv_at(triangle, b1, b2);
+
+        // Solve for the position derivatives from the edges in uv space.
+        let (du02, dv02) = (uv0.0 - uv2.0, uv0.1 - uv2.1);
+        let (du12, dv12) = (uv1.0 - uv2.0, uv1.1 - uv2.1);
+        let (dp02, dp12) = (p0 - p2, p1 - p2);
+        let determinant = du02 * dv12 - dv02 * du12;
+        if determinant.abs() > 1e-12 {
+            rec.dpdu = &(&(dv12 * &dp02) - &(dv02 * &dp12)) / determinant;
+            rec.dpdv = &(&(du02 * &dp12) - &(du12 * &dp02)) / determinant;
+        }
+
+        rec.make_normal_face_ray(ray);
+        if !self.normals.is_empty() {
+            let [n0, n1, n2] = triangle.map(|i| &self.normals[i]);
+            let shading_normal = (&(&(b0 * n0) + &(b1 * n1)) + &(b2 * n2)).unit_vector();
+            rec.normal = if shading_normal.dot(&rec.normal) < 0.0 {
+                -&shading_normal
+            } else {
+                shading_normal
+            };
+        }
+        rec
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn bounding_box(&self) -> Option<Aabb> {
+        let first = self.positions.first()?;
+        Some(
+            self.positions
+                .iter()
+                .fold(Aabb::new(first, first), |bounds, p| {
+                    Aabb::surrounding(&bounds, &Aabb::new(p, p))
+                }),
+        )
+    }
+
+    fn area(&self) -> f64 {
+        self.triangles
+            .iter()
+            .map(|triangle| triangle_area(&self.positions, triangle))
+            .sum()
+    }
+
+    fn average_emission(&self) -> Color {
+        self.material.average_emission()
+    }
+
+    fn hits(&self, ray: &Ray, ray_t: Range<f64>) -> Option<HitRecord<'_>> {
+        let mut hit = None;
+        self.bvh.traverse(ray, ray_t, |index, ray_t| {
+            let triangle = &self.triangles[index];
+            let (t, b1, b2) = self.intersect(ray, triangle, &ray_t)?;
+            let rec = self.hit_record(ray, triangle, t, b1, b2);
+            if !self.material.alpha_test(&rec) {
+                return None;
+            }
+            hit = Some(rec);
+            Some(t)
+        });
+        hit
+    }
+
+    // Every point of the mesh along `direction` could have been sampled, so each one
+    // the ray passes through adds its density.
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let area = self.area();
+        if area <= 0.0 {
+            return 0.0;
+        }
+        let ray = Ray::new(origin.clone(), direction.clone(), 0.0);
+        let mut start = 0.001;
+        let mut pdf = 0.0;
+        while let Some(rec) = self.hits(&ray, start..utils::INFINITY) {
+            let distance_squared = rec.t * rec.t * direction.len_squared();
+            let cosine = (direction.dot(&rec.normal) / direction.len()).abs();
+            if cosine > 0.0 {
+                pdf += distance_squared / (cosine * area);
+            }
+            start = rec.t + 0.001;
+        }
+        pdf
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        if self.triangles.is_empty() {
+            return Vec3::random_unit_vector();
+        }
+        let (index, _) = self.areas.sample();
+        let [p0, p1, p2] = self.triangles[index].map(|i| &self.positions[i]);
+        let mut rng = rand::thread_rng();
+        let r1 = rng.gen_range(0.0..1.0_f64).sqrt();
+        let r2 = rng.gen_range(0.0..1.0);
+        let point = &(&((1.0 - r1) * p0) + &((r1 * (1.0 - r2)) * p1)) + &((r1 * r2) * p2);
+        &point - origin
+    }
+}
+
+fn triangle_area(positions: &[Point3], triangle: &[usize; 3]) -> f64 {
+    let [p0, p1, p2] = triangle.map(|i| &positions[i]);
+    0.5 * (p1 - p0).cross(&(p2 - p0)).len()
+}
+
+// Splits a triangle given the midpoints of the edges that need splitting, where edge
+// `i` runs from vertex `i` to the next. Winding is preserved.
+fn split_triangle(triangle: &[usize; 3], middles: &[Option<usize>; 3], out: &mut Vec<[usize; 3]>) {
+    let split = middles.iter().filter(|middle| middle.is_some()).count();
+    // Rotate so that the split edges come first.
+    let rotation = match split {
+        1 => middles.iter().position(Option::is_some).unwrap_or(0),
+        2 => (middles.iter().position(Option::is_none).unwrap_or(0) + 1) % 3,
+        _ => 0,
+    };
+    let v = [0, 1, 2].map(|i| triangle[(i + rotation) % 3]);
+    let m = [0, 1, 2].map(|i| middles[(i + rotation) % 3]);
+
+    match (split, m) {
+        (1, [Some(m0), _, _]) => {
+            out.push([v[0], m0, v[2]]);
+            out.push([m0, v[1], v[2]]);
+        }
+        (2, [Some(m0), Some(m1), _]) => {
+            out.push([m0, v[1], m1]);
+            out.push([v[0], m0, m1]);
+            out.push([v[0], m1, v[2]]);
+        }
+        (3, [Some(m0), Some(m1), Some(m2)]) => {
+            out.push([v[0], m0, m2]);
+            out.push([m0, v[1], m1]);
+            out.push([m2, m1, v[2]]);
+            out.push([m0, m1, m2]);
+        }
+        _ => out.push(*triangle),
+    }
+}
+
+// Merges vertices at the same position, returning the distinct positions and the
+// position of each vertex.
+fn weld(positions: &[Point3]) -> (Vec<Point3>, Vec<usize>) {
+    let mut indices: HashMap<[u64; 3], usize> = HashMap::new();
+    let mut points = Vec::new();
+    let vertex_points = positions
+        .iter()
+        .map(|p| {
+            // Adding zero turns -0.0 into 0.0 so that both weld together.
+            let key = [p.x(), p.y(), p.z()].map(|c| (c + 0.0).to_bits());
+            *indices.entry(key).or_insert_with(|| {
+                points.push(p.clone());
+                points.len() - 1
+            })
+        })
+        .collect();
+    (points, vertex_points)
+}
+
+// Area-weighted averages of the face normals around each vertex.
+fn vertex_normals(positions: &[Point3], triangles: &[[usize; 3]]) -> Vec<Vec3> {
+    let mut normals = vec![Vec3::new(); positions.len()];
+    for triangle in triangles.iter() {
+        let [p0, p1, p2] = triangle.map(|i| &positions[i]);
+        let face_normal = (p1 - p0).cross(&(p2 - p0));
+        for &i in triangle.iter() {
+            normals[i] += face_normal.clone();
+        }
+    }
+    normals
+        .into_iter()
+        .map(|normal| {
+            if normal.is_near_zero() {
+                Vec3::new_with(0.0, 1.0, 0.0)
+            } else {
+                normal.unit_vector()
+            }
+        })
+        .collect()
+}
+
+fn parse_vector<'a>(mut fields: impl Iterator<Item = &'a str>) -> io::Result<Vec3> {
+    let mut component = || -> io::Result<f64> {
+        fields
+            .next()
+            .and_then(|field| field.parse().ok())
+            .ok_or_else(|| invalid_data("malformed vector in OBJ file"))
+    };
+    Ok(Vec3::new_with(component()?, component()?, component()?))
+}
+
+// OBJ indices start at one, and negative ones count back from the latest element.
+fn obj_index(index: &str, count: usize) -> io::Result<usize> {
+    let index: i64 = index
+        .parse()
+        .map_err(|_| invalid_data("malformed index in OBJ file"))?;
+    let resolved = if index < 0 {
+        count as i64 + index
+    } else {
+        index - 1
+    };
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(invalid_data("index out of range in OBJ file"));
+    }
+    Ok(resolved as usize)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color::Color,
+        material::LambertianMaterial,
+        texture::{NoiseTexture, SolidColor},
+    };
+
+    fn grey() -> Box<dyn Material> {
+        Box::new(LambertianMaterial::new(&Color::new_with(0.5, 0.5, 0.5)))
+    }
+
+    // A cube whose faces each have their own normal, so every corner is split into
+    // three vertices, as OBJ exporters write hard edges.
+    fn faceted_cube() -> TriangleMesh {
+        let obj = "\
+v -1 -1 -1\nv 1 -1 -1\nv 1 1 -1\nv -1 1 -1\n\
+v -1 -1 1\nv 1 -1 1\nv 1 1 1\nv -1 1 1\n\
+vn 0 0 -1\nvn 0 0 1\nvn 0 -1 0\nvn 0 1 0\nvn -1 0 0\nvn 1 0 0\n\
+f 1//1 4//1 3//1 2//1\nf 5//2 6//2 7//2 8//2\n\
+f 1//3 2//3 6//3 5//3\nf 4//4 8//4 7//4 3//4\n\
+f 1//5 5//5 8//5 4//5\nf 2//6 3//6 7//6 6//6\n";
+        let path = std::env::temp_dir().join(format!("cube-{}.obj", std::process::id()));
+        fs::write(&path, obj).unwrap();
+        let mesh = TriangleMesh::load_obj(&path, grey()).unwrap();
+        fs::remove_file(path).unwrap();
+        mesh
+    }
+
+    #[test]
+    fn displaced_obj_mesh_stays_watertight() {
+        let cube = faceted_cube();
+        assert_eq!(cube.positions.len(), 24);
+
+        let height = NoiseTexture::new(4.0);
+        let mesh = cube.displaced(&height, 0.2, &Tessellation::EdgeLength(0.3));
+        assert!(mesh.triangles.len() > 12);
+
+        // Every edge, with its ends compared by position, borders exactly two triangles.
+        let (_, points) = weld(&mesh.positions);
+        let mut edges: HashMap<(usize, usize), usize> = HashMap::new();
+        for triangle in mesh.triangles.iter() {
+            for i in 0..3 {
+                let (a, b) = (points[triangle[i]], points[triangle[(i + 1) % 3]]);
+                *edges.entry((a.min(b), a.max(b))).or_default() += 1;
+            }
+        }
+        assert!(edges.values().all(|&count| count == 2));
+
+        // And no ray from the middle escapes through a crack.
+        let origin = Point3::new();
+        for _ in 0..2000 {
+            let ray = Ray::new(origin.clone(), Vec3::random_unit_vector(), 0.0);
+            assert!(mesh.hits(&ray, 0.001..utils::INFINITY).is_some());
+        }
+    }
+
+    #[test]
+    fn displacement_moves_shared_corners_along_averaged_normal() {
+        let mesh = faceted_cube().displaced(
+            &SolidColor::scalar(1.0),
+            0.5,
+            &Tessellation::EdgeLength(10.0),
+        );
+        let corner = 1.0 + 0.5 / 3.0_f64.sqrt();
+        for p in mesh.positions.iter() {
+            for c in [p.x(), p.y(), p.z()] {
+                assert!((c.abs() - corner).abs() < 1e-9, "{c}");
+            }
+        }
+    }
+
+    #[test]
+    fn light_pdf_integrates_to_one() {
+        // A square folded along its diagonal, facing the origin.
+        let positions = vec![
+            Point3::new_with(-1.0, -1.0, -0.5),
+            Point3::new_with(1.0, -1.0, -1.0),
+            Point3::new_with(1.0, 1.0, -0.5),
+            Point3::new_with(-1.0, 1.0, -1.0),
+        ];
+        let mesh = TriangleMesh::new(positions, vec![[0, 1, 2], [0, 2, 3]], grey());
+        let origin = Point3::new();
+
+        let samples = 200000;
+        let integral = (0..samples)
+            .map(|_| mesh.pdf_value(&origin, &Vec3::random_unit_vector()))
+            .sum::<f64>()
+            * 4.0
+            * utils::PI
+            / samples as f64;
+        assert!((integral - 1.0).abs() < 0.02, "{integral}");
+
+        let direction = mesh.random(&origin);
+        assert!(mesh.pdf_value(&origin, &direction) > 0.0);
+    }
+}
@@ -0,0 +1,298 @@
+use rand::Rng;
+
+use crate::{color::Color, utils, vec3::Vec3};
+
+// Directions are expressed in a local shading frame whose z axis is the normal.
+fn cos2_theta(w: &Vec3) -> f64 {
+    w.z().powi(2)
+}
+
+fn tan2_theta(w: &Vec3) -> f64 {
+    (1.0 - cos2_theta(w)).max(0.0) / cos2_theta(w)
+}
+
+pub struct TrowbridgeReitz {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl TrowbridgeReitz {
+    pub fn new(alpha_x: f64, alpha_y: f64) -> TrowbridgeReitz {
+        TrowbridgeReitz {
+            alpha_x: alpha_x.max(1e-4),
+            alpha_y: alpha_y.max(1e-4),
+        }
+    }
+
+    // Perceptual roughness in [0, 1], squared as in Disney's and glTF's conventions.
+    pub fn from_roughness(roughness_u: f64, roughness_v: f64) -> TrowbridgeReitz {
+        TrowbridgeReitz::new(
+            roughness_u.clamp(0.0, 1.0).powi(2),
+            roughness_v.clamp(0.0, 1.0).powi(2),
+        )
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < 1e-3
+    }
+
+    pub fn d(&self, wm: &Vec3) -> f64 {
+        let tan2 = tan2_theta(wm);
+        if tan2.is_infinite() || tan2.is_nan() {
+            return 0.0;
+        }
+        let cos4 = cos2_theta(wm).powi(2);
+        let sin2 = 1.0 - cos2_theta(wm);
+        let (cos2_phi, sin2_phi) = if sin2 > 0.0 {
+            (wm.x().powi(2) / sin2, wm.y().powi(2) / sin2)
+        } else {
+            (1.0, 0.0)
+        };
+        let e = tan2 * (cos2_phi / self.alpha_x.powi(2) + sin2_phi / self.alpha_y.powi(2));
+        1.0 / (utils::PI * self.alpha_x * self.alpha_y * cos4 * (1.0 + e).powi(2))
+    }
+
+    pub fn lambda(&self, w: &Vec3) -> f64 {
+        let tan2 = tan2_theta(w);
+        if tan2.is_infinite() || tan2.is_nan() {
+            return 0.0;
+        }
+        let sin2 = 1.0 - cos2_theta(w);
+        let alpha2 = if sin2 > 0.0 {
+            (w.x().powi(2) * self.alpha_x.powi(2) + w.y().powi(2) * self.alpha_y.powi(2)) / sin2
+        } else {
+            self.alpha_x * self.alpha_y
+        };
+        ((1.0 + alpha2 * tan2).sqrt() - 1.0) / 2.0
+    }
+
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // Height-correlated Smith masking-shadowing.
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Density of visible normals as seen from `w`.
+    pub fn d_visible(&self, w: &Vec3, wm: &Vec3) -> f64 {
+        if w.z() == 0.0 {
+            return 0.0;
+        }
+        self.g1(w) / w.z().abs() * self.d(wm) * w.dot(wm).abs()
+    }
+
+    // Samples a visible normal (Heitz 2018), always in the upper hemisphere.
+    pub fn sample_wm(&self, w: &Vec3) -> Vec3 {
+        let mut wh =
+            Vec3::new_with(self.alpha_x * w.x(), self.alpha_y * w.y(), w.z()).unit_vector();
+        if wh.z() < 0.0 {
+            wh = -&wh;
+        }
+
+        let t1 = if wh.z() < 0.99999 {
+            Vec3::new_with(0.0, 0.0, 1.0).cross(&wh).unit_vector()
+        } else {
+            Vec3::new_with(1.0, 0.0, 0.0)
+        };
+        let t2 = wh.cross(&t1);
+
+        let mut rng = rand::thread_rng();
+        let r = rng.gen_range(0.0..1.0_f64).sqrt();
+        let phi = 2.0 * utils::PI * rng.gen_range(0.0..1.0);
+        let (px, py) = (r * phi.cos(), r * phi.sin());
+        let h = (1.0 - px.powi(2)).sqrt();
+        let s = (1.0 + wh.z()) / 2.0;
+        let py = (1.0 - s) * h + s * py;
+
+        let pz = (1.0 - px.powi(2) - py.powi(2)).max(0.0).sqrt();
+        let nh = &(&(px * &t1) + &(py * &t2)) + &(pz * &wh);
+        Vec3::new_with(
+            self.alpha_x * nh.x(),
+            self.alpha_y * nh.y(),
+            nh.z().max(1e-6),
+        )
+        .unit_vector()
+    }
+}
+
+pub fn reflect(wo: &Vec3, n: &Vec3) -> Vec3 {
+    &(-wo) + &(2.0 * wo.dot(n) * n)
+}
+
+// Refracts `wo` about `n` for a relative IOR `eta` (transmitted over incident side),
+// or returns None on total internal reflection.
+pub fn refract(wo: &Vec3, n: &Vec3, eta: f64) -> Option<Vec3> {
+    let mut cos_theta_i = wo.dot(n);
+    let (eta, n) = if cos_theta_i < 0.0 {
+        cos_theta_i = -cos_theta_i;
+        (1.0 / eta, -n)
+    } else {
+        (eta, n.clone())
+    };
+
+    let sin2_theta_t = (1.0 - cos_theta_i.powi(2)).max(0.0) / eta.powi(2);
+    if sin2_theta_t >= 1.0 {
+        return None;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    Some(&(-&(wo / eta)) + &((cos_theta_i / eta - cos_theta_t) * &n))
+}
+
+pub fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let mut cos_theta_i = cos_theta_i.clamp(-1.0, 1.0);
+    let eta = if cos_theta_i < 0.0 {
+        cos_theta_i = -cos_theta_i;
+        1.0 / eta
+    } else {
+        eta
+    };
+
+    let sin2_theta_t = (1.0 - cos_theta_i.powi(2)) / eta.powi(2);
+    if sin2_theta_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).max(0.0).sqrt();
+    let r_parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let r_perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    (r_parallel.powi(2) + r_perpendicular.powi(2)) / 2.0
+}
+
+pub fn schlick(cos_theta: f64, f0: &Color) -> Color {
+    let weight = (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5);
+    let white = Color::new_with(1.0, 1.0, 1.0);
+    f0 + &(weight * &(&white - f0))
+}
+
+// Unpolarised Fresnel reflectance of a conductor with complex IOR eta + i k.
+pub fn fresnel_complex(cos_theta_i: f64, eta: f64, k: f64) -> f64 {
+    let cos_theta_i = cos_theta_i.clamp(0.0, 1.0);
+    let eta = Complex::new(eta, k);
+    let sin2_theta_i = Complex::new(1.0 - cos_theta_i.powi(2), 0.0);
+    let sin2_theta_t = sin2_theta_i.div(&eta.mul(&eta));
+    let cos_theta_t = Complex::new(1.0, 0.0).sub(&sin2_theta_t).sqrt();
+    let cos_i = Complex::new(cos_theta_i, 0.0);
+
+    let eta_cos_i = eta.mul(&cos_i);
+    let r_parallel = eta_cos_i
+        .sub(&cos_theta_t)
+        .div(&eta_cos_i.add(&cos_theta_t));
+    let eta_cos_t = eta.mul(&cos_theta_t);
+    let r_perpendicular = cos_i.sub(&eta_cos_t).div(&cos_i.add(&eta_cos_t));
+    (r_parallel.norm() + r_perpendicular.norm()) / 2.0
+}
+
+pub fn fresnel_complex_rgb(cos_theta_i: f64, eta: &Color, k: &Color) -> Color {
+    Color::new_with(
+        fresnel_complex(cos_theta_i, eta.x(), k.x()),
+        fresnel_complex(cos_theta_i, eta.y(), k.y()),
+        fresnel_complex(cos_theta_i, eta.z(), k.z()),
+    )
+}
+
+// Reflectance of a film of index `film_eta` and thickness `thickness_nm` between an
+// incident medium of index `eta_i` and a substrate of complex index eta_t + i k_t. The
+// waves bouncing inside the film are summed with the Airy formula for each
+// polarisation, and the two are averaged.
+pub fn fresnel_thin_film(
+    cos_theta_i: f64,
+    eta_i: f64,
+    film_eta: f64,
+    thickness_nm: f64,
+    eta_t: f64,
+    k_t: f64,
+    wavelength_nm: f64,
+) -> f64 {
+    let cos_theta_i = cos_theta_i.clamp(0.0, 1.0);
+    let sin2_theta_film = (eta_i / film_eta).powi(2) * (1.0 - cos_theta_i.powi(2));
+    if sin2_theta_film >= 1.0 {
+        return 1.0;
+    }
+    let cos_theta_film = (1.0 - sin2_theta_film).sqrt();
+
+    let substrate = Complex::new(eta_t, k_t);
+    let sin2_theta_t =
+        Complex::new(film_eta.powi(2) * sin2_theta_film, 0.0).div(&substrate.mul(&substrate));
+    let cos_theta_t = Complex::new(1.0, 0.0).sub(&sin2_theta_t).sqrt();
+
+    let (n1, n2) = (Complex::new(eta_i, 0.0), Complex::new(film_eta, 0.0));
+    let (c1, c2) = (
+        Complex::new(cos_theta_i, 0.0),
+        Complex::new(cos_theta_film, 0.0),
+    );
+    let amplitude = |na: &Complex, ca: &Complex, nb: &Complex, cb: &Complex| {
+        let (a, b) = (na.mul(ca), nb.mul(cb));
+        a.sub(&b).div(&a.add(&b))
+    };
+
+    let delta = 4.0 * utils::PI * film_eta * thickness_nm * cos_theta_film / wavelength_nm;
+    let phase = Complex::new(delta.cos(), delta.sin());
+    let airy = |r12: Complex, r23: Complex| {
+        let r23 = r23.mul(&phase);
+        r12.add(&r23).norm() / Complex::new(1.0, 0.0).add(&r12.mul(&r23)).norm()
+    };
+
+    let perpendicular = airy(
+        amplitude(&n1, &c1, &n2, &c2),
+        amplitude(&n2, &c2, &substrate, &cos_theta_t),
+    );
+    let parallel = airy(
+        amplitude(&n2, &c1, &n1, &c2),
+        amplitude(&substrate, &c2, &n2, &cos_theta_t),
+    );
+    ((perpendicular + parallel) / 2.0).clamp(0.0, 1.0)
+}
+
+#[derive(Clone, Copy)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Complex {
+        Complex { re, im }
+    }
+
+    fn add(&self, other: &Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+
+    fn sub(&self, other: &Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+
+    fn mul(&self, other: &Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+
+    fn div(&self, other: &Complex) -> Complex {
+        let scale = 1.0 / other.norm();
+        Complex::new(
+            scale * (self.re * other.re + self.im * other.im),
+            scale * (self.im * other.re - self.re * other.im),
+        )
+    }
+
+    fn norm(&self) -> f64 {
+        self.re.powi(2) + self.im.powi(2)
+    }
+
+    fn sqrt(&self) -> Complex {
+        let n = self.norm().sqrt();
+        if n == 0.0 {
+            return Complex::new(0.0, 0.0);
+        }
+        let t1 = (0.5 * (n + self.re.abs())).sqrt();
+        let t2 = 0.5 * self.im / t1;
+        if self.re >= 0.0 {
+            Complex::new(t1, t2)
+        } else {
+            Complex::new(t2.abs(), t1.copysign(self.im))
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use std::ops::Range;
+
+use crate::{
+    aabb::Aabb,
+    color::Color,
+    hittable::{HitRecord, Hittable},
+    ray::{Ray, RayKind},
+    vec3::{Point3, Vec3},
+};
+
+// Which kinds of rays see an object.
+#[derive(Clone, Copy)]
+pub struct Visibility {
+    pub camera: bool,
+    pub shadow: bool,
+    pub reflection: bool,
+    pub refraction: bool,
+}
+
+impl Visibility {
+    pub const ALL: Visibility = Visibility {
+        camera: true,
+        shadow: true,
+        reflection: true,
+        refraction: true,
+    };
+
+    fn sees(&self, kind: RayKind) -> bool {
+        match kind {
+            RayKind::Camera => self.camera,
+            RayKind::Shadow => self.shadow,
+            RayKind::Reflection => self.reflection,
+            RayKind::Refraction => self.refraction,
+        }
+    }
+}
+
+// A named object in the scene, which lights can be linked to by name and which can be
+// hidden from some kinds of rays. Names given further in take precedence.
+pub struct Object {
+    name: String,
+    shape: Box<dyn Hittable>,
+    visibility: Visibility,
+}
+
+impl Object {
+    pub fn new(name: &str, shape: Box<dyn Hittable>) -> Object {
+        Object {
+            name: name.to_string(),
+            shape,
+            visibility: Visibility::ALL,
+        }
+    }
+
+    pub fn with_visibility(mut self, visibility: Visibility) -> Object {
+        self.visibility = visibility;
+        self
+    }
+}
+
+impl Hittable for Object {
+    fn hits(&self, ray: &Ray, ray_t: Range<f64>) -> Option<HitRecord<'_>> {
+        if !self.visibility.sees(ray.kind()) {
+            return None;
+        }
+        let mut rec = self.shape.hits(ray, ray_t)?;
+        rec.object.get_or_insert(&self.name);
+        Some(rec)
+    }
+
+    fn transmittance(&self, ray: &Ray, ray_t: Range<f64>) -> f64 {
+        if !self.visibility.sees(ray.kind()) {
+            return 1.0;
+        }
+        self.shape.transmittance(ray, ray_t)
+    }
+
+    fn area(&self) -> f64 {
+        self.shape.area()
+    }
+
+    fn average_emission(&self) -> Color {
+        self.shape.average_emission()
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.shape.bounding_box()
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.shape.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        self.shape.random(origin)
+    }
+}
@@ -0,0 +1,51 @@
+use crate::vec3::Vec3;
+
+pub struct Onb {
+    axis: [Vec3; 3],
+}
+
+impl Onb {
+    pub fn new(n: &Vec3) -> Onb {
+        let w = n.unit_vector();
+        let a = if w.x().abs() > 0.9 {
+            Vec3::new_with(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new_with(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(&a).unit_vector();
+        let u = w.cross(&v);
+        Onb { axis: [u, v, w] }
+    }
+
+    // A frame around `n` whose u axis points as close to `reference` as possible.
+    pub fn with_reference(n: &Vec3, reference: &Vec3) -> Onb {
+        let w = n.unit_vector();
+        let u = reference - &(reference.dot(&w) * &w);
+        if u.is_near_zero() {
+            return Onb::new(n);
+        }
+        let u = u.unit_vector();
+        let v = w.cross(&u);
+        Onb { axis: [u, v, w] }
+    }
+
+    pub fn u(&self) -> &Vec3 {
+        &self.axis[0]
+    }
+
+    pub fn v(&self) -> &Vec3 {
+        &self.axis[1]
+    }
+
+    pub fn w(&self) -> &Vec3 {
+        &self.axis[2]
+    }
+
+    pub fn transform(&self, v: &Vec3) -> Vec3 {
+        &(&(v.x() * self.u()) + &(v.y() * self.v())) + &(v.z() * self.w())
+    }
+
+    pub fn to_local(&self, v: &Vec3) -> Vec3 {
+        Vec3::new_with(v.dot(self.u()), v.dot(self.v()), v.dot(self.w()))
+    }
+}
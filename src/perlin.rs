@@ -0,0 +1,76 @@
+use rand::seq::SliceRandom;
+
+use crate::vec3::{Point3, Vec3};
+
+const POINT_COUNT: usize = 256;
+
+pub struct Perlin {
+    random_vectors: Vec<Vec3>,
+    permutations: [Vec<usize>; 3],
+}
+
+impl Perlin {
+    pub fn new() -> Perlin {
+        let random_vectors = (0..POINT_COUNT)
+            .map(|_| Vec3::random_within(-1.0, 1.0).unit_vector())
+            .collect();
+        Perlin {
+            random_vectors,
+            permutations: [
+                Perlin::generate_permutation(),
+                Perlin::generate_permutation(),
+                Perlin::generate_permutation(),
+            ],
+        }
+    }
+
+    fn generate_permutation() -> Vec<usize> {
+        let mut permutation: Vec<usize> = (0..POINT_COUNT).collect();
+        permutation.shuffle(&mut rand::thread_rng());
+        permutation
+    }
+
+    pub fn noise(&self, p: &Point3) -> f64 {
+        let floor = [p.x().floor(), p.y().floor(), p.z().floor()];
+        let fraction = [p.x() - floor[0], p.y() - floor[1], p.z() - floor[2]];
+        let cell = floor.map(|f| f as i64);
+
+        let mut accumulated = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let index = self.permutations[0][((cell[0] + di) & 255) as usize]
+                        ^ self.permutations[1][((cell[1] + dj) & 255) as usize]
+                        ^ self.permutations[2][((cell[2] + dk) & 255) as usize];
+                    let offset = Vec3::new_with(
+                        fraction[0] - di as f64,
+                        fraction[1] - dj as f64,
+                        fraction[2] - dk as f64,
+                    );
+                    // Hermite smoothing of the trilinear weights.
+                    let weight = |corner: i64, t: f64| {
+                        let t = t * t * (3.0 - 2.0 * t);
+                        corner as f64 * t + (1.0 - corner as f64) * (1.0 - t)
+                    };
+                    accumulated += weight(di, fraction[0])
+                        * weight(dj, fraction[1])
+                        * weight(dk, fraction[2])
+                        * self.random_vectors[index].dot(&offset);
+                }
+            }
+        }
+        accumulated
+    }
+
+    pub fn turbulence(&self, p: &Point3, depth: usize) -> f64 {
+        let mut accumulated = 0.0;
+        let mut p = p.clone();
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            accumulated += weight * self.noise(&p);
+            weight *= 0.5;
+            p = 2.0 * &p;
+        }
+        accumulated.abs()
+    }
+}
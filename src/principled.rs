@@ -0,0 +1,397 @@
+use std::rc::Rc;
+
+use rand::Rng;
+
+use crate::{
+    color::Color,
+    hittable::HitRecord,
+    material::Material,
+    medium::Interior,
+    microfacet::{self, TrowbridgeReitz},
+    ray::Ray,
+    spectrum::RefractiveIndex,
+    texture::{ChannelTexture, SolidColor, Texture},
+    utils,
+    vec3::Vec3,
+};
+
+// Burley's principled BSDF (Disney 2012, with the 2015 transmission lobe). Every
+// parameter is a texture; scalar parameters are read from the red channel.
+pub struct Principled {
+    base_color: Rc<dyn Texture>,
+    metallic: Rc<dyn Texture>,
+    roughness: Rc<dyn Texture>,
+    specular: Rc<dyn Texture>,
+    specular_tint: Rc<dyn Texture>,
+    anisotropic: Rc<dyn Texture>,
+    sheen: Rc<dyn Texture>,
+    sheen_tint: Rc<dyn Texture>,
+    clearcoat: Rc<dyn Texture>,
+    clearcoat_gloss: Rc<dyn Texture>,
+    transmission: Rc<dyn Texture>,
+    ior: f64,
+    priority: u32,
+}
+
+impl Principled {
+    pub fn new(base_color: Rc<dyn Texture>) -> Principled {
+        let scalar = |value: f64| -> Rc<dyn Texture> { Rc::new(SolidColor::scalar(value)) };
+        Principled {
+            base_color,
+            metallic: scalar(0.0),
+            roughness: scalar(0.5),
+            specular: scalar(0.5),
+            specular_tint: scalar(0.0),
+            anisotropic: scalar(0.0),
+            sheen: scalar(0.0),
+            sheen_tint: scalar(0.5),
+            clearcoat: scalar(0.0),
+            clearcoat_gloss: scalar(1.0),
+            transmission: scalar(0.0),
+            ior: 1.5,
+            priority: 0,
+        }
+    }
+
+    // glTF 2.0 metallic-roughness: roughness lives in the green channel and metallic in
+    // the blue channel of the packed texture, each scaled by its factor. The glTF
+    // dielectric F0 of 0.04 is the default specular of 0.5. KHR_materials_transmission,
+    // KHR_materials_ior and KHR_materials_clearcoat map onto `with_transmission`,
+    // `with_ior` and `with_clearcoat` (with a gloss of one minus the coat roughness).
+    pub fn from_gltf(
+        base_color: Rc<dyn Texture>,
+        metallic_roughness: Rc<dyn Texture>,
+        metallic_factor: f64,
+        roughness_factor: f64,
+    ) -> Principled {
+        Principled::new(base_color)
+            .with_metallic(Rc::new(ChannelTexture::new(
+                metallic_roughness.clone(),
+                2,
+                metallic_factor,
+            )))
+            .with_roughness(Rc::new(ChannelTexture::new(
+                metallic_roughness,
+                1,
+                roughness_factor,
+            )))
+    }
+
+    pub fn with_metallic(mut self, metallic: Rc<dyn Texture>) -> Principled {
+        self.metallic = metallic;
+        self
+    }
+
+    pub fn with_roughness(mut self, roughness: Rc<dyn Texture>) -> Principled {
+        self.roughness = roughness;
+        self
+    }
+
+    pub fn with_specular(mut self, specular: Rc<dyn Texture>, tint: Rc<dyn Texture>) -> Principled {
+        self.specular = specular;
+        self.specular_tint = tint;
+        self
+    }
+
+    pub fn with_anisotropic(mut self, anisotropic: Rc<dyn Texture>) -> Principled {
+        self.anisotropic = anisotropic;
+        self
+    }
+
+    pub fn with_sheen(mut self, sheen: Rc<dyn Texture>, tint: Rc<dyn Texture>) -> Principled {
+        self.sheen = sheen;
+        self.sheen_tint = tint;
+        self
+    }
+
+    pub fn with_clearcoat(
+        mut self,
+        clearcoat: Rc<dyn Texture>,
+        gloss: Rc<dyn Texture>,
+    ) -> Principled {
+        self.clearcoat = clearcoat;
+        self.clearcoat_gloss = gloss;
+        self
+    }
+
+    pub fn with_transmission(mut self, transmission: Rc<dyn Texture>) -> Principled {
+        self.transmission = transmission;
+        self
+    }
+
+    pub fn with_ior(mut self, ior: f64) -> Principled {
+        self.ior = ior.max(1.0);
+        self
+    }
+
+    // Where transmissive materials overlap, the one with the highest priority fills the
+    // overlap, as for `Dielectric::with_priority`.
+    pub fn with_priority(mut self, priority: u32) -> Principled {
+        self.priority = priority;
+        self
+    }
+
+    fn lobes(&self, rec: &HitRecord) -> Lobes {
+        let scalar =
+            |texture: &Rc<dyn Texture>| texture.value(rec.u, rec.v, &rec.p).x().clamp(0.0, 1.0);
+        let base_color = self.base_color.value(rec.u, rec.v, &rec.p);
+        let metallic = scalar(&self.metallic);
+        let roughness = scalar(&self.roughness);
+        let transmission = scalar(&self.transmission);
+
+        let white = Color::new_with(1.0, 1.0, 1.0);
+        let tint = if base_color.luminance() > 0.0 {
+            &base_color / base_color.luminance()
+        } else {
+            white.clone()
+        };
+        let dielectric_f0 =
+            0.08 * scalar(&self.specular) * &lerp(&white, &tint, scalar(&self.specular_tint));
+        let sheen_color = scalar(&self.sheen) * &lerp(&white, &tint, scalar(&self.sheen_tint));
+
+        let aspect = (1.0 - 0.9 * scalar(&self.anisotropic)).sqrt();
+        let alpha = roughness.powi(2);
+        let clearcoat = 0.25 * scalar(&self.clearcoat);
+
+        let diffuse = (1.0 - metallic) * (1.0 - transmission);
+        let transmission = (1.0 - metallic) * transmission;
+        let specular = metallic + 0.25 * (1.0 - metallic);
+        let total = diffuse + specular + clearcoat + transmission;
+
+        Lobes {
+            specular_f0: lerp(&dielectric_f0, &base_color, metallic),
+            base_color,
+            sheen_color,
+            roughness,
+            eta: if rec.front_face {
+                self.ior / rec.outer_refraction_index
+            } else {
+                rec.outer_refraction_index / self.ior
+            },
+            distribution: TrowbridgeReitz::new(alpha / aspect, alpha * aspect),
+            clearcoat,
+            clearcoat_alpha: 0.1 + (0.001 - 0.1) * scalar(&self.clearcoat_gloss),
+            diffuse,
+            transmission,
+            probabilities: [
+                diffuse / total,
+                specular / total,
+                clearcoat / total,
+                transmission / total,
+            ],
+        }
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let uvw = rec.shading_frame();
+        let wo = uvw.to_local(&(-&ray_in.direction().unit_vector()));
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        let lobes = self.lobes(rec);
+        let wi = lobes.sample(&wo)?;
+        let pdf = lobes.pdf(&wo, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        let attenuation = &lobes.eval(&wo, &wi) / pdf;
+        let scattered = Ray::new(rec.p.clone(), uvw.transform(&wi), ray_in.time());
+        Some((attenuation, scattered))
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let uvw = rec.shading_frame();
+        let wo = uvw.to_local(&(-&ray_in.direction().unit_vector()));
+        let wi = uvw.to_local(&direction.unit_vector());
+        if wo.z() <= 0.0 || wi.z() == 0.0 {
+            return Color::new();
+        }
+        self.lobes(rec).eval(&wo, &wi)
+    }
+
+    fn pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Option<f64> {
+        let lobes = self.lobes(rec);
+        if lobes.distribution.is_smooth() {
+            return None;
+        }
+        let uvw = rec.shading_frame();
+        let wo = uvw.to_local(&(-&ray_in.direction().unit_vector()));
+        let wi = uvw.to_local(&direction.unit_vector());
+        if wo.z() <= 0.0 || wi.z() == 0.0 {
+            return Some(0.0);
+        }
+        Some(lobes.pdf(&wo, &wi))
+    }
+
+    fn interior(&self) -> Option<Interior> {
+        Some(Interior {
+            refraction_index: RefractiveIndex::Constant(self.ior),
+            priority: self.priority,
+            absorption: Color::new(),
+            scattering: Color::new(),
+        })
+    }
+}
+
+// The principled parameters resolved at one shading point. All directions are in the
+// local shading frame with `wo` above the surface.
+struct Lobes {
+    base_color: Color,
+    specular_f0: Color,
+    sheen_color: Color,
+    roughness: f64,
+    eta: f64,
+    distribution: TrowbridgeReitz,
+    clearcoat: f64,
+    clearcoat_alpha: f64,
+    diffuse: f64,
+    transmission: f64,
+    probabilities: [f64; 4],
+}
+
+impl Lobes {
+    fn sample(&self, wo: &Vec3) -> Option<Vec3> {
+        let mut rng = rand::thread_rng();
+        let mut choice = rng.gen_range(0.0..1.0);
+        let mut lobe = 0;
+        while lobe < 3 && choice >= self.probabilities[lobe] {
+            choice -= self.probabilities[lobe];
+            lobe += 1;
+        }
+
+        let wi = match lobe {
+            0 => Vec3::random_cosine_direction(),
+            1 => microfacet::reflect(wo, &self.distribution.sample_wm(wo)),
+            2 => microfacet::reflect(wo, &sample_gtr1(self.clearcoat_alpha)),
+            _ => microfacet::refract(wo, &self.distribution.sample_wm(wo), self.eta)?,
+        };
+        // A lobe only covers its own hemisphere, as assumed by `pdf`.
+        let transmitted = lobe == 3;
+        let valid = if transmitted {
+            wi.z() < 0.0
+        } else {
+            wi.z() > 0.0
+        };
+        valid.then_some(wi)
+    }
+
+    // The full f * |cos| of every lobe, so that the weight is independent of which
+    // lobe produced the sample.
+    fn eval(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        if wi.z() < 0.0 {
+            return self.eval_transmission(wo, wi);
+        }
+
+        let wh = (wo + wi).unit_vector();
+        let cos_d = wi.dot(&wh);
+        let mut f = Color::new();
+
+        if self.diffuse > 0.0 {
+            let fd90 = 0.5 + 2.0 * self.roughness * cos_d.powi(2);
+            let retro = (1.0 + (fd90 - 1.0) * schlick_weight(wi.z()))
+                * (1.0 + (fd90 - 1.0) * schlick_weight(wo.z()));
+            let diffuse = &(retro / utils::PI * &self.base_color)
+                + &(schlick_weight(cos_d) * &self.sheen_color);
+            f += self.diffuse * &diffuse;
+        }
+
+        let fresnel = lerp(
+            &microfacet::schlick(cos_d, &self.specular_f0),
+            &grey(microfacet::fresnel_dielectric(wo.dot(&wh), self.eta)),
+            self.transmission,
+        );
+        let specular =
+            self.distribution.d(&wh) * self.distribution.g(wo, wi) / (4.0 * wo.z() * wi.z());
+        f += specular * &fresnel;
+
+        if self.clearcoat > 0.0 {
+            let coat = TrowbridgeReitz::new(0.25, 0.25).g(wo, wi);
+            let fresnel = microfacet::schlick(cos_d, &grey(0.04)).x();
+            f += grey(
+                self.clearcoat * gtr1(wh.z(), self.clearcoat_alpha) * coat * fresnel
+                    / (4.0 * wo.z() * wi.z()),
+            );
+        }
+
+        wi.z() * &f
+    }
+
+    fn eval_transmission(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        let Some((wm, denominator)) = self.transmission_half_vector(wo, wi) else {
+            return Color::new();
+        };
+        let transmittance = 1.0 - microfacet::fresnel_dielectric(wo.dot(&wm), self.eta);
+        let f = transmittance
+            * self.distribution.d(&wm)
+            * self.distribution.g(wo, wi)
+            * (wi.dot(&wm) * wo.dot(&wm) / (wo.z() * denominator)).abs();
+        (self.transmission * f) * &self.base_color
+    }
+
+    // The generalised half vector of a refraction, with the Jacobian denominator of
+    // Walter et al. (2007).
+    fn transmission_half_vector(&self, wo: &Vec3, wi: &Vec3) -> Option<(Vec3, f64)> {
+        if self.transmission <= 0.0 {
+            return None;
+        }
+        let mut wm = (&(self.eta * wi) + wo).unit_vector();
+        if wm.z() < 0.0 {
+            wm = -&wm;
+        }
+        if wo.dot(&wm) <= 0.0 || wi.dot(&wm) >= 0.0 {
+            return None;
+        }
+        let denominator = (wi.dot(&wm) + wo.dot(&wm) / self.eta).powi(2);
+        Some((wm, denominator))
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        let [diffuse, specular, clearcoat, transmission] = self.probabilities;
+        if wi.z() < 0.0 {
+            return match self.transmission_half_vector(wo, wi) {
+                Some((wm, denominator)) => {
+                    transmission * self.distribution.d_visible(wo, &wm) * wi.dot(&wm).abs()
+                        / denominator
+                }
+                None => 0.0,
+            };
+        }
+
+        let wh = (wo + wi).unit_vector();
+        let cos_o = wo.dot(&wh).abs();
+        diffuse * wi.z() / utils::PI
+            + specular * self.distribution.d_visible(wo, &wh) / (4.0 * cos_o)
+            + clearcoat * gtr1(wh.z(), self.clearcoat_alpha) * wh.z() / (4.0 * cos_o)
+    }
+}
+
+// Berry's distribution (GTR with gamma = 1), used for the clearcoat.
+fn gtr1(cos_theta: f64, alpha: f64) -> f64 {
+    let alpha2 = alpha.powi(2);
+    (alpha2 - 1.0) / (utils::PI * alpha2.ln() * (1.0 + (alpha2 - 1.0) * cos_theta.powi(2)))
+}
+
+fn sample_gtr1(alpha: f64) -> Vec3 {
+    let mut rng = rand::thread_rng();
+    let alpha2 = alpha.powi(2);
+    let cos_theta = ((1.0 - alpha2.powf(1.0 - rng.gen_range(0.0..1.0))) / (1.0 - alpha2)).sqrt();
+    let sin_theta = (1.0 - cos_theta.powi(2)).max(0.0).sqrt();
+    let phi = 2.0 * utils::PI * rng.gen_range(0.0..1.0);
+    Vec3::new_with(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+fn schlick_weight(cos_theta: f64) -> f64 {
+    (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5)
+}
+
+fn lerp(a: &Color, b: &Color, t: f64) -> Color {
+    &((1.0 - t) * a) + &(t * b)
+}
+
+fn grey(value: f64) -> Color {
+    Color::new_with(value, value, value)
+}
@@ -0,0 +1,482 @@
+use std::f64::consts::PI;
+
+use crate::{
+    aperture::Aperture,
+    mesh::Tessellation,
+    utils,
+    vec3::{Point3, Vec3},
+};
+
+// The camera settings a projection may draw on.
+pub struct View<'a> {
+    pub vfov: f64,
+    pub aspect_ratio: f64,
+    pub focus_dist: f64,
+    pub defocus_angle: f64,
+    pub aperture: &'a Aperture,
+    pub cat_eye: f64,
+}
+
+// A primary ray and the weight of the radiance it brings back, below one where the
+// optics let less light through to that part of the film.
+pub struct CameraRay {
+    pub origin: Point3,
+    pub direction: Vec3,
+    pub weight: f64,
+}
+
+impl CameraRay {
+    pub fn new(origin: Point3, direction: Vec3) -> CameraRay {
+        CameraRay {
+            origin,
+            direction,
+            weight: 1.0,
+        }
+    }
+
+    pub fn with_weight(mut self, weight: f64) -> CameraRay {
+        self.weight = weight;
+        self
+    }
+}
+
+// Maps positions on the film to primary rays. Film coordinates run over [0, 1] from
+// the top-left corner of the image, and rays are in camera space: x to the right, y
+// up, looking down -z.
+pub trait Projection {
+    // None where the film lies outside the projection, such as the corners around a
+    // circular fisheye.
+    fn generate(&self, view: &View, x: f64, y: f64) -> Option<CameraRay>;
+
+    // Whether this is the plain pinhole or thin-lens view, which stereo pairs replace
+    // with an off-axis view per eye.
+    fn is_perspective(&self) -> bool {
+        false
+    }
+
+    // The angle across one pixel of a film `image_height` pixels tall.
+    fn pixel_angle(&self, view: &View, image_height: f64) -> f64 {
+        utils::degrees_to_radians(view.vfov) / image_height
+    }
+
+    // Tessellation that splits displaced meshes down to roughly `pixels` per edge.
+    fn tessellation(
+        &self,
+        view: &View,
+        eye: &Point3,
+        pixels: f64,
+        image_height: f64,
+    ) -> Tessellation {
+        Tessellation::ScreenSpace {
+            eye: eye.clone(),
+            angle: pixels * self.pixel_angle(view, image_height),
+        }
+    }
+}
+
+pub struct Perspective;
+
+impl Projection for Perspective {
+    fn generate(&self, view: &View, x: f64, y: f64) -> Option<CameraRay> {
+        let height = 2.0 * (utils::degrees_to_radians(view.vfov) / 2.0).tan() * view.focus_dist;
+        let width = height * view.aspect_ratio;
+        let target = Point3::new_with((x - 0.5) * width, (0.5 - y) * height, -view.focus_dist);
+
+        let origin = lens_sample(view, x, y)?;
+        let direction = &target - &origin;
+        Some(CameraRay::new(origin, direction))
+    }
+
+    fn is_perspective(&self) -> bool {
+        true
+    }
+}
+
+// A perspective view from one eye of a stereo pair. The eyes sit `interocular` apart
+// and look in parallel, with their frusta shifted to frame the same window at the
+// `convergence` distance, where objects appear at screen depth.
+pub struct OffAxis {
+    eye: Eye,
+    interocular: f64,
+    convergence: f64,
+}
+
+impl OffAxis {
+    pub fn new(eye: Eye, interocular: f64, convergence: f64) -> OffAxis {
+        OffAxis {
+            eye,
+            interocular,
+            convergence,
+        }
+    }
+}
+
+impl Projection for OffAxis {
+    fn generate(&self, view: &View, x: f64, y: f64) -> Option<CameraRay> {
+        let height = 2.0 * (utils::degrees_to_radians(view.vfov) / 2.0).tan() * self.convergence;
+        let width = height * view.aspect_ratio;
+        let window = Point3::new_with((x - 0.5) * width, (0.5 - y) * height, -self.convergence);
+        let eye = Point3::new_with(self.eye.side() * self.interocular / 2.0, 0.0, 0.0);
+
+        // Depth of field still focuses at the camera's focus distance along the ray.
+        let focus = &eye + &((view.focus_dist / self.convergence) * &(&window - &eye));
+        let origin = &eye + &lens_sample(view, x, y)?;
+        let direction = &focus - &origin;
+        Some(CameraRay::new(origin, direction))
+    }
+}
+
+// Parallel rays through a viewport `height` world units tall, with no defocus.
+pub struct Orthographic {
+    height: f64,
+}
+
+impl Orthographic {
+    pub fn new(height: f64) -> Orthographic {
+        Orthographic { height }
+    }
+}
+
+impl Projection for Orthographic {
+    fn generate(&self, view: &View, x: f64, y: f64) -> Option<CameraRay> {
+        let origin = Point3::new_with(
+            (x - 0.5) * self.height * view.aspect_ratio,
+            (0.5 - y) * self.height,
+            0.0,
+        );
+        Some(CameraRay::new(origin, Vec3::new_with(0.0, 0.0, -1.0)))
+    }
+
+    fn tessellation(
+        &self,
+        _view: &View,
+        _eye: &Point3,
+        pixels: f64,
+        image_height: f64,
+    ) -> Tessellation {
+        Tessellation::EdgeLength(pixels * self.height / image_height)
+    }
+}
+
+// A full sphere of longitude across the width and latitude down the height, for 2:1
+// lat-long images; the view direction lands in the centre.
+pub struct Equirectangular;
+
+impl Projection for Equirectangular {
+    fn generate(&self, _view: &View, x: f64, y: f64) -> Option<CameraRay> {
+        let (longitude, latitude) = lat_long(x, y);
+        Some(CameraRay::new(
+            Point3::new(),
+            direction_at(longitude, latitude),
+        ))
+    }
+
+    fn pixel_angle(&self, _view: &View, image_height: f64) -> f64 {
+        PI / image_height
+    }
+}
+
+pub enum FisheyeMapping {
+    // Image radius proportional to the angle off axis, as for dome masters.
+    Equidistant,
+    // Image radius proportional to 2 sin(θ/2), preserving solid angle.
+    Equisolid,
+}
+
+// A circular fisheye whose image circle fills the height of the film, covering `fov`
+// degrees from edge to edge.
+pub struct Fisheye {
+    fov: f64,
+    mapping: FisheyeMapping,
+}
+
+impl Fisheye {
+    pub fn new(fov: f64, mapping: FisheyeMapping) -> Fisheye {
+        Fisheye { fov, mapping }
+    }
+}
+
+impl Projection for Fisheye {
+    fn generate(&self, view: &View, x: f64, y: f64) -> Option<CameraRay> {
+        let px = 2.0 * (x - 0.5) * view.aspect_ratio;
+        let py = 2.0 * (0.5 - y);
+        let r = (px * px + py * py).sqrt();
+        if r > 1.0 {
+            return None;
+        }
+
+        let theta_max = utils::degrees_to_radians(self.fov) / 2.0;
+        let theta = match self.mapping {
+            FisheyeMapping::Equidistant => r * theta_max,
+            FisheyeMapping::Equisolid => 2.0 * (r * (theta_max / 2.0).sin()).asin(),
+        };
+        let psi = py.atan2(px);
+        let direction = Vec3::new_with(
+            theta.sin() * psi.cos(),
+            theta.sin() * psi.sin(),
+            -theta.cos(),
+        );
+        Some(CameraRay::new(Point3::new(), direction))
+    }
+
+    fn pixel_angle(&self, _view: &View, image_height: f64) -> f64 {
+        utils::degrees_to_radians(self.fov) / image_height
+    }
+}
+
+// Longitude spread evenly across `horizontal_fov` degrees of the width, and heights
+// on the cylinder projected through the camera's vertical field of view.
+pub struct Cylindrical {
+    horizontal_fov: f64,
+}
+
+impl Cylindrical {
+    pub fn new(horizontal_fov: f64) -> Cylindrical {
+        Cylindrical { horizontal_fov }
+    }
+}
+
+impl Projection for Cylindrical {
+    fn generate(&self, view: &View, x: f64, y: f64) -> Option<CameraRay> {
+        let longitude = (x - 0.5) * utils::degrees_to_radians(self.horizontal_fov);
+        let height = 2.0 * (0.5 - y) * (utils::degrees_to_radians(view.vfov) / 2.0).tan();
+        let direction = Vec3::new_with(longitude.sin(), height, -longitude.cos());
+        Some(CameraRay::new(Point3::new(), direction))
+    }
+}
+
+pub enum Eye {
+    Left,
+    Right,
+}
+
+impl Eye {
+    // The direction of the eye from the centre of the head along the camera's x axis.
+    pub fn side(&self) -> f64 {
+        match self {
+            Eye::Left => -1.0,
+            Eye::Right => 1.0,
+        }
+    }
+}
+
+// Omnidirectional stereo: an equirectangular view for one eye, with every ray leaving
+// from the point where that eye would be when turned to face it, on a circle
+// `interpupillary_distance` across.
+pub struct OmnidirectionalStereo {
+    interpupillary_distance: f64,
+    eye: Eye,
+}
+
+impl OmnidirectionalStereo {
+    pub fn new(interpupillary_distance: f64, eye: Eye) -> OmnidirectionalStereo {
+        OmnidirectionalStereo {
+            interpupillary_distance,
+            eye,
+        }
+    }
+}
+
+impl Projection for OmnidirectionalStereo {
+    fn generate(&self, _view: &View, x: f64, y: f64) -> Option<CameraRay> {
+        let (longitude, latitude) = lat_long(x, y);
+        let offset = self.eye.side() * self.interpupillary_distance / 2.0;
+        // The right-hand side of the horizontal heading.
+        let origin = Point3::new_with(offset * longitude.cos(), 0.0, offset * longitude.sin());
+        Some(CameraRay::new(origin, direction_at(longitude, latitude)))
+    }
+
+    fn pixel_angle(&self, _view: &View, image_height: f64) -> f64 {
+        PI / image_height
+    }
+}
+
+// A point on the camera's thin lens, or its centre without defocus blur. Towards the
+// edges of the frame the lens barrel cuts into the aperture from the side, leaving the
+// cat's-eye shapes of optical vignetting: the barrel's opening is a second disk the
+// size of the lens, shifted by `cat_eye` lens radii at the corners.
+fn lens_sample(view: &View, x: f64, y: f64) -> Option<Point3> {
+    if view.defocus_angle <= 0.0 {
+        return Some(Point3::new());
+    }
+    let p = view.aperture.sample();
+    if view.cat_eye > 0.0 {
+        let corner = 0.5 * (view.aspect_ratio * view.aspect_ratio + 1.0).sqrt();
+        let shift = view.cat_eye / corner;
+        let barrel = Vec3::new_with(
+            shift * (x - 0.5) * view.aspect_ratio,
+            shift * (0.5 - y),
+            0.0,
+        );
+        if (&p - &barrel).len_squared() > 1.0 {
+            return None;
+        }
+    }
+    let radius = view.focus_dist * utils::degrees_to_radians(view.defocus_angle / 2.0).tan();
+    Some(radius * &p)
+}
+
+fn lat_long(x: f64, y: f64) -> (f64, f64) {
+    ((x - 0.5) * 2.0 * PI, (0.5 - y) * PI)
+}
+
+fn direction_at(longitude: f64, latitude: f64) -> Vec3 {
+    Vec3::new_with(
+        longitude.sin() * latitude.cos(),
+        latitude.sin(),
+        -longitude.cos() * latitude.cos(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view(aperture: &Aperture) -> View<'_> {
+        View {
+            vfov: 90.0,
+            aspect_ratio: 2.0,
+            focus_dist: 1.0,
+            defocus_angle: 0.0,
+            aperture,
+            cat_eye: 0.0,
+        }
+    }
+
+    fn assert_near(actual: &Vec3, expected: &Vec3) {
+        assert!(
+            (actual - expected).len() < 1e-9,
+            "({}, {}, {}) != ({}, {}, {})",
+            actual.x(),
+            actual.y(),
+            actual.z(),
+            expected.x(),
+            expected.y(),
+            expected.z()
+        );
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel_across_the_viewport() {
+        let aperture = Aperture::Circle;
+        let view = view(&aperture);
+        let projection = Orthographic::new(4.0);
+        let forward = Vec3::new_with(0.0, 0.0, -1.0);
+        for (x, y, origin) in [
+            (0.5, 0.5, Point3::new()),
+            (0.0, 0.0, Point3::new_with(-4.0, 2.0, 0.0)),
+            (1.0, 1.0, Point3::new_with(4.0, -2.0, 0.0)),
+            (0.75, 0.25, Point3::new_with(2.0, 1.0, 0.0)),
+        ] {
+            let ray = projection.generate(&view, x, y).unwrap();
+            assert_near(&ray.origin, &origin);
+            assert_near(&ray.direction, &forward);
+        }
+    }
+
+    #[test]
+    fn equirectangular_spans_the_whole_sphere() {
+        let aperture = Aperture::Circle;
+        let view = view(&aperture);
+        for (x, y, direction) in [
+            (0.5, 0.5, Vec3::new_with(0.0, 0.0, -1.0)),
+            (0.75, 0.5, Vec3::new_with(1.0, 0.0, 0.0)),
+            (0.25, 0.5, Vec3::new_with(-1.0, 0.0, 0.0)),
+            (0.0, 0.5, Vec3::new_with(0.0, 0.0, 1.0)),
+            (0.5, 0.0, Vec3::new_with(0.0, 1.0, 0.0)),
+            (0.5, 1.0, Vec3::new_with(0.0, -1.0, 0.0)),
+        ] {
+            let ray = Equirectangular.generate(&view, x, y).unwrap();
+            assert_near(&ray.origin, &Point3::new());
+            assert_near(&ray.direction, &direction);
+        }
+    }
+
+    #[test]
+    fn fisheyes_map_the_image_circle_to_their_field_of_view() {
+        let aperture = Aperture::Circle;
+        let mut view = view(&aperture);
+        view.aspect_ratio = 1.0;
+        for mapping in [FisheyeMapping::Equidistant, FisheyeMapping::Equisolid] {
+            let fisheye = Fisheye::new(180.0, mapping);
+            for (x, y, direction) in [
+                (0.5, 0.5, Vec3::new_with(0.0, 0.0, -1.0)),
+                (1.0, 0.5, Vec3::new_with(1.0, 0.0, 0.0)),
+                (0.5, 0.0, Vec3::new_with(0.0, 1.0, 0.0)),
+            ] {
+                let ray = fisheye.generate(&view, x, y).unwrap();
+                assert_near(&ray.direction, &direction);
+            }
+            // The corners lie outside the image circle.
+            assert!(fisheye.generate(&view, 0.0, 0.0).is_none());
+        }
+
+        // Halfway out, equidistant lenses see half the angle and equisolid ones less.
+        let angle = |mapping| {
+            let ray = Fisheye::new(180.0, mapping)
+                .generate(&view, 0.75, 0.5)
+                .unwrap();
+            (-ray.direction.z()).acos().to_degrees()
+        };
+        assert!((angle(FisheyeMapping::Equidistant) - 45.0).abs() < 1e-9);
+        let equisolid = angle(FisheyeMapping::Equisolid);
+        let expected = 2.0 * (0.5 * (PI / 4.0).sin()).asin().to_degrees();
+        assert!((equisolid - expected).abs() < 1e-9, "{equisolid}");
+    }
+
+    #[test]
+    fn ods_eyes_sit_on_the_viewing_circle() {
+        let aperture = Aperture::Circle;
+        let view = view(&aperture);
+        let left = OmnidirectionalStereo::new(0.064, Eye::Left);
+        let right = OmnidirectionalStereo::new(0.064, Eye::Right);
+
+        // Looking ahead the eyes are apart along x, and looking right along z.
+        let ray = left.generate(&view, 0.5, 0.5).unwrap();
+        assert_near(&ray.origin, &Point3::new_with(-0.032, 0.0, 0.0));
+        assert_near(&ray.direction, &Vec3::new_with(0.0, 0.0, -1.0));
+        let ray = left.generate(&view, 0.75, 0.5).unwrap();
+        assert_near(&ray.origin, &Point3::new_with(0.0, 0.0, -0.032));
+        assert_near(&ray.direction, &Vec3::new_with(1.0, 0.0, 0.0));
+
+        for (x, y) in [(0.1, 0.3), (0.5, 0.5), (0.8, 0.9)] {
+            let (l, r) = (
+                left.generate(&view, x, y).unwrap(),
+                right.generate(&view, x, y).unwrap(),
+            );
+            assert_near(&l.direction, &r.direction);
+            assert_near(&l.origin, &(-&r.origin));
+            assert!((l.origin.len() - 0.032).abs() < 1e-9);
+            assert!(l.origin.dot(&l.direction).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn off_axis_eyes_converge_on_the_same_window() {
+        let aperture = Aperture::Circle;
+        let mut view = view(&aperture);
+        view.focus_dist = 3.0;
+        let (interocular, convergence) = (0.5, 5.0);
+        let left = OffAxis::new(Eye::Left, interocular, convergence);
+        let right = OffAxis::new(Eye::Right, interocular, convergence);
+
+        // Where a ray crosses the plane `distance` in front of the camera.
+        let at_depth = |ray: &CameraRay, distance: f64| {
+            let t = (-distance - ray.origin.z()) / ray.direction.z();
+            &ray.origin + &(t * &ray.direction)
+        };
+        for (x, y) in [(0.5, 0.5), (0.1, 0.2), (0.9, 0.7)] {
+            let (l, r) = (
+                left.generate(&view, x, y).unwrap(),
+                right.generate(&view, x, y).unwrap(),
+            );
+            assert_near(&l.origin, &Point3::new_with(-0.25, 0.0, 0.0));
+            assert_near(&r.origin, &Point3::new_with(0.25, 0.0, 0.0));
+            assert_near(&at_depth(&l, convergence), &at_depth(&r, convergence));
+            // Nearer and farther the eyes see the scene from different sides.
+            let near = &at_depth(&r, 1.0) - &at_depth(&l, 1.0);
+            let far = &at_depth(&r, 20.0) - &at_depth(&l, 20.0);
+            assert!(near.x() > 0.0 && far.x() < 0.0);
+        }
+    }
+}
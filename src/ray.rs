@@ -1,9 +1,20 @@
 use crate::vec3::{Vec3, Point3};
 
+// What a ray is traced for, so that objects can be hidden from some kinds of rays.
+// Scattering inside a volume counts as reflection.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RayKind {
+    Camera,
+    Shadow,
+    Reflection,
+    Refraction,
+}
+
 pub struct Ray {
     direction: Vec3,
     origin: Point3,
     time: f64,
+    kind: RayKind,
 }
 
 impl Ray {
@@ -12,9 +23,15 @@ impl Ray {
             direction,
             origin,
             time,
+            kind: RayKind::Camera,
         }
     }
 
+    pub fn with_kind(mut self, kind: RayKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
     pub fn origin(&self) -> &Point3 {
         &self.origin
     }
@@ -27,6 +44,10 @@ impl Ray {
         self.time
     }
 
+    pub fn kind(&self) -> RayKind {
+        self.kind
+    }
+
     pub fn at(&self, t: f64) -> Point3 {
         &self.origin + &(t * &(self.direction))
     }
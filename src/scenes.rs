@@ -0,0 +1,601 @@
+use std::{io, rc::Rc};
+
+use crate::{
+    aabb::Aabb,
+    aperture::{Aperture, ApertureMask},
+    blackbody::{self, Normalization},
+    bump::{BumpMapped, NormalMapped},
+    camera::Camera,
+    coated::Coated,
+    color::Color,
+    fog::Fog,
+    hittable::Hittable,
+    hittable_list::HittableList,
+    ies::IesProfile,
+    lens::RealisticLens,
+    light::{AreaLight, DirectionalLight, LightList, LinkSet, LinkedLight, PointLight, SpotLight},
+    light_sampler::LightSelection,
+    material::{
+        Cutout, Dielectric, LambertianMaterial, LightDiffuser, Material, Metal, MixMaterial, Power,
+    },
+    mesh::{Tessellation, TriangleMesh},
+    object::{Object, Visibility},
+    perlin::Perlin,
+    principled::Principled,
+    projection::{
+        Cylindrical, Equirectangular, Eye, Fisheye, FisheyeMapping, OmnidirectionalStereo,
+        Orthographic,
+    },
+    sphere::Sphere,
+    stereo::{Stereo, StereoLayout},
+    texture::{ImageTexture, NoiseTexture, SolidColor, Texture},
+    vec3::{Point3, Vec3},
+    volume::{GridMedium, VoxelGrid},
+};
+
+// A downlight throwing most of its light in a 60 degree cone, for when no IES file is
+// given.
+const DOWNLIGHT_IES: &str = "IESNA:LM-63-2002
+[TEST] built-in
+[LUMINAIRE] downlight
+TILT=NONE
+1 1000 1 7 1 1 2 0.1 0.1 0.0
+1.0 1.0 12
+0 15 30 45 60 75 90
+0
+900 850 700 450 150 20 0
+";
+
+// A 50mm double-Gauss design, for when no lens prescription is given.
+const DGAUSS_50MM: &str = "
+# radius  thickness  eta  aperture
+29.475   3.76   1.67   25.2
+84.83    0.12   1      25.2
+19.275   4.025  1.67   23
+40.77    3.275  1.699  23
+12.75    5.705  1      18
+0        4.5    0      17.1
+-14.495  1.18   1.603  17
+40.77    6.065  1.658  20
+-20.385  0.19   1      20
+437.065  3.22   1.717  20
+-39.73   0      1      20
+";
+
+fn sphere(center: &Point3, radius: f64, material: Box<dyn Material>) -> Box<Sphere> {
+    Box::new(Sphere::new(center, center, radius, 0.0, 0.0, material))
+}
+
+fn solid(r: f64, g: f64, b: f64) -> Rc<dyn Texture> {
+    Rc::new(SolidColor::new(&Color::new_with(r, g, b)))
+}
+
+fn scalar(value: f64) -> Rc<dyn Texture> {
+    Rc::new(SolidColor::scalar(value))
+}
+
+fn ground() -> Box<Sphere> {
+    let material = LambertianMaterial::new(&Color::new_with(0.5, 0.5, 0.5));
+    sphere(
+        &Point3::new_with(0.0, -1000.0, 0.0),
+        1000.0,
+        Box::new(material),
+    )
+}
+
+fn invalid_input(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+// A row of spheres through the materials, lit by a warm lamp and rendered spectrally
+// so the glass disperses. `base_color` and `normal_map` are PPM images for the
+// textured sphere at the end of the row.
+pub fn materials(base_color: Option<&str>, normal_map: Option<&str>) -> io::Result<()> {
+    let mut world = HittableList::new();
+    let floor = Principled::new(Rc::new(NoiseTexture::new(4.0))).with_roughness(scalar(0.8));
+    world.add(sphere(
+        &Point3::new_with(0.0, -1000.0, 0.0),
+        1000.0,
+        Box::new(floor),
+    ));
+
+    let soap_film = Rc::new(NoiseTexture::new(2.0));
+    let bk7 = [1.039_612_12, 0.231_792_344, 1.010_469_45];
+    let bk7_poles = [0.006_000_698_67, 0.020_017_914_4, 103.560_653];
+    let principled = Principled::new(solid(0.8, 0.2, 0.1))
+        .with_specular(scalar(0.5), scalar(0.2))
+        .with_anisotropic(scalar(0.5))
+        .with_sheen(scalar(0.3), scalar(0.5))
+        .with_clearcoat(scalar(1.0), scalar(0.9));
+    let glass = Principled::new(solid(1.0, 1.0, 1.0))
+        .with_transmission(scalar(1.0))
+        .with_roughness(scalar(0.1))
+        .with_ior(1.45)
+        .with_priority(1);
+    let textured: Box<dyn Material> = match base_color {
+        Some(path) => {
+            let base_color = Rc::new(ImageTexture::load(path)?);
+            Box::new(Principled::from_gltf(
+                base_color,
+                solid(0.0, 0.4, 0.0),
+                1.0,
+                1.0,
+            ))
+        }
+        None => Box::new(Principled::new(solid(0.3, 0.6, 0.3))),
+    };
+    let textured: Box<dyn Material> = match normal_map {
+        Some(path) => Box::new(
+            NormalMapped::new(textured, Rc::new(ImageTexture::load_linear(path)?))
+                .with_strength(1.5),
+        ),
+        None => textured,
+    };
+
+    let row: Vec<Box<dyn Material>> = vec![
+        Box::new(Metal::gold(0.2)),
+        Box::new(Metal::copper(0.05).with_thin_film(soap_film.clone(), 1.45)),
+        Box::new(BumpMapped::new(
+            Box::new(Metal::aluminium(0.3)),
+            Rc::new(NoiseTexture::new(8.0)),
+            0.02,
+        )),
+        Box::new(Metal::conductor(
+            &Color::new_with(0.2, 0.9, 1.1),
+            &Color::new_with(3.9, 2.4, 2.1),
+            0.05,
+            0.4,
+        )),
+        Box::new(Dielectric::sellmeier(bk7, bk7_poles)),
+        Box::new(
+            Dielectric::cauchy(1.5046, 0.004_20)
+                .with_roughness(0.2)
+                .with_absorption(&Color::new_with(0.0, 0.4, 0.8)),
+        ),
+        Box::new(Dielectric::new(1.33).with_thin_film(soap_film, 1.33)),
+        Box::new(Dielectric::subsurface(
+            1.4,
+            &Color::new_with(0.95, 0.85, 0.8),
+            &Color::new_with(0.2, 0.1, 0.05),
+        )),
+        Box::new(
+            Coated::new(
+                Box::new(LambertianMaterial::new(&Color::new_with(0.7, 0.1, 0.1))),
+                1.5,
+            )
+            .with_roughness(0.05)
+            .with_thickness(0.1)
+            .with_absorption(&Color::new_with(0.1, 0.3, 0.6)),
+        ),
+        Box::new(MixMaterial::new(
+            Box::new(LambertianMaterial::new(&Color::new_with(0.1, 0.2, 0.5))),
+            Box::new(Metal::new(&Color::new_with(0.9, 0.9, 0.9), 0.1)),
+            Rc::new(NoiseTexture::new(6.0)),
+        )),
+        Box::new(
+            Cutout::new(Box::new(principled), Rc::new(NoiseTexture::new(3.0))).with_threshold(0.4),
+        ),
+        Box::new(glass),
+        textured,
+    ];
+    let count = row.len() as f64;
+    for (i, material) in row.into_iter().enumerate() {
+        let x = 1.2 * (i as f64 - (count - 1.0) / 2.0);
+        world.add(sphere(&Point3::new_with(x, 0.5, 0.0), 0.5, material));
+    }
+
+    let lamp_center = Point3::new_with(0.0, 6.0, 4.0);
+    let lamp = LightDiffuser::blackbody(3200.0, Normalization::Luminance)
+        .with_two_sided(false)
+        .with_power(Power::Lumens(3000.0));
+    let lamp: Rc<Sphere> = sphere(&lamp_center, 1.0, Box::new(lamp)).into();
+    world.add(Box::new(lamp.clone()));
+    let mut lights = LightList::new();
+    lights.add(Box::new(AreaLight::new(lamp)));
+
+    let mut camera = Camera::new();
+    camera.image_width = 640;
+    camera.aspect_ratio = 3.0;
+    camera.samples_per_pixel = 64;
+    camera.max_bounces = 12;
+    camera.vfov = 25.0;
+    camera.lookfrom = Point3::new_with(0.0, 2.5, 12.0);
+    camera.lookat = Point3::new_with(0.0, 0.5, 0.0);
+    camera.spectral = true;
+    camera.render(&world, &lights);
+    Ok(())
+}
+
+// A product shot lit by many lights through haze: a key spotlight linked to the product
+// alone, photometric downlights, a string of bulbs and a moonlit sky. `profile` is an
+// IES file to shape the downlights by.
+pub fn lights(selection: Option<&str>, profile: Option<&str>) -> io::Result<()> {
+    let selection = match selection {
+        None | Some("bvh") => LightSelection::Bvh,
+        Some("power") => LightSelection::Power,
+        Some("all") => LightSelection::All,
+        Some(_) => return Err(invalid_input("light selection is all, power or bvh")),
+    };
+    let profile = Rc::new(match profile {
+        Some(path) => IesProfile::load(path)?,
+        None => IesProfile::parse(DOWNLIGHT_IES)?,
+    });
+
+    let mut world = HittableList::new();
+    world.add(Box::new(Object::new("floor", ground())));
+    let product = Principled::new(solid(0.9, 0.7, 0.2))
+        .with_metallic(scalar(1.0))
+        .with_roughness(scalar(0.25));
+    world.add(Box::new(Object::new(
+        "product",
+        sphere(&Point3::new_with(0.0, 1.0, 0.0), 1.0, Box::new(product)),
+    )));
+    // A backdrop that lights can pass through, so it never throws shadows on the floor.
+    let backdrop = LambertianMaterial::new(&Color::new_with(0.2, 0.25, 0.3));
+    world.add(Box::new(
+        Object::new(
+            "set",
+            sphere(&Point3::new_with(0.0, 1.0, -8.0), 5.0, Box::new(backdrop)),
+        )
+        .with_visibility(Visibility {
+            shadow: false,
+            ..Visibility::ALL
+        }),
+    ));
+
+    let mut lights = LightList::new().with_selection(selection);
+    // A warm key of 0.4 W/sr of visible light.
+    let key_color = 0.4 * &blackbody::blackbody_normalized(4500.0, Normalization::RadiantPower);
+    let key = SpotLight::new(
+        &Point3::new_with(3.0, 5.0, 3.0),
+        &Point3::new_with(0.0, 1.0, 0.0),
+        &key_color,
+        25.0,
+        15.0,
+    );
+    lights.add_punctual(Box::new(
+        LinkedLight::new("key", Box::new(key))
+            .with_illumination(LinkSet::include(&["product"]))
+            .with_shadowing(LinkSet::exclude(&["set"])),
+    ));
+    let down = Vec3::new_with(0.0, -1.0, 0.0);
+    let along = Vec3::new_with(1.0, 0.0, 0.0);
+    for x in [-4.0, 4.0] {
+        let position = Point3::new_with(x, 6.0, 0.0);
+        lights.add_punctual(Box::new(
+            PointLight::new(&position, &Color::new_with(0.02, 0.02, 0.02)).with_profile(
+                profile.clone(),
+                &down,
+                &along,
+            ),
+        ));
+    }
+    let rim = SpotLight::new(
+        &Point3::new_with(-3.0, 4.0, -4.0),
+        &Point3::new_with(0.0, 1.0, 0.0),
+        &Color::new_with(0.01, 0.01, 0.012),
+        40.0,
+        30.0,
+    )
+    .with_profile(profile, &down, &along);
+    lights.add_punctual(Box::new(rim));
+    lights.add_punctual(Box::new(DirectionalLight::new(
+        &Vec3::new_with(-0.3, -1.0, -0.5),
+        &Color::new_with(0.05, 0.06, 0.1),
+    )));
+
+    // The string of bulbs does not light the backdrop, which would wash it out.
+    for i in 0..24 {
+        let x = -6.0 + 0.5 * i as f64;
+        let center = Point3::new_with(x, 4.0 + 0.2 * (x / 2.0).cos(), -2.0);
+        let bulb = LightDiffuser::blackbody(2700.0, Normalization::MaxComponent)
+            .with_power(Power::Lumens(2.0));
+        let name = format!("bulb {i}");
+        let bulb: Rc<dyn Hittable> =
+            Rc::new(Object::new(&name, sphere(&center, 0.05, Box::new(bulb))));
+        world.add(Box::new(bulb.clone()));
+        let light = AreaLight::new(bulb);
+        lights.add(Box::new(
+            LinkedLight::new(&name, Box::new(light)).with_illumination(LinkSet::exclude(&["set"])),
+        ));
+    }
+
+    let mut camera = Camera::new();
+    camera.image_width = 400;
+    camera.samples_per_pixel = 64;
+    camera.max_bounces = 8;
+    camera.vfov = 40.0;
+    camera.lookfrom = Point3::new_with(0.0, 2.5, 8.0);
+    camera.lookat = Point3::new_with(0.0, 1.0, 0.0);
+    camera.fog = Some(Fog::homogeneous(
+        &Color::new_with(0.002, 0.002, 0.002),
+        &Color::new_with(0.006, 0.01, 0.016),
+    ));
+    camera.render(&world, &lights);
+    Ok(())
+}
+
+// A glowing cloud over a misty valley. `grid` is a VXG1 density grid for the cloud; the
+// built-in one also burns hotter towards its core.
+pub fn volumes(grid: Option<&str>) -> io::Result<()> {
+    let bounds = Aabb::new(
+        &Point3::new_with(-2.0, 0.5, -2.0),
+        &Point3::new_with(2.0, 4.5, 2.0),
+    );
+    let albedo = Color::new_with(0.8, 0.8, 0.8);
+    let cloud = match grid {
+        Some(path) => GridMedium::new(VoxelGrid::load(path)?, bounds, 4.0, &albedo),
+        None => {
+            let (density, temperature) = billow(32);
+            GridMedium::new(density, bounds, 4.0, &albedo).with_temperature(
+                temperature,
+                2500.0,
+                1e-5,
+            )
+        }
+    };
+
+    let mut world = HittableList::new();
+    world.add(ground());
+    world.add(Box::new(cloud));
+    let (sun_center, sun_radius) = (Point3::new_with(20.0, 30.0, 10.0), 6.0);
+    let sun =
+        LightDiffuser::blackbody(5800.0, Normalization::Luminance).with_power(Power::Watts(30.0));
+    let sun: Rc<Sphere> = sphere(&sun_center, sun_radius, Box::new(sun)).into();
+    world.add(Box::new(sun.clone()));
+    // The sky is large enough to be found by scattering alone.
+    let sky = LightDiffuser::new(&Color::new_with(0.15, 0.2, 0.3));
+    world.add(sphere(&Point3::new(), 500.0, Box::new(sky)));
+    let mut lights = LightList::new();
+    lights.add(Box::new(AreaLight::new(sun)));
+
+    let mut camera = Camera::new();
+    camera.image_width = 400;
+    camera.samples_per_pixel = 64;
+    camera.max_bounces = 16;
+    camera.vfov = 35.0;
+    camera.lookfrom = Point3::new_with(0.0, 2.0, 12.0);
+    camera.lookat = Point3::new_with(0.0, 2.5, 0.0);
+    camera.fog = Some(Fog::exponential_height(
+        &Color::new_with(0.01, 0.01, 0.01),
+        &Color::new_with(0.03, 0.05, 0.08),
+        0.0,
+        1.5,
+    ));
+    camera.render(&world, &lights);
+    Ok(())
+}
+
+// Density and temperature grids of a turbulent ball, `n` voxels a side.
+fn billow(n: usize) -> (VoxelGrid, VoxelGrid) {
+    let noise = Perlin::new();
+    let mut density = Vec::with_capacity(n * n * n);
+    let mut temperature = Vec::with_capacity(n * n * n);
+    for k in 0..n {
+        for j in 0..n {
+            for i in 0..n {
+                let p = Point3::new_with(
+                    (i as f64 + 0.5) / n as f64 - 0.5,
+                    (j as f64 + 0.5) / n as f64 - 0.5,
+                    (k as f64 + 0.5) / n as f64 - 0.5,
+                );
+                let falloff = (1.0 - 2.0 * p.len()).max(0.0);
+                let detail =
+                    0.5 + 0.5 * noise.noise(&(8.0 * &p)) + noise.turbulence(&(4.0 * &p), 5);
+                density.push((falloff * detail) as f32);
+                temperature.push(falloff.powi(2) as f32);
+            }
+        }
+    }
+    (
+        VoxelGrid::new(n, n, n, density),
+        VoxelGrid::new(n, n, n, temperature),
+    )
+}
+
+// A displaced mesh under a panel light. `obj` is a Wavefront OBJ file to displace in
+// place of the built-in cube.
+pub fn meshes(obj: Option<&str>) -> io::Result<()> {
+    let mut camera = Camera::new();
+    camera.image_width = 400;
+    camera.samples_per_pixel = 64;
+    camera.max_bounces = 8;
+    camera.vfov = 35.0;
+    camera.lookfrom = Point3::new_with(3.0, 3.0, 6.0);
+    camera.lookat = Point3::new_with(0.0, 1.0, 0.0);
+
+    let rock = || Principled::new(solid(0.45, 0.4, 0.35)).with_roughness(scalar(0.7));
+    let mesh = match obj {
+        Some(path) => TriangleMesh::load_obj(path, Box::new(rock()))?,
+        None => cube(Box::new(rock())),
+    };
+    let height = NoiseTexture::new(3.0);
+    let mut world = HittableList::new();
+    world.add(ground());
+    world.add(Box::new(mesh.displaced(
+        &height,
+        0.1,
+        &camera.tessellation(2.0),
+    )));
+
+    // A rippled puddle around it, tessellated evenly as it is seen mostly edge on.
+    let pool = TriangleMesh::new(
+        vec![
+            Point3::new_with(-4.0, 0.01, -4.0),
+            Point3::new_with(4.0, 0.01, -4.0),
+            Point3::new_with(4.0, 0.01, 4.0),
+            Point3::new_with(-4.0, 0.01, 4.0),
+        ],
+        vec![[0, 2, 1], [0, 3, 2]],
+        Box::new(Principled::new(solid(0.05, 0.1, 0.12)).with_roughness(scalar(0.15))),
+    )
+    .with_uvs(vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]);
+    world.add(Box::new(pool.displaced(
+        &NoiseTexture::new(20.0),
+        0.02,
+        &Tessellation::EdgeLength(0.1),
+    )));
+
+    let glow = LightDiffuser::blackbody(6500.0, Normalization::Physical)
+        .with_two_sided(false)
+        .with_power(Power::Lumens(1500.0));
+    let panel: Rc<dyn Hittable> = Rc::new(TriangleMesh::new(
+        vec![
+            Point3::new_with(-1.0, 5.0, -1.0),
+            Point3::new_with(1.0, 5.0, -1.0),
+            Point3::new_with(1.0, 5.0, 1.0),
+            Point3::new_with(-1.0, 5.0, 1.0),
+        ],
+        vec![[0, 1, 2], [0, 2, 3]],
+        Box::new(glow),
+    ));
+    world.add(Box::new(panel.clone()));
+    let mut lights = LightList::new();
+    lights.add(Box::new(AreaLight::new(panel)));
+
+    camera.render(&world, &lights);
+    Ok(())
+}
+
+// A unit cube resting on the ground, with its own corners and normals on each face as
+// an OBJ export would have them.
+fn cube(material: Box<dyn Material>) -> TriangleMesh {
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut triangles = Vec::new();
+    for axis in 0..3 {
+        for side in [-1.0, 1.0] {
+            let mut normal = [0.0; 3];
+            normal[axis] = side;
+            let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+            let start = positions.len();
+            for (u, v) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+                let mut corner = [0.0; 3];
+                corner[axis] = 0.5 * side;
+                corner[u_axis] = u - 0.5;
+                corner[v_axis] = v - 0.5;
+                positions.push(Point3::new_with(corner[0], corner[1] + 1.0, corner[2]));
+                normals.push(Vec3::new_with(normal[0], normal[1], normal[2]));
+                uvs.push((u, v));
+            }
+            if side > 0.0 {
+                triangles.extend([[start, start + 1, start + 2], [start, start + 2, start + 3]]);
+            } else {
+                triangles.extend([[start, start + 2, start + 1], [start, start + 3, start + 2]]);
+            }
+        }
+    }
+    TriangleMesh::new(positions, triangles, material)
+        .with_normals(normals)
+        .with_uvs(uvs)
+}
+
+// A row of metal balls through another camera. `projection` picks the camera and `args`
+// its options:
+//   bokeh [blades]         polygonal aperture with cat's-eye highlights
+//   mirror                 ring aperture of a catadioptric lens
+//   mask <image>           aperture drawn as a PPM image
+//   lens [prescription]    a real lens design, written as for pbrt
+//   orthographic, equirectangular, cylindrical
+//   fisheye [equisolid]
+//   ods <left|right>       omnidirectional stereo, one eye
+//   stereo <side-by-side|top-bottom|anaglyph|separate <left> <right>>
+pub fn cameras(projection: Option<&str>, args: &[&str]) -> io::Result<()> {
+    let mut world = HittableList::new();
+    world.add(ground());
+    for i in -3i32..=3 {
+        let center = Point3::new_with(1.5 * i as f64, 0.5, -1.5 * i.abs() as f64);
+        let albedo = Color::new_with(0.5 + 0.07 * i as f64, 0.4, 0.5 - 0.07 * i as f64);
+        world.add(sphere(&center, 0.5, Box::new(Metal::new(&albedo, 0.1))));
+    }
+    let lamp_center = Point3::new_with(0.0, 8.0, 4.0);
+    let lamp = LightDiffuser::new(&Color::new_with(8.0, 8.0, 8.0));
+    let lamp: Rc<Sphere> = sphere(&lamp_center, 2.0, Box::new(lamp)).into();
+    world.add(Box::new(lamp.clone()));
+    let mut lights = LightList::new();
+    lights.add(Box::new(AreaLight::new(lamp)));
+
+    let mut camera = Camera::new();
+    camera.image_width = 400;
+    camera.samples_per_pixel = 64;
+    camera.max_bounces = 8;
+    camera.vfov = 40.0;
+    camera.lookfrom = Point3::new_with(0.0, 1.5, 8.0);
+    camera.lookat = Point3::new_with(0.0, 0.5, 0.0);
+    camera.focus_dist = 8.0;
+
+    let arg = |i: usize| args.get(i).copied();
+    match projection {
+        None | Some("bokeh") => {
+            let blades = arg(0).and_then(|blades| blades.parse().ok()).unwrap_or(6);
+            camera.aperture = Aperture::Polygon {
+                blades,
+                rotation: 15.0,
+            };
+            camera.defocus_angle = 2.0;
+            camera.cat_eye = 0.5;
+        }
+        Some("mirror") => {
+            camera.aperture = Aperture::Ring { obstruction: 0.5 };
+            camera.defocus_angle = 2.0;
+        }
+        Some("mask") => {
+            let path = arg(0).ok_or_else(|| invalid_input("mask needs an image"))?;
+            camera.aperture = Aperture::Mask(ApertureMask::load(path)?);
+            camera.defocus_angle = 2.0;
+        }
+        Some("lens") => {
+            let lens = match arg(0) {
+                Some(path) => RealisticLens::load(path)?,
+                None => RealisticLens::parse(DGAUSS_50MM)?,
+            };
+            camera.projection = Box::new(lens.with_film_diagonal(35.0).with_aperture_diameter(8.0));
+        }
+        Some("orthographic") => camera.projection = Box::new(Orthographic::new(6.0)),
+        Some("equirectangular") => {
+            camera.aspect_ratio = 2.0;
+            camera.projection = Box::new(Equirectangular);
+        }
+        Some("cylindrical") => {
+            camera.aspect_ratio = 3.0;
+            camera.projection = Box::new(Cylindrical::new(150.0));
+        }
+        Some("fisheye") => {
+            let mapping = match arg(0) {
+                Some("equisolid") => FisheyeMapping::Equisolid,
+                _ => FisheyeMapping::Equidistant,
+            };
+            camera.aspect_ratio = 1.0;
+            camera.projection = Box::new(Fisheye::new(180.0, mapping));
+        }
+        Some("ods") => {
+            let eye = match arg(0) {
+                Some("right") => Eye::Right,
+                _ => Eye::Left,
+            };
+            camera.aspect_ratio = 2.0;
+            camera.projection = Box::new(OmnidirectionalStereo::new(0.064, eye));
+        }
+        Some("stereo") => {
+            let layout = match arg(0) {
+                None | Some("side-by-side") => StereoLayout::SideBySide,
+                Some("top-bottom") => StereoLayout::TopBottom,
+                Some("anaglyph") => StereoLayout::Anaglyph,
+                Some("separate") => match (arg(1), arg(2)) {
+                    (Some(left), Some(right)) => StereoLayout::Separate {
+                        left: left.into(),
+                        right: right.into(),
+                    },
+                    _ => return Err(invalid_input("separate stereo needs two image paths")),
+                },
+                Some(_) => return Err(invalid_input("unknown stereo layout")),
+            };
+            let stereo = Stereo::new(0.064, 8.0).with_layout(layout);
+            return camera.render_stereo(&world, &lights, &stereo);
+        }
+        Some(_) => return Err(invalid_input("unknown camera")),
+    }
+    camera.render(&world, &lights);
+    Ok(())
+}
@@ -0,0 +1,317 @@
+use rand::Rng;
+
+use crate::color::Color;
+
+pub const WAVELENGTH_MIN: f64 = 360.0;
+pub const WAVELENGTH_MAX: f64 = 830.0;
+pub const SODIUM_D_LINE: f64 = 587.6;
+
+const WAVELENGTH_COUNT: usize = 3;
+
+// Smits (1999) basis spectra, ten equal bins from 380nm to 720nm.
+const SMITS_MIN: f64 = 380.0;
+const SMITS_MAX: f64 = 720.0;
+
+struct SmitsBasis {
+    white: [f64; 10],
+    cyan: [f64; 10],
+    magenta: [f64; 10],
+    yellow: [f64; 10],
+    red: [f64; 10],
+    green: [f64; 10],
+    blue: [f64; 10],
+}
+
+// Smits' published basis for reflectances, which stays below one.
+const SMITS_REFLECTANCE: SmitsBasis = SmitsBasis {
+    white: [1.0, 1.0, 0.9999, 0.9993, 0.9992, 0.9998, 1.0, 1.0, 1.0, 1.0],
+    cyan: [
+        0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0, 0.0, 0.0,
+    ],
+    magenta: [
+        1.0, 1.0, 0.9685, 0.2229, 0.0, 0.0458, 0.8369, 1.0, 1.0, 0.9959,
+    ],
+    yellow: [
+        0.0001, 0.0, 0.1088, 0.6651, 1.0, 1.0, 0.9996, 0.9586, 0.9685, 0.9840,
+    ],
+    red: [
+        0.1012, 0.0515, 0.0, 0.0, 0.0, 0.0, 0.8325, 1.0149, 1.0149, 1.0149,
+    ],
+    green: [
+        0.0, 0.0, 0.0273, 0.7937, 1.0, 0.9418, 0.1719, 0.0, 0.0, 0.0025,
+    ],
+    blue: [
+        1.0, 1.0, 0.8916, 0.3323, 0.0, 0.0, 0.0003, 0.0369, 0.0483, 0.0496,
+    ],
+};
+
+// A basis for emitters, which may exceed one. Smits fits his illuminant basis to a D65
+// white, but `SpectralFilm` balances an equal-energy white, so this is the reflectance
+// basis refitted by least squares to reproduce white and the primaries through the film.
+const SMITS_ILLUMINANT: SmitsBasis = SmitsBasis {
+    white: [
+        1.0000, 1.0000, 1.0000, 0.9997, 0.9999, 1.0003, 0.9999, 0.9998, 1.0000, 1.0000,
+    ],
+    cyan: [
+        0.9709, 0.9412, 1.0014, 1.0063, 1.0084, 0.9971, 0.1407, 0.0, 0.0, 0.0,
+    ],
+    magenta: [
+        0.9999, 0.9984, 0.9642, 0.2152, 0.0, 0.0448, 0.8490, 1.0089, 1.0016, 0.9960,
+    ],
+    yellow: [
+        0.0003, 0.0036, 0.1129, 0.6664, 1.0009, 1.0005, 0.9995, 0.9584, 0.9685, 0.9840,
+    ],
+    red: [
+        0.1011, 0.0508, 0.0, 0.0, 0.0, 0.0, 0.8250, 1.0588, 1.0223, 1.0146,
+    ],
+    green: [
+        0.0001, 0.0021, 0.0317, 0.7995, 1.0066, 0.9379, 0.1572, 0.0, 0.0, 0.0024,
+    ],
+    blue: [
+        0.9998, 0.9966, 0.8876, 0.3304, 0.0, 0.0, 0.0003, 0.0372, 0.0484, 0.0496,
+    ],
+};
+
+// Multi-lobe fit of the CIE 1931 2-degree observer (Wyman, Sloan and Shirley 2013).
+pub fn cie_xyz(wavelength_nm: f64) -> Color {
+    let lobe = |mean: f64, sigma_low: f64, sigma_high: f64| {
+        let sigma = if wavelength_nm < mean {
+            sigma_low
+        } else {
+            sigma_high
+        };
+        (-0.5 * ((wavelength_nm - mean) / sigma).powi(2)).exp()
+    };
+    let x = 1.056 * lobe(599.8, 37.9, 31.0) + 0.362 * lobe(442.0, 16.0, 26.7)
+        - 0.065 * lobe(501.1, 20.4, 26.2);
+    let y = 0.821 * lobe(568.8, 46.9, 40.5) + 0.286 * lobe(530.9, 16.3, 31.1);
+    let z = 1.217 * lobe(437.0, 11.8, 36.0) + 0.681 * lobe(459.0, 26.0, 13.8);
+    Color::new_with(x, y, z)
+}
+
+pub fn xyz_to_linear_srgb(xyz: &Color) -> Color {
+    Color::new_with(
+        3.240_454_2 * xyz.x() - 1.537_138_5 * xyz.y() - 0.498_531_4 * xyz.z(),
+        -0.969_266_0 * xyz.x() + 1.876_010_8 * xyz.y() + 0.041_556_0 * xyz.z(),
+        0.055_643_4 * xyz.x() - 0.204_025_9 * xyz.y() + 1.057_225_2 * xyz.z(),
+    )
+}
+
+fn smits_bin(table: &[f64; 10], wavelength_nm: f64) -> f64 {
+    let bin_width = (SMITS_MAX - SMITS_MIN) / table.len() as f64;
+    let bin = ((wavelength_nm - SMITS_MIN) / bin_width).floor();
+    table[bin.clamp(0.0, (table.len() - 1) as f64) as usize]
+}
+
+// Upsamples a linear RGB reflectance to a smooth spectrum with Smits' method and
+// evaluates it at one wavelength.
+pub fn rgb_to_spectrum(rgb: &Color, wavelength_nm: f64) -> f64 {
+    smits(&SMITS_REFLECTANCE, rgb, wavelength_nm)
+}
+
+// As `rgb_to_spectrum`, for emitted radiance.
+pub fn rgb_to_illuminant_spectrum(rgb: &Color, wavelength_nm: f64) -> f64 {
+    smits(&SMITS_ILLUMINANT, rgb, wavelength_nm)
+}
+
+fn smits(basis: &SmitsBasis, rgb: &Color, wavelength_nm: f64) -> f64 {
+    let (r, g, b) = (rgb.x().max(0.0), rgb.y().max(0.0), rgb.z().max(0.0));
+    let bin = |table: &[f64; 10]| smits_bin(table, wavelength_nm);
+
+    if r <= g && r <= b {
+        let mut value = r * bin(&basis.white);
+        if g <= b {
+            value += (g - r) * bin(&basis.cyan) + (b - g) * bin(&basis.blue);
+        } else {
+            value += (b - r) * bin(&basis.cyan) + (g - b) * bin(&basis.green);
+        }
+        value
+    } else if g <= r && g <= b {
+        let mut value = g * bin(&basis.white);
+        if r <= b {
+            value += (r - g) * bin(&basis.magenta) + (b - r) * bin(&basis.blue);
+        } else {
+            value += (b - g) * bin(&basis.magenta) + (r - b) * bin(&basis.red);
+        }
+        value
+    } else {
+        let mut value = b * bin(&basis.white);
+        if r <= g {
+            value += (r - b) * bin(&basis.yellow) + (g - r) * bin(&basis.green);
+        } else {
+            value += (g - b) * bin(&basis.yellow) + (r - g) * bin(&basis.red);
+        }
+        value
+    }
+}
+
+// Hero wavelength sampling (Wilkie et al. 2014): one uniformly sampled wavelength plus
+// evenly rotated companions. Per-wavelength values travel in the channels of a Color.
+#[derive(Clone)]
+pub struct SampledWavelengths {
+    lambda: [f64; WAVELENGTH_COUNT],
+}
+
+impl SampledWavelengths {
+    pub fn sample() -> SampledWavelengths {
+        let mut rng = rand::thread_rng();
+        let range = WAVELENGTH_MAX - WAVELENGTH_MIN;
+        let hero = rng.gen_range(0.0..range);
+
+        let mut lambda = [0.0; WAVELENGTH_COUNT];
+        for (i, wavelength) in lambda.iter_mut().enumerate() {
+            let offset = hero + i as f64 * range / WAVELENGTH_COUNT as f64;
+            *wavelength = WAVELENGTH_MIN + offset % range;
+        }
+        SampledWavelengths { lambda }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    pub fn upsample(&self, rgb: &Color) -> Color {
+        Color::new_with(
+            rgb_to_spectrum(rgb, self.lambda[0]),
+            rgb_to_spectrum(rgb, self.lambda[1]),
+            rgb_to_spectrum(rgb, self.lambda[2]),
+        )
+    }
+
+    pub fn upsample_illuminant(&self, rgb: &Color) -> Color {
+        Color::new_with(
+            rgb_to_illuminant_spectrum(rgb, self.lambda[0]),
+            rgb_to_illuminant_spectrum(rgb, self.lambda[1]),
+            rgb_to_illuminant_spectrum(rgb, self.lambda[2]),
+        )
+    }
+
+    // Weight that keeps only the hero wavelength, once a wavelength-dependent
+    // event (like dispersion) has split the companions off the path.
+    pub fn hero_only() -> Color {
+        Color::new_with(WAVELENGTH_COUNT as f64, 0.0, 0.0)
+    }
+}
+
+// Converts per-wavelength radiance to linear sRGB, white balanced so that a constant
+// spectrum maps to a neutral grey.
+pub struct SpectralFilm {
+    normalization: f64,
+    white_balance: Color,
+}
+
+impl SpectralFilm {
+    pub fn new() -> SpectralFilm {
+        let mut integral = Color::new();
+        let mut wavelength = WAVELENGTH_MIN;
+        while wavelength <= WAVELENGTH_MAX {
+            integral += cie_xyz(wavelength);
+            wavelength += 1.0;
+        }
+        let white = xyz_to_linear_srgb(&(&integral / integral.y()));
+        SpectralFilm {
+            normalization: integral.y(),
+            white_balance: Color::new_with(1.0 / white.x(), 1.0 / white.y(), 1.0 / white.z()),
+        }
+    }
+
+    pub fn to_rgb(&self, radiance: &Color, wavelengths: &SampledWavelengths) -> Color {
+        let pdf = 1.0 / (WAVELENGTH_MAX - WAVELENGTH_MIN);
+        let mut xyz = Color::new();
+        for (i, &wavelength) in wavelengths.lambda.iter().enumerate() {
+            xyz += radiance[i] * &cie_xyz(wavelength);
+        }
+        let xyz = &xyz / (WAVELENGTH_COUNT as f64 * pdf * self.normalization);
+        &xyz_to_linear_srgb(&xyz) * &self.white_balance
+    }
+}
+
+#[derive(Clone)]
+pub enum RefractiveIndex {
+    Constant(f64),
+    // n = a + b / lambda^2, with lambda in micrometres.
+    Cauchy { a: f64, b: f64 },
+    // n^2 = 1 + sum(b_i lambda^2 / (lambda^2 - c_i)), with lambda in micrometres.
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl RefractiveIndex {
+    // RGB paths have no wavelength and use the index at the sodium D line.
+    pub fn at(&self, wavelength_nm: Option<f64>) -> f64 {
+        let lambda = wavelength_nm.unwrap_or(SODIUM_D_LINE) * 1e-3;
+        match self {
+            RefractiveIndex::Constant(index) => *index,
+            RefractiveIndex::Cauchy { a, b } => a + b / lambda.powi(2),
+            RefractiveIndex::Sellmeier { b, c } => {
+                let lambda_squared = lambda.powi(2);
+                let sum: f64 = (0..3)
+                    .map(|i| b[i] * lambda_squared / (lambda_squared - c[i]))
+                    .sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, RefractiveIndex::Constant(_))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The film's colour for a spectrum upsampled from `rgb`, integrated over evenly
+    // spaced hero wavelengths.
+    fn round_trip(rgb: &Color, upsample: fn(&SampledWavelengths, &Color) -> Color) -> Color {
+        let film = SpectralFilm::new();
+        let steps = 4000;
+        let range = WAVELENGTH_MAX - WAVELENGTH_MIN;
+        let mut total = Color::new();
+        for i in 0..steps {
+            let hero = (i as f64 + 0.5) * range / (WAVELENGTH_COUNT * steps) as f64;
+            let lambda = [0, 1, 2].map(|k| WAVELENGTH_MIN + hero + k as f64 * range / 3.0);
+            let wavelengths = SampledWavelengths { lambda };
+            total += film.to_rgb(&upsample(&wavelengths, rgb), &wavelengths);
+        }
+        &total / steps as f64
+    }
+
+    #[test]
+    fn upsampled_colours_survive_the_film() {
+        let colours = [
+            Color::new_with(1.0, 1.0, 1.0),
+            Color::new_with(0.2, 0.5, 0.9),
+            Color::new_with(0.9, 0.1, 0.3),
+            Color::new_with(0.0, 1.0, 0.0),
+        ];
+        for rgb in colours.iter() {
+            // Emitters come back exactly, so a white light stays white.
+            let emitted = round_trip(rgb, SampledWavelengths::upsample_illuminant);
+            assert!(
+                (&emitted - rgb).len() < 1e-3,
+                "{} {}",
+                emitted.x(),
+                emitted.z()
+            );
+            // Reflectances stay within Smits' fit.
+            let reflected = round_trip(rgb, SampledWavelengths::upsample);
+            assert!(
+                (&reflected - rgb).len() < 0.02,
+                "{} {}",
+                reflected.x(),
+                reflected.z()
+            );
+        }
+    }
+
+    #[test]
+    fn reflectances_stay_below_one() {
+        let white = Color::new_with(1.0, 1.0, 1.0);
+        let mut wavelength = WAVELENGTH_MIN;
+        while wavelength < WAVELENGTH_MAX {
+            assert!(rgb_to_spectrum(&white, wavelength) <= 1.0);
+            wavelength += 1.0;
+        }
+    }
+}
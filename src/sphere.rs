@@ -1,12 +1,19 @@
 use crate::{
+    aabb::Aabb,
+    color::Color,
     hittable::{HitRecord, Hittable},
-    material::Material,
+    material::{EmitterSurface, Material, SurfaceSample},
+    onb::Onb,
     ray::Ray,
-    utils::Interval,
-    vec3::Point3,
+    utils::{self, Interval},
+    vec3::{Point3, Vec3},
 };
+use rand::Rng;
 use std::ops::Range;
 
+// Samples per axis of the grid spread over a sphere for emitters.
+const EMITTER_SAMPLES: usize = 16;
+
 pub struct Sphere {
     center_start: Point3,
     center_end: Point3,
@@ -23,19 +30,21 @@ impl Sphere {
         radius: f64,
         time0: f64,
         time1: f64,
-        material: Box<dyn Material>,
+        mut material: Box<dyn Material>,
     ) -> Sphere {
+        let radius = radius.max(0.0);
+        material.set_emitter_surface(&Sphere::emitter_surface(center_start, radius));
         Sphere {
             center_start: center_start.clone(),
             center_end: center_end.clone(),
-            radius: radius.max(0.0),
+            radius,
             start_time: time0,
             end_time: time1,
             material,
         }
     }
 
-    fn center(&mut self, current_time: f64) -> Point3 {
+    fn center(&self, current_time: f64) -> Point3 {
         if self.start_time < self.end_time {
             let t = (current_time - self.start_time) / (self.end_time - self.start_time);
             let c = &self.center_end - &self.center_start;
@@ -44,14 +53,70 @@ impl Sphere {
             self.center_start.clone()
         }
     }
+
+    // A grid even in height and longitude, which covers a sphere in equal areas.
+    fn emitter_surface(center: &Point3, radius: f64) -> EmitterSurface {
+        let mut samples = Vec::with_capacity(EMITTER_SAMPLES.pow(2));
+        for i in 0..EMITTER_SAMPLES {
+            for j in 0..EMITTER_SAMPLES {
+                let y = 1.0 - 2.0 * (i as f64 + 0.5) / EMITTER_SAMPLES as f64;
+                let phi = 2.0 * utils::PI * (j as f64 + 0.5) / EMITTER_SAMPLES as f64;
+                let r = (1.0 - y * y).sqrt();
+                let n = Vec3::new_with(r * phi.cos(), y, r * phi.sin());
+                let (u, v) = Sphere::uv(&n);
+                samples.push(SurfaceSample {
+                    u,
+                    v,
+                    p: center + &(radius * &n),
+                    weight: 1.0 / EMITTER_SAMPLES.pow(2) as f64,
+                });
+            }
+        }
+        EmitterSurface {
+            area: 4.0 * utils::PI * radius.powi(2),
+            samples,
+        }
+    }
+
+    // Longitude and latitude of a point on the unit sphere, both mapped to [0, 1].
+    fn uv(p: &Point3) -> (f64, f64) {
+        let theta = (-p.y()).clamp(-1.0, 1.0).acos();
+        let phi = (-p.z()).atan2(p.x()) + utils::PI;
+        (phi / (2.0 * utils::PI), theta / utils::PI)
+    }
+
+    // Partial derivatives of the surface position along `uv`, at unit normal `n`.
+    fn uv_derivatives(&self, n: &Vec3) -> (Vec3, Vec3) {
+        let dpdu = (2.0 * utils::PI * self.radius) * &Vec3::new_with(n.z(), 0.0, -n.x());
+        let sin_theta = (1.0 - n.y().powi(2)).max(0.0).sqrt();
+        if sin_theta < 1e-8 {
+            return (dpdu, Vec3::new_with(utils::PI * self.radius, 0.0, 0.0));
+        }
+        let dpdv = (utils::PI * self.radius)
+            * &Vec3::new_with(
+                -n.x() * n.y() / sin_theta,
+                sin_theta,
+                -n.y() * n.z() / sin_theta,
+            );
+        (dpdu, dpdv)
+    }
 }
 
 impl Hittable for Sphere {
-    fn hits(&mut self, ray: &Ray, ray_t: Range<f64>) -> Option<HitRecord> {
+    fn bounding_box(&self) -> Option<Aabb> {
+        let radius = Vec3::new_with(self.radius, self.radius, self.radius);
+        let at = |center: &Point3| Aabb::new(&(center - &radius), &(center + &radius));
+        Some(Aabb::surrounding(
+            &at(&self.center_start),
+            &at(&self.center_end),
+        ))
+    }
+
+    fn hits(&self, ray: &Ray, ray_t: Range<f64>) -> Option<HitRecord<'_>> {
         let oc = &self.center(ray.time()) - ray.origin();
         let a = &ray.direction().len_squared();
         let h = ray.direction().dot(&oc);
-        let c = &oc.len_squared() - self.radius.powi(2);
+        let c = oc.len_squared() - self.radius.powi(2);
         let discriminant = h.powi(2) - a * c;
 
         if discriminant < 0.0 {
@@ -59,19 +124,71 @@ impl Hittable for Sphere {
         }
 
         let sqrtd = discriminant.sqrt();
-        let mut root = (h - sqrtd) / a;
-        if !ray_t.surrounds(root) {
-            root = (h + sqrtd) / a;
+        for root in [(h - sqrtd) / a, (h + sqrtd) / a] {
             if !ray_t.surrounds(root) {
-                return None;
+                continue;
+            }
+
+            let t = root;
+            let p = ray.at(t);
+            let outward_normal = &(&p - &self.center(ray.time())) / self.radius;
+            let (u, v) = Sphere::uv(&outward_normal);
+            let (dpdu, dpdv) = self.uv_derivatives(&outward_normal);
+            let mut rec = HitRecord::new(p, outward_normal, t, self.material.as_ref());
+            rec.u = u;
+            rec.v = v;
+            rec.dpdu = dpdu;
+            rec.dpdv = dpdv;
+            rec.make_normal_face_ray(ray);
+            if self.material.alpha_test(&rec) {
+                return Some(rec);
             }
         }
+        None
+    }
+
+    fn area(&self) -> f64 {
+        4.0 * utils::PI * self.radius.powi(2)
+    }
+
+    fn average_emission(&self) -> Color {
+        self.material.average_emission()
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let ray = Ray::new(origin.clone(), direction.clone(), self.start_time);
+        if self.hits(&ray, 0.001..utils::INFINITY).is_none() {
+            return 0.0;
+        }
 
-        let t = root;
-        let p = ray.at(t);
-        let outward_normal = &(&p - &self.center(ray.time())) / self.radius;
-        let mut rec = HitRecord::new(p, outward_normal, t, self.material.as_ref());
-        rec.make_normal_face_ray(ray);
-        Some(rec)
+        let distance_squared = (&self.center(self.start_time) - origin).len_squared();
+        if distance_squared <= self.radius.powi(2) {
+            return 1.0 / (4.0 * utils::PI);
+        }
+        let cos_theta_max = (1.0 - self.radius.powi(2) / distance_squared).sqrt();
+        let solid_angle = 2.0 * utils::PI * (1.0 - cos_theta_max);
+        1.0 / solid_angle
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        let direction = &self.center(self.start_time) - origin;
+        let distance_squared = direction.len_squared();
+        if distance_squared <= self.radius.powi(2) {
+            return Vec3::random_unit_vector();
+        }
+        let uvw = Onb::new(&direction);
+        uvw.transform(&random_to_sphere(self.radius, distance_squared))
     }
 }
+
+fn random_to_sphere(radius: f64, distance_squared: f64) -> Vec3 {
+    let mut rng = rand::thread_rng();
+    let r1: f64 = rng.gen_range(0.0..1.0);
+    let r2: f64 = rng.gen_range(0.0..1.0);
+    let z = 1.0 + r2 * ((1.0 - radius.powi(2) / distance_squared).sqrt() - 1.0);
+
+    let phi = 2.0 * utils::PI * r1;
+    let x = phi.cos() * (1.0 - z.powi(2)).sqrt();
+    let y = phi.sin() * (1.0 - z.powi(2)).sqrt();
+    Vec3::new_with(x, y, z)
+}
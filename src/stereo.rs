@@ -0,0 +1,145 @@
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use crate::{
+    color::Color,
+    image::Image,
+    projection::{Eye, OffAxis},
+};
+
+pub enum StereoLayout {
+    // Left eye on the left half of a double-width image.
+    SideBySide,
+    // Left eye above the right in a double-height image.
+    TopBottom,
+    Separate { left: PathBuf, right: PathBuf },
+    // Red from the left eye and cyan from the right, for red-cyan glasses.
+    Anaglyph,
+}
+
+// A stereo pair rendered from one camera setup, written to standard output unless the
+// layout puts the eyes in their own files.
+pub struct Stereo {
+    interocular: f64,
+    convergence: f64,
+    layout: StereoLayout,
+}
+
+impl Stereo {
+    pub fn new(interocular: f64, convergence: f64) -> Stereo {
+        Stereo {
+            interocular,
+            convergence,
+            layout: StereoLayout::SideBySide,
+        }
+    }
+
+    pub fn with_layout(mut self, layout: StereoLayout) -> Stereo {
+        self.layout = layout;
+        self
+    }
+
+    pub fn eye(&self, eye: Eye) -> OffAxis {
+        OffAxis::new(eye, self.interocular, self.convergence)
+    }
+
+    pub fn write(&self, left: &Image, right: &Image) -> io::Result<()> {
+        let image = match &self.layout {
+            StereoLayout::SideBySide => side_by_side(left, right),
+            StereoLayout::TopBottom => top_bottom(left, right),
+            StereoLayout::Separate {
+                left: left_path,
+                right: right_path,
+            } => {
+                left.save(left_path)?;
+                return right.save(right_path);
+            }
+            StereoLayout::Anaglyph => anaglyph(left, right),
+        };
+        let mut out = io::stdout().lock();
+        image.write_ppm(&mut out)?;
+        out.flush()
+    }
+}
+
+fn side_by_side(left: &Image, right: &Image) -> Image {
+    let mut image = Image::new(2 * left.width(), left.height());
+    image.blit(left, 0, 0);
+    image.blit(right, left.width(), 0);
+    image
+}
+
+fn top_bottom(left: &Image, right: &Image) -> Image {
+    let mut image = Image::new(left.width(), 2 * left.height());
+    image.blit(left, 0, 0);
+    image.blit(right, 0, left.height());
+    image
+}
+
+fn anaglyph(left: &Image, right: &Image) -> Image {
+    let (width, height) = (left.width(), left.height());
+    let mut image = Image::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let (l, r) = (left.get(x, y), right.get(x, y));
+            image.set(x, y, Color::new_with(l.x(), r.y(), r.z()));
+        }
+    }
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 2x1 image whose pixels are told apart by `tag` and their column.
+    fn eye(tag: f64) -> Image {
+        let mut image = Image::new(2, 1);
+        image.set(0, 0, Color::new_with(tag, tag + 0.1, tag + 0.2));
+        image.set(1, 0, Color::new_with(tag + 0.5, tag + 0.6, tag + 0.7));
+        image
+    }
+
+    fn assert_same(actual: &Color, expected: &Color) {
+        assert!(
+            actual.x() == expected.x() && actual.y() == expected.y() && actual.z() == expected.z(),
+            "({}, {}, {}) != ({}, {}, {})",
+            actual.x(),
+            actual.y(),
+            actual.z(),
+            expected.x(),
+            expected.y(),
+            expected.z()
+        );
+    }
+
+    #[test]
+    fn layouts_place_the_right_eye_after_the_left() {
+        let (left, right) = (eye(0.0), eye(1.0));
+
+        let image = side_by_side(&left, &right);
+        assert_eq!((image.width(), image.height()), (4, 1));
+        for x in 0..2 {
+            assert_same(image.get(x, 0), left.get(x, 0));
+            assert_same(image.get(x + 2, 0), right.get(x, 0));
+        }
+
+        let image = top_bottom(&left, &right);
+        assert_eq!((image.width(), image.height()), (2, 2));
+        for x in 0..2 {
+            assert_same(image.get(x, 0), left.get(x, 0));
+            assert_same(image.get(x, 1), right.get(x, 0));
+        }
+    }
+
+    #[test]
+    fn anaglyphs_take_red_from_the_left_and_cyan_from_the_right() {
+        let (left, right) = (eye(0.0), eye(1.0));
+        let image = anaglyph(&left, &right);
+        assert_eq!((image.width(), image.height()), (2, 1));
+        assert_same(image.get(0, 0), &Color::new_with(0.0, 1.1, 1.2));
+        assert_same(image.get(1, 0), &Color::new_with(0.5, 1.6, 1.7));
+    }
+}
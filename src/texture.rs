@@ -0,0 +1,239 @@
+use std::{fs, io, path::Path, rc::Rc};
+
+use crate::{color::Color, perlin::Perlin, vec3::Point3};
+
+pub trait Texture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+}
+
+pub struct SolidColor {
+    albedo: Color,
+}
+
+impl SolidColor {
+    pub fn new(albedo: &Color) -> SolidColor {
+        SolidColor {
+            albedo: albedo.clone(),
+        }
+    }
+
+    pub fn scalar(value: f64) -> SolidColor {
+        SolidColor::new(&Color::new_with(value, value, value))
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        self.albedo.clone()
+    }
+}
+
+// One channel of another texture, scaled and broadcast to grey. Handy for packed
+// maps such as glTF's metallic-roughness texture.
+pub struct ChannelTexture {
+    source: Rc<dyn Texture>,
+    channel: usize,
+    scale: f64,
+}
+
+impl ChannelTexture {
+    pub fn new(source: Rc<dyn Texture>, channel: usize, scale: f64) -> ChannelTexture {
+        ChannelTexture {
+            source,
+            channel: channel.min(2),
+            scale,
+        }
+    }
+}
+
+impl Texture for ChannelTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let value = self.scale * self.source.value(u, v, p)[self.channel];
+        Color::new_with(value, value, value)
+    }
+}
+
+// Marble-like swirls of Perlin turbulence, in [0, 1].
+pub struct NoiseTexture {
+    noise: Perlin,
+    scale: f64,
+}
+
+impl NoiseTexture {
+    pub fn new(scale: f64) -> NoiseTexture {
+        NoiseTexture {
+            noise: Perlin::new(),
+            scale,
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        let value = 0.5 * (1.0 + (self.scale * p.z() + 10.0 * self.noise.turbulence(p, 7)).sin());
+        Color::new_with(value, value, value)
+    }
+}
+
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl ImageTexture {
+    // Loads a colour image from a binary or ASCII PPM, undoing the gamma 2 encoding
+    // that the renderer writes.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<ImageTexture> {
+        let mut image = ImageTexture::load_linear(path)?;
+        for pixel in image.pixels.iter_mut() {
+            *pixel = &pixel.clone() * &pixel.clone();
+        }
+        Ok(image)
+    }
+
+    // Loads a PPM whose values are data rather than colour, e.g. normal or height maps.
+    pub fn load_linear<P: AsRef<Path>>(path: P) -> io::Result<ImageTexture> {
+        let bytes = fs::read(path)?;
+        let mut header = PpmHeader::new(&bytes);
+        let magic = header.token()?;
+        let width = header.number()?;
+        let height = header.number()?;
+        let max_value = header.number()?.max(1) as f64;
+        let count = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(3))
+            .ok_or_else(|| invalid_data("image dimensions are too large"))?;
+
+        let samples: Vec<f64> = match magic.as_str() {
+            "P6" => {
+                let data = bytes
+                    .get(header.position + 1..)
+                    .ok_or_else(|| invalid_data("image data is truncated"))?;
+                if max_value < 256.0 {
+                    data.iter().take(count).map(|&b| b as f64).collect()
+                } else {
+                    data.chunks_exact(2)
+                        .take(count)
+                        .map(|word| u16::from_be_bytes([word[0], word[1]]) as f64)
+                        .collect()
+                }
+            }
+            "P3" => (0..count)
+                .map(|_| header.number().map(|value| value as f64))
+                .collect::<io::Result<_>>()?,
+            _ => return Err(invalid_data("only P3 and P6 images are supported")),
+        };
+        if samples.len() != count {
+            return Err(invalid_data("image data is truncated"));
+        }
+
+        let pixels = samples
+            .chunks_exact(3)
+            .map(|rgb| Color::new_with(rgb[0], rgb[1], rgb[2]))
+            .map(|rgb| &rgb / max_value)
+            .collect();
+        Ok(ImageTexture {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point3) -> Color {
+        if self.pixels.is_empty() {
+            return Color::new_with(0.0, 1.0, 1.0);
+        }
+        let u = u.clamp(0.0, 1.0);
+        let v = 1.0 - v.clamp(0.0, 1.0);
+        let i = ((u * self.width as f64) as usize).min(self.width - 1);
+        let j = ((v * self.height as f64) as usize).min(self.height - 1);
+        self.pixels[j * self.width + i].clone()
+    }
+}
+
+struct PpmHeader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> PpmHeader<'a> {
+    fn new(bytes: &'a [u8]) -> PpmHeader<'a> {
+        PpmHeader { bytes, position: 0 }
+    }
+
+    fn token(&mut self) -> io::Result<String> {
+        loop {
+            while self.position < self.bytes.len()
+                && self.bytes[self.position].is_ascii_whitespace()
+            {
+                self.position += 1;
+            }
+            if self.position < self.bytes.len() && self.bytes[self.position] == b'#' {
+                while self.position < self.bytes.len() && self.bytes[self.position] != b'\n' {
+                    self.position += 1;
+                }
+            } else {
+                break;
+            }
+        }
+        let start = self.position;
+        while self.position < self.bytes.len() && !self.bytes[self.position].is_ascii_whitespace() {
+            self.position += 1;
+        }
+        if start == self.position {
+            return Err(invalid_data("unexpected end of image header"));
+        }
+        Ok(String::from_utf8_lossy(&self.bytes[start..self.position]).into_owned())
+    }
+
+    fn number(&mut self) -> io::Result<usize> {
+        let token = self.token()?;
+        token
+            .parse::<f64>()
+            .map(|value| value.max(0.0) as usize)
+            .map_err(|_| invalid_data("malformed number in image"))
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(name: &str, bytes: &[u8]) -> io::Result<ImageTexture> {
+        let path = std::env::temp_dir().join(format!("{}-{}.ppm", name, std::process::id()));
+        fs::write(&path, bytes).unwrap();
+        let image = ImageTexture::load_linear(&path);
+        fs::remove_file(path).unwrap();
+        image
+    }
+
+    #[test]
+    fn loads_binary_ppm() {
+        let image = load("binary", b"P6 2 1 255\n\xff\x00\x00\x00\x00\xff").unwrap();
+        assert_eq!((image.width(), image.height()), (2, 1));
+        assert_eq!(image.pixels[0].x(), 1.0);
+        assert_eq!(image.pixels[1].z(), 1.0);
+    }
+
+    #[test]
+    fn rejects_malformed_ppms() {
+        assert!(load("headeronly", b"P6 2 1 255").is_err());
+        assert!(load("truncated", b"P6 2 1 255\n\xff\x00").is_err());
+        assert!(load("oversized", b"P6 99999999999 99999999999 255\n").is_err());
+    }
+}
@@ -0,0 +1,166 @@
+use std::rc::Rc;
+
+use crate::{
+    color::Color,
+    hittable::HitRecord,
+    microfacet,
+    spectrum::{self, WAVELENGTH_MAX, WAVELENGTH_MIN},
+    texture::Texture,
+};
+
+const WAVELENGTH_SAMPLES: usize = 32;
+
+// A thin transparent film on top of a surface, such as soap, oil or an anodised oxide
+// layer. The thickness texture gives the film thickness in nanometres.
+pub struct ThinFilm {
+    thickness: Rc<dyn Texture>,
+    refraction_index: f64,
+    // Wavelengths and their linear sRGB weights, normalised so that a flat spectrum
+    // integrates to white.
+    samples: Vec<(f64, Color)>,
+}
+
+impl ThinFilm {
+    pub fn new(thickness: Rc<dyn Texture>, refraction_index: f64) -> ThinFilm {
+        let step = (WAVELENGTH_MAX - WAVELENGTH_MIN) / WAVELENGTH_SAMPLES as f64;
+        let mut samples: Vec<(f64, Color)> = (0..WAVELENGTH_SAMPLES)
+            .map(|i| WAVELENGTH_MIN + (i as f64 + 0.5) * step)
+            .map(|wavelength| {
+                let rgb = spectrum::xyz_to_linear_srgb(&spectrum::cie_xyz(wavelength));
+                (wavelength, rgb)
+            })
+            .collect();
+
+        let mut white = Color::new();
+        for (_, rgb) in samples.iter() {
+            white += rgb.clone();
+        }
+        for (_, rgb) in samples.iter_mut() {
+            *rgb = Color::new_with(
+                rgb.x() / white.x(),
+                rgb.y() / white.y(),
+                rgb.z() / white.z(),
+            );
+        }
+
+        ThinFilm {
+            thickness,
+            refraction_index,
+            samples,
+        }
+    }
+
+    // Reflectance seen from a medium of index `eta_i` over a substrate whose complex
+    // index is given per RGB channel. Spectral paths get the reflectance at their hero
+    // wavelength, the same in every channel; RGB paths get it integrated against the
+    // colour matching functions.
+    pub fn reflectance(
+        &self,
+        rec: &HitRecord,
+        cos_theta_i: f64,
+        eta_i: f64,
+        eta_t: &Color,
+        k_t: &Color,
+    ) -> Color {
+        let thickness = self.thickness.value(rec.u, rec.v, &rec.p).x().max(0.0);
+        let at = |wavelength: f64| {
+            microfacet::fresnel_thin_film(
+                cos_theta_i,
+                eta_i,
+                self.refraction_index,
+                thickness,
+                channel_at(eta_t, wavelength),
+                channel_at(k_t, wavelength),
+                wavelength,
+            )
+        };
+        if let Some(wavelength) = rec.wavelength {
+            let r = at(wavelength).clamp(0.0, 1.0);
+            return Color::new_with(r, r, r);
+        }
+
+        let mut reflectance = Color::new();
+        for (wavelength, weight) in self.samples.iter() {
+            reflectance += at(*wavelength) * weight;
+        }
+        Color::new_with(
+            reflectance.x().clamp(0.0, 1.0),
+            reflectance.y().clamp(0.0, 1.0),
+            reflectance.z().clamp(0.0, 1.0),
+        )
+    }
+}
+
+// Treats RGB substrate constants as samples at 650, 550 and 450 nm.
+fn channel_at(rgb: &Color, wavelength_nm: f64) -> f64 {
+    if wavelength_nm >= 550.0 {
+        let t = ((wavelength_nm - 550.0) / 100.0).min(1.0);
+        (1.0 - t) * rgb.y() + t * rgb.x()
+    } else {
+        let t = ((wavelength_nm - 450.0) / 100.0).max(0.0);
+        (1.0 - t) * rgb.z() + t * rgb.y()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::LambertianMaterial,
+        texture::SolidColor,
+        vec3::{Point3, Vec3},
+    };
+
+    #[test]
+    fn spectral_paths_see_their_own_wavelength() {
+        let film = ThinFilm::new(Rc::new(SolidColor::scalar(300.0)), 1.33);
+        let material = LambertianMaterial::new(&Color::new());
+        let mut rec = HitRecord::new(Point3::new(), Vec3::new_with(0.0, 0.0, 1.0), 1.0, &material);
+        let eta_t = Color::new_with(1.5, 1.5, 1.5);
+        let k_t = Color::new();
+
+        let mut seen = Vec::new();
+        for wavelength in [450.0, 550.0, 650.0] {
+            rec.wavelength = Some(wavelength);
+            let reflectance = film.reflectance(&rec, 0.8, 1.0, &eta_t, &k_t);
+            let expected =
+                microfacet::fresnel_thin_film(0.8, 1.0, 1.33, 300.0, 1.5, 0.0, wavelength);
+            assert!((reflectance.x() - expected).abs() < 1e-12);
+            assert_eq!(reflectance.x(), reflectance.z());
+            seen.push(expected);
+        }
+        // Interference makes the film reflect some wavelengths more than others.
+        assert!((seen[0] - seen[2]).abs() > 0.01, "{seen:?}");
+    }
+
+    #[test]
+    fn vanishing_films_leave_the_bare_surface() {
+        for cos_theta in [1.0, 0.7, 0.3] {
+            let glass = microfacet::fresnel_thin_film(cos_theta, 1.0, 1.33, 0.0, 1.5, 0.0, 550.0);
+            let bare = microfacet::fresnel_dielectric(cos_theta, 1.5);
+            assert!((glass - bare).abs() < 1e-9, "{glass} {bare}");
+
+            let gold = microfacet::fresnel_thin_film(cos_theta, 1.0, 1.33, 0.0, 0.2, 3.0, 550.0);
+            let bare = microfacet::fresnel_complex(cos_theta, 0.2, 3.0);
+            assert!((gold - bare).abs() < 1e-9, "{gold} {bare}");
+        }
+
+        // A film matching the air around it is no film at all, however thick.
+        let matched = microfacet::fresnel_thin_film(0.8, 1.0, 1.0, 400.0, 1.5, 0.0, 500.0);
+        assert!((matched - microfacet::fresnel_dielectric(0.8, 1.5)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rgb_paths_see_interference_colours() {
+        let film = ThinFilm::new(Rc::new(SolidColor::scalar(400.0)), 1.33);
+        let material = LambertianMaterial::new(&Color::new());
+        let rec = HitRecord::new(Point3::new(), Vec3::new_with(0.0, 0.0, 1.0), 1.0, &material);
+        let eta_t = Color::new_with(1.5, 1.5, 1.5);
+        let reflectance = film.reflectance(&rec, 1.0, 1.0, &eta_t, &Color::new());
+        let channels = [reflectance.x(), reflectance.y(), reflectance.z()];
+        let spread = channels.iter().cloned().fold(0.0, f64::max)
+            - channels.iter().cloned().fold(1.0, f64::min);
+        assert!(spread > 0.01, "{channels:?}");
+        assert!(channels.iter().all(|c| (0.0..=1.0).contains(c)));
+    }
+}
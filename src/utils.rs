@@ -1,9 +1,11 @@
 use std::ops::Range;
 
 pub const PI: f64 = std::f64::consts::PI;
-pub const INFINITY: f64 = std::f64::INFINITY;
+pub const INFINITY: f64 = f64::INFINITY;
+#[allow(dead_code, reason = "the book's interval constants, kept for completeness")]
 pub const EMPTY_INTERVAL: Range<f64> = 0.0..0.0;
-pub const UNIVERSE_INTERVAL: Range<f64> = std::f64::NEG_INFINITY..std::f64::INFINITY;
+#[allow(dead_code, reason = "the book's interval constants, kept for completeness")]
+pub const UNIVERSE_INTERVAL: Range<f64> = f64::NEG_INFINITY..f64::INFINITY;
 
 pub fn degrees_to_radians(degrees: f64) -> f64 {
     degrees * PI / 180.0
@@ -47,10 +47,10 @@ impl Vec3 {
 
     pub fn random_on_hemisphere(normal: &Vec3) -> Vec3 {
         let on_unit_sphere = Vec3::random_unit_vector();
-        if on_unit_sphere.dot(&normal) > 0.0 {
+        if on_unit_sphere.dot(normal) > 0.0 {
             return on_unit_sphere;
         }
-        return -&on_unit_sphere;
+        -&on_unit_sphere
     }
 
     pub fn random_in_unit_disk() -> Vec3 {
@@ -63,6 +63,18 @@ impl Vec3 {
         }
     }
 
+    pub fn random_cosine_direction() -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let r1: f64 = rng.gen_range(0.0..1.0);
+        let r2: f64 = rng.gen_range(0.0..1.0);
+
+        let phi = 2.0 * std::f64::consts::PI * r1;
+        let x = phi.cos() * r2.sqrt();
+        let y = phi.sin() * r2.sqrt();
+        let z = (1.0 - r2).sqrt();
+        Vec3::new_with(x, y, z)
+    }
+
     pub fn x(&self) -> f64 {
         self.e[0]
     }
@@ -77,7 +89,7 @@ impl Vec3 {
 
     pub fn is_near_zero(&self) -> bool {
         let threshold = 1e-8;
-        self.e.iter().all(|elem| elem < &threshold)
+        self.e.iter().all(|elem| elem.abs() < threshold)
     }
 
     pub fn len(&self) -> f64 {
@@ -93,7 +105,7 @@ impl Vec3 {
     }
 
     pub fn refract(&self, normal: &Vec3, etai_over_etat: f64) -> Vec3 {
-        let cos_theta = (-self).dot(&normal).min(1.0);
+        let cos_theta = (-self).dot(normal).min(1.0);
         let r_out_perp = etai_over_etat * &(self + &(cos_theta * normal));
         let r_out_para = -&(1.0 - &r_out_perp.len_squared()).abs().sqrt() * normal;
         &r_out_para + &r_out_perp
@@ -226,3 +238,24 @@ impl std::ops::DivAssign<f64> for Vec3 {
         self.e[2] /= value;
     }
 }
+
+impl std::ops::Index<usize> for Vec3 {
+    type Output = f64;
+
+    fn index(&self, axis: usize) -> &f64 {
+        &self.e[axis]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn near_zero_compares_magnitudes() {
+        assert!(Vec3::new_with(1e-9, -1e-9, 0.0).is_near_zero());
+        // Large negative components are far from zero.
+        assert!(!Vec3::new_with(-1.0, 0.0, 0.0).is_near_zero());
+        assert!(!Vec3::new_with(0.0, 0.0, -5.0).is_near_zero());
+    }
+}
@@ -0,0 +1,361 @@
+use std::{fs, io, ops::Range, path::Path};
+
+use rand::Rng;
+
+use crate::{
+    aabb::Aabb,
+    blackbody,
+    color::Color,
+    hittable::{HitRecord, Hittable},
+    material::Material,
+    ray::{Ray, RayKind},
+    utils,
+    vec3::{Point3, Vec3},
+};
+
+const GRID_MAGIC: &[u8; 4] = b"VXG1";
+const GRID_HEADER_LEN: usize = 16;
+
+// Dense voxel grid with cell-centred samples, stored x-fastest.
+pub struct VoxelGrid {
+    nx: usize,
+    ny: usize,
+    nz: usize,
+    values: Vec<f32>,
+}
+
+impl VoxelGrid {
+    pub fn new(nx: usize, ny: usize, nz: usize, values: Vec<f32>) -> VoxelGrid {
+        assert_eq!(
+            values.len(),
+            nx * ny * nz,
+            "voxel count does not match grid size"
+        );
+        VoxelGrid { nx, ny, nz, values }
+    }
+
+    // File layout: "VXG1", then nx, ny, nz as little-endian u32, then nx * ny * nz
+    // little-endian f32 values.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<VoxelGrid> {
+        let bytes = fs::read(path)?;
+        if bytes.len() < GRID_HEADER_LEN || &bytes[0..4] != GRID_MAGIC {
+            return Err(invalid_data("not a voxel grid file"));
+        }
+
+        let read_u32 = |offset: usize| {
+            let mut word = [0; 4];
+            word.copy_from_slice(&bytes[offset..offset + 4]);
+            u32::from_le_bytes(word) as usize
+        };
+        let (nx, ny, nz) = (read_u32(4), read_u32(8), read_u32(12));
+
+        let payload = &bytes[GRID_HEADER_LEN..];
+        if nx == 0 || ny == 0 || nz == 0 || payload.len() != nx * ny * nz * 4 {
+            return Err(invalid_data("voxel grid size does not match its header"));
+        }
+
+        let values = payload
+            .chunks_exact(4)
+            .map(|word| f32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect();
+        Ok(VoxelGrid::new(nx, ny, nz, values))
+    }
+
+    pub fn max_value(&self) -> f64 {
+        self.values
+            .iter()
+            .fold(0.0_f32, |max, &value| max.max(value)) as f64
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f64 {
+        self.values[(z * self.ny + y) * self.nx + x] as f64
+    }
+
+    // Trilinear lookup at local coordinates in [0, 1]^3.
+    pub fn lookup(&self, local: &Point3) -> f64 {
+        let axis = |coordinate: f64, n: usize| {
+            let position = (coordinate * n as f64 - 0.5).clamp(0.0, (n - 1) as f64);
+            let i0 = (position.floor() as usize).min(n - 1);
+            let i1 = (i0 + 1).min(n - 1);
+            (i0, i1, position - i0 as f64)
+        };
+        let (x0, x1, fx) = axis(local.x(), self.nx);
+        let (y0, y1, fy) = axis(local.y(), self.ny);
+        let (z0, z1, fz) = axis(local.z(), self.nz);
+
+        let lerp = |a: f64, b: f64, t: f64| a + t * (b - a);
+        let c00 = lerp(self.voxel(x0, y0, z0), self.voxel(x1, y0, z0), fx);
+        let c10 = lerp(self.voxel(x0, y1, z0), self.voxel(x1, y1, z0), fx);
+        let c01 = lerp(self.voxel(x0, y0, z1), self.voxel(x1, y0, z1), fx);
+        let c11 = lerp(self.voxel(x0, y1, z1), self.voxel(x1, y1, z1), fx);
+        lerp(lerp(c00, c10, fy), lerp(c01, c11, fy), fz)
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn to_local(bounds: &Aabb, p: &Point3) -> Point3 {
+    let extent = bounds.max() - bounds.min();
+    let offset = p - bounds.min();
+    Point3::new_with(
+        offset.x() / extent.x(),
+        offset.y() / extent.y(),
+        offset.z() / extent.z(),
+    )
+}
+
+pub struct GridPhase {
+    albedo: Color,
+    bounds: Aabb,
+    temperature: Option<VoxelGrid>,
+    temperature_scale: f64,
+    emission_scale: f64,
+}
+
+impl Material for GridPhase {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let scattered = Ray::new(rec.p.clone(), Vec3::random_unit_vector(), ray_in.time());
+        Some((self.albedo.clone(), scattered))
+    }
+
+    fn eval(&self, _ray_in: &Ray, _rec: &HitRecord, _direction: &Vec3) -> Color {
+        (1.0 / (4.0 * utils::PI)) * &self.albedo
+    }
+
+    fn emit(&self, _ray_in: &Ray, rec: &HitRecord) -> Color {
+        match &self.temperature {
+            Some(temperature) => {
+                let kelvin =
+                    self.temperature_scale * temperature.lookup(&to_local(&self.bounds, &rec.p));
+                let absorption = &Color::new_with(1.0, 1.0, 1.0) - &self.albedo;
+                &(self.emission_scale * &absorption) * &blackbody::blackbody(kelvin)
+            }
+            None => Color::new(),
+        }
+    }
+}
+
+// Heterogeneous medium over a world-space box, sampled with delta tracking.
+pub struct GridMedium {
+    bounds: Aabb,
+    density: VoxelGrid,
+    density_scale: f64,
+    majorant: f64,
+    phase: GridPhase,
+}
+
+impl GridMedium {
+    pub fn new(density: VoxelGrid, bounds: Aabb, density_scale: f64, albedo: &Color) -> GridMedium {
+        let majorant = density.max_value() * density_scale;
+        GridMedium {
+            phase: GridPhase {
+                albedo: albedo.clone(),
+                bounds: bounds.clone(),
+                temperature: None,
+                temperature_scale: 0.0,
+                emission_scale: 0.0,
+            },
+            bounds,
+            density,
+            density_scale,
+            majorant,
+        }
+    }
+
+    pub fn with_temperature(
+        mut self,
+        temperature: VoxelGrid,
+        temperature_scale: f64,
+        emission_scale: f64,
+    ) -> GridMedium {
+        self.phase.temperature = Some(temperature);
+        self.phase.temperature_scale = temperature_scale;
+        self.phase.emission_scale = emission_scale;
+        self
+    }
+
+    fn density_at(&self, p: &Point3) -> f64 {
+        self.density_scale * self.density.lookup(&to_local(&self.bounds, p))
+    }
+}
+
+impl Hittable for GridMedium {
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds.clone())
+    }
+
+    fn hits(&self, ray: &Ray, ray_t: Range<f64>) -> Option<HitRecord<'_>> {
+        if ray.kind() == RayKind::Shadow {
+            return None;
+        }
+        let segment = self.bounds.hit(ray, ray_t)?;
+        if self.majorant <= 0.0 {
+            return None;
+        }
+
+        let mut rng = rand::thread_rng();
+        let step = self.majorant * ray.direction().len();
+        let mut t = segment.start;
+        loop {
+            t -= (1.0 - rng.gen_range(0.0..1.0_f64)).ln() / step;
+            if t >= segment.end {
+                return None;
+            }
+            let p = ray.at(t);
+            if rng.gen_range(0.0..self.majorant) < self.density_at(&p) {
+                let mut rec = HitRecord::new(p, Vec3::new_with(1.0, 0.0, 0.0), t, &self.phase);
+                rec.front_face = true;
+                return Some(rec);
+            }
+        }
+    }
+
+    // Ratio tracking, which unlike delta tracking gives a fractional estimate rather
+    // than a hit or a miss.
+    fn transmittance(&self, ray: &Ray, ray_t: Range<f64>) -> f64 {
+        let Some(segment) = self.bounds.hit(ray, ray_t) else {
+            return 1.0;
+        };
+        if self.majorant <= 0.0 {
+            return 1.0;
+        }
+
+        let mut rng = rand::thread_rng();
+        let step = self.majorant * ray.direction().len();
+        let mut transmittance = 1.0;
+        let mut t = segment.start;
+        loop {
+            t -= (1.0 - rng.gen_range(0.0..1.0_f64)).ln() / step;
+            if t >= segment.end {
+                return transmittance;
+            }
+            transmittance *= 1.0 - self.density_at(&ray.at(t)) / self.majorant;
+        }
+    }
+
+    // Sampled as a light by picking points uniformly inside the bounds, whose density
+    // along a direction grows with the square of the distance travelled through them.
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let ray = Ray::new(origin.clone(), direction.unit_vector(), 0.0);
+        let Some(segment) = self.bounds.hit(&ray, 0.0..utils::INFINITY) else {
+            return 0.0;
+        };
+        let extent = self.bounds.max() - self.bounds.min();
+        let volume = extent.x() * extent.y() * extent.z();
+        if volume <= 0.0 {
+            return 0.0;
+        }
+        (segment.end.powi(3) - segment.start.powi(3)) / (3.0 * volume)
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let (min, max) = (self.bounds.min(), self.bounds.max());
+        let point = Point3::new_with(
+            rng.gen_range(0.0..=1.0) * (max.x() - min.x()) + min.x(),
+            rng.gen_range(0.0..=1.0) * (max.y() - min.y()) + min.y(),
+            rng.gen_range(0.0..=1.0) * (max.z() - min.z()) + min.z(),
+        );
+        &point - origin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid_file(name: &str, header: (u32, u32, u32), values: &[f32]) -> std::path::PathBuf {
+        let mut bytes = GRID_MAGIC.to_vec();
+        for n in [header.0, header.1, header.2] {
+            bytes.extend_from_slice(&n.to_le_bytes());
+        }
+        for value in values {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        let path = std::env::temp_dir().join(format!("{}-{}.vxg", name, std::process::id()));
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn loads_grid_in_x_fastest_order() {
+        let values: Vec<f32> = (0..12).map(|i| i as f32).collect();
+        let path = grid_file("order", (3, 2, 2), &values);
+        let grid = VoxelGrid::load(&path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!((grid.nx, grid.ny, grid.nz), (3, 2, 2));
+        assert_eq!(grid.voxel(1, 0, 0), 1.0);
+        assert_eq!(grid.voxel(0, 1, 0), 3.0);
+        assert_eq!(grid.voxel(0, 0, 1), 6.0);
+        assert_eq!(grid.max_value(), 11.0);
+    }
+
+    #[test]
+    fn rejects_malformed_grids() {
+        let path = grid_file("short", (2, 2, 2), &[1.0; 7]);
+        assert!(VoxelGrid::load(&path).is_err());
+        fs::remove_file(&path).unwrap();
+
+        fs::write(&path, b"VXG2\0\0\0\0").unwrap();
+        assert!(VoxelGrid::load(&path).is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn lookup_interpolates_between_cell_centres() {
+        let grid = VoxelGrid::new(2, 2, 2, vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0]);
+        let at = |x, y, z| grid.lookup(&Point3::new_with(x, y, z));
+
+        assert_eq!(at(0.25, 0.25, 0.25), 0.0);
+        assert_eq!(at(0.75, 0.75, 0.75), 7.0);
+        assert!((at(0.5, 0.25, 0.25) - 0.5).abs() < 1e-12);
+        assert!((at(0.5, 0.5, 0.5) - 3.5).abs() < 1e-12);
+        // Outside the outermost centres the lookup holds the edge values.
+        assert_eq!(at(0.0, 0.0, 0.0), 0.0);
+        assert_eq!(at(1.0, 1.0, 1.0), 7.0);
+    }
+
+    #[test]
+    fn ratio_tracking_matches_beer_lambert() {
+        let density = VoxelGrid::new(1, 1, 1, vec![1.0]);
+        let bounds = Aabb::new(&Point3::new(), &Point3::new_with(1.0, 1.0, 1.0));
+        let medium = GridMedium::new(density, bounds, 2.0, &Color::new_with(0.5, 0.5, 0.5));
+        let shadow = Ray::new(
+            Point3::new_with(-1.0, 0.5, 0.5),
+            Vec3::new_with(1.0, 0.0, 0.0),
+            0.0,
+        )
+        .with_kind(RayKind::Shadow);
+
+        assert!(medium.hits(&shadow, 0.0..10.0).is_none());
+        let samples = 20000;
+        let mean = (0..samples)
+            .map(|_| medium.transmittance(&shadow, 0.0..10.0))
+            .sum::<f64>()
+            / samples as f64;
+        assert!((mean - (-2.0_f64).exp()).abs() < 0.01, "{mean}");
+    }
+
+    #[test]
+    fn light_pdf_integrates_to_one() {
+        let density = VoxelGrid::new(1, 1, 1, vec![1.0]);
+        let bounds = Aabb::new(
+            &Point3::new_with(-1.0, -0.5, -3.0),
+            &Point3::new_with(2.0, 0.5, 1.0),
+        );
+        let medium = GridMedium::new(density, bounds, 1.0, &Color::new_with(0.5, 0.5, 0.5));
+        let origin = Point3::new();
+
+        let samples = 200000;
+        let integral = (0..samples)
+            .map(|_| medium.pdf_value(&origin, &Vec3::random_unit_vector()))
+            .sum::<f64>()
+            * 4.0
+            * utils::PI
+            / samples as f64;
+        assert!((integral - 1.0).abs() < 0.02, "{integral}");
+    }
+}